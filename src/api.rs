@@ -0,0 +1,138 @@
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use tokio::{
+    io::BufReader,
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{business::BusinessLogic, daemon, http, protocol};
+
+/// Body of POST /command
+#[derive(Deserialize)]
+struct CommandBody {
+    /// Command line of the socket protocol, e.g. "toggle_active"
+    command: String,
+}
+
+/// Build a JSON response for a protocol reply
+fn reply(response: protocol::Response) -> (&'static str, String) {
+    match response {
+        protocol::Response::Success(msg) | protocol::Response::Data(msg) => (
+            "200 OK",
+            json!({ "ok": true, "output": msg.trim_end() }).to_string(),
+        ),
+        protocol::Response::Error(msg) => (
+            "400 Bad Request",
+            json!({ "ok": false, "error": msg }).to_string(),
+        ),
+    }
+}
+
+fn error(status: &'static str, msg: &str) -> (&'static str, String) {
+    (status, json!({ "ok": false, "error": msg }).to_string())
+}
+
+/// Hosts and origins the API answers to
+struct Access {
+    port: u16,
+    /// Extra origins allowed besides pages served from localhost, e.g. a browser extension
+    origins: Vec<String>,
+}
+
+impl Access {
+    /// Only local names for this port count, a rebound DNS name pointing at 127.0.0.1 doesn't
+    fn allows_host(&self, host: &str) -> bool {
+        ["127.0.0.1", "localhost", "[::1]"]
+            .iter()
+            .any(|name| host == format!("{name}:{}", self.port))
+    }
+
+    /// Browsers send Origin on cross-site and POST requests
+    fn allows_origin(&self, origin: &str) -> bool {
+        origin
+            .strip_prefix("http://")
+            .is_some_and(|host| self.allows_host(host))
+            || self.origins.iter().any(|allowed| allowed == origin)
+    }
+}
+
+async fn handle_request(
+    request: &http::HttpRequest,
+    access: &Access,
+    business_logic: &BusinessLogic,
+) -> (&'static str, String) {
+    if !request
+        .host
+        .as_deref()
+        .is_some_and(|host| access.allows_host(host))
+    {
+        return error("403 Forbidden", "Host not allowed");
+    }
+    if let Some(origin) = &request.origin
+        && !access.allows_origin(origin)
+    {
+        return error("403 Forbidden", "Origin not allowed");
+    }
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/state") => match serde_json::to_string(&business_logic.snapshot().await) {
+            Ok(body) => ("200 OK", body),
+            Err(e) => error("500 Internal Server Error", &e.to_string()),
+        },
+        ("POST", "/command") => {
+            // Requiring JSON forces a CORS preflight, the Host check above covers DNS rebinding
+            let is_json = request
+                .content_type
+                .as_deref()
+                .is_some_and(|t| t.starts_with("application/json"));
+            if !is_json {
+                return error("415 Unsupported Media Type", "Expected application/json");
+            }
+            let body: CommandBody = match serde_json::from_slice(&request.body) {
+                Ok(body) => body,
+                Err(e) => return error("400 Bad Request", &e.to_string()),
+            };
+            let request = match protocol::parse_request(&body.command) {
+                Ok(protocol::Request::Subscribe) => {
+                    return error("400 Bad Request", "Use GET /state to poll the state");
+                }
+                Ok(request) => request,
+                Err(e) => return error("400 Bad Request", &e.to_string()),
+            };
+            reply(daemon::process_request(request, "http", business_logic).await)
+        }
+        (_, "/state" | "/command") => error("405 Method Not Allowed", "Method not allowed"),
+        _ => error("404 Not Found", "Not found"),
+    }
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    access: Arc<Access>,
+    business_logic: BusinessLogic,
+) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let request = http::read_request(&mut BufReader::new(reader)).await?;
+    let (status, body) = handle_request(&request, &access, &business_logic).await;
+    http::write_response(&mut writer, status, "application/json", &body).await
+}
+
+/// Serve the JSON control API over HTTP on the given address
+/// `allowed_origins` may call it besides pages served from localhost
+pub async fn run_server(
+    addr: &str,
+    allowed_origins: Vec<String>,
+    business_logic: BusinessLogic,
+) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    let access = Arc::new(Access {
+        port: listener.local_addr()?.port(),
+        origins: allowed_origins,
+    });
+    tracing::info!("HTTP API listening on http://{}", addr);
+    http::serve(listener, move |stream| {
+        handle_connection(stream, access.clone(), business_logic.clone())
+    })
+    .await
+}
@@ -0,0 +1,81 @@
+use anyhow::Result;
+use serde_json::json;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static AUDIT_LOG: OnceLock<Mutex<File>> = OnceLock::new();
+
+/// Start appending audit records to `path`, creating it when missing
+pub fn open(path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let _ = AUDIT_LOG.set(Mutex::new(file));
+    Ok(())
+}
+
+/// Append one JSON line describing who did what and how it went, no-op unless open() was called
+/// `who` is the origin (e.g. "cli pid=1234 (waybar)", "rule 2"), `result` the outcome message
+pub fn record(who: &str, operation: &str, ok: bool, result: &str) {
+    let Some(file) = AUDIT_LOG.get() else {
+        return;
+    };
+    let line = json!({
+        "time": timestamp(SystemTime::now()),
+        "who": who,
+        "operation": operation,
+        "ok": ok,
+        "result": result.trim_end(),
+    });
+    let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+    if let Err(e) = writeln!(file, "{}", line) {
+        tracing::warn!("Failed to write audit log: {e}");
+    }
+}
+
+/// RFC 3339 UTC timestamp with millisecond precision
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs() as i64;
+    let (days, day_secs) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    let (year, month, day) = civil_date(days);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        day_secs / 3_600,
+        day_secs % 3_600 / 60,
+        day_secs % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// UTC day as YYYY-MM-DD
+pub fn date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let (year, month, day) = civil_date(secs.div_euclid(86_400));
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+fn civil_date(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
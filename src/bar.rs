@@ -0,0 +1,134 @@
+use anyhow::Result;
+use serde_json::json;
+use std::time::Duration;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+};
+
+use crate::business::StateSnapshot;
+
+/// Output formats for plain status bars
+#[derive(Debug, Clone, Copy)]
+pub enum StatusFormat {
+    /// One line of text per update (polybar `tail = true`, lemonbar, ...)
+    Text,
+    /// i3bar JSON protocol (i3bar, swaybar, i3status-rust custom blocks)
+    I3bar,
+}
+
+/// Short summary like "📌 2 ⬇ 1"
+fn status_text(snapshot: &StateSnapshot) -> String {
+    let mut text = format!("📌 {}", snapshot.sticky.len());
+    if !snapshot.staged.is_empty() {
+        text.push_str(&format!(" ⬇ {}", snapshot.staged.len()));
+    }
+    text
+}
+
+/// State of the focused window: sticky, staged or normal
+fn focused_state(snapshot: &StateSnapshot) -> &'static str {
+    match snapshot.focused {
+        Some(id) if snapshot.sticky.contains(&id) => "sticky",
+        Some(id) if snapshot.staged.contains(&id) => "staged",
+        _ => "normal",
+    }
+}
+
+/// Render a snapshot as a waybar custom module line
+fn waybar_line(snapshot: &StateSnapshot) -> String {
+    let text = status_text(snapshot);
+    let focused_state = focused_state(snapshot);
+    let tooltip = format!(
+        "Sticky windows: {}\nStaged windows: {}\nFocused window: {}",
+        snapshot.sticky.len(),
+        snapshot.staged.len(),
+        focused_state
+    );
+
+    json!({
+        "text": text,
+        "tooltip": tooltip,
+        "class": focused_state,
+        "alt": focused_state,
+    })
+    .to_string()
+}
+
+/// Print one line per state update until the daemon goes away
+async fn stream_snapshots(socket_path: &str, render: fn(&StateSnapshot) -> String) -> Result<()> {
+    let stream = UnixStream::connect(socket_path).await?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    writer.write_all(b"subscribe\n").await?;
+    writer.flush().await?;
+
+    let mut line = String::new();
+    while reader.read_line(&mut line).await? > 0 {
+        match serde_json::from_str::<StateSnapshot>(&line) {
+            Ok(snapshot) => println!("{}", render(&snapshot)),
+            Err(e) => eprintln!("Invalid state update: {e:?}"),
+        }
+        line.clear();
+    }
+
+    Ok(())
+}
+
+/// Render a snapshot as one i3bar status line (a JSON array of blocks)
+fn i3bar_line(snapshot: &StateSnapshot) -> String {
+    let block = json!({
+        "name": "nsticky",
+        "instance": focused_state(snapshot),
+        "full_text": status_text(snapshot),
+        "short_text": format!("📌{}", snapshot.sticky.len()),
+    });
+    format!("[{}],", block)
+}
+
+/// Run as a text or i3bar status source, reconnecting whenever the daemon restarts
+pub async fn run_status(socket_path: &str, format: StatusFormat) -> Result<()> {
+    let render: fn(&StateSnapshot) -> String = match format {
+        StatusFormat::Text => status_text,
+        StatusFormat::I3bar => {
+            // Header, then an endless array of status lines
+            println!("{}", json!({ "version": 1 }));
+            println!("[");
+            i3bar_line
+        }
+    };
+
+    loop {
+        if let Err(e) = stream_snapshots(socket_path, render).await {
+            eprintln!("Daemon connection lost: {e:?}");
+        }
+        match format {
+            StatusFormat::Text => println!("📌 -"),
+            StatusFormat::I3bar => println!(
+                "[{}],",
+                json!({ "name": "nsticky", "instance": "disconnected", "full_text": "📌 -" })
+            ),
+        }
+        tokio::time::sleep(Duration::from_secs(2)).await;
+    }
+}
+
+/// Run as a waybar custom module, reconnecting whenever the daemon restarts
+pub async fn run_waybar(socket_path: &str) -> Result<()> {
+    loop {
+        if let Err(e) = stream_snapshots(socket_path, waybar_line).await {
+            eprintln!("Daemon connection lost: {e:?}");
+        }
+        println!(
+            "{}",
+            json!({
+                "text": "",
+                "tooltip": "nsticky daemon not running",
+                "class": "disconnected",
+                "alt": "disconnected",
+            })
+        );
+        tokio::time::sleep(Duration::from_secs(2)).await;
+    }
+}
@@ -1,44 +1,644 @@
 use anyhow::Result;
-use std::collections::HashSet;
-use tokio::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use tokio::{
+    sync::{Mutex, broadcast, oneshot},
+    task::{AbortHandle, JoinSet},
+};
+
+use crate::config::{Config, FollowMode};
+use crate::metrics::{LatencySummary, METRICS, RecentError};
+use crate::rules::{Action, Outcome, Rule, RuleSet, RuleSource};
+use crate::supervisor::TaskHealth;
+use crate::system_integration::{Backend, WindowInfo, WindowSelector, WorkspaceInfo};
+
+/// Sticky-follow pause state, either everywhere or for single outputs
+#[derive(Debug, Clone, Default)]
+pub struct PauseState {
+    pub all: bool,
+    pub outputs: HashSet<String>,
+    /// Paused because the session is locked, independent of user pauses
+    pub locked: bool,
+}
+
+/// A tracked window together with where it currently lives
+#[derive(Debug, Clone)]
+pub struct TrackedWindow {
+    pub window: WindowInfo,
+    pub staged: bool,
+    pub workspace: Option<WorkspaceInfo>,
+    /// Time left until a window added with `--for` is removed again
+    pub expires_in: Option<Duration>,
+    pub label: Option<String>,
+}
+
+/// How `set` reconciles the sticky list with the windows it was given
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetMode {
+    /// Sticky list becomes exactly the given windows
+    Replace,
+    Add,
+    Remove,
+}
+
+/// Where `remove --home` left a window
+#[derive(Debug, Clone)]
+pub enum HomeReturn {
+    /// Moved back to the workspace it was on when it became sticky
+    Moved(WorkspaceInfo),
+    /// That workspace is gone, e.g. niri removed it once it was empty
+    Gone,
+    /// No home was recorded, e.g. the window became sticky before the daemon started
+    Unknown,
+}
+
+/// Snapshot of daemon state pushed to subscribers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    pub sticky: Vec<u64>,
+    pub staged: Vec<u64>,
+    pub focused: Option<u64>,
+}
+
+/// Daemon state, counters and latencies shown by `nsticky status`
+#[derive(Debug, Clone)]
+pub struct DaemonStatus {
+    pub backend: Backend,
+    pub follow_mode: FollowMode,
+    pub primary_output: Option<String>,
+    pub paused: PauseState,
+    pub sticky: usize,
+    pub staged: usize,
+    pub event_stream_connected: bool,
+    /// State-changing requests held until the compositor is reachable again
+    pub queued_requests: usize,
+    pub moves: u64,
+    pub move_failures: u64,
+    pub query_failures: u64,
+    pub event_parse_errors: u64,
+    pub recent_errors: Vec<RecentError>,
+    pub events: u64,
+    pub trace_ipc: bool,
+    pub focus_mode: bool,
+    /// (follower, leader) pairs, sorted by follower
+    pub attachments: Vec<(u64, u64)>,
+    /// Windows marked with `protect`, sorted
+    pub protected: Vec<u64>,
+    pub tasks: Vec<TaskHealth>,
+    pub latencies: Vec<LatencySummary>,
+}
+
+/// Windows whose sticky/staged state changed between two snapshots
+#[derive(Debug, Default)]
+pub struct StateChanges {
+    pub stuck: Vec<u64>,
+    pub unstuck: Vec<u64>,
+    pub staged: Vec<u64>,
+    pub unstaged: Vec<u64>,
+}
+
+impl StateChanges {
+    /// Compare two snapshots, `is_open` tells closed windows apart from unstuck ones
+    pub fn between(
+        old: &StateSnapshot,
+        new: &StateSnapshot,
+        is_open: impl Fn(u64) -> bool,
+    ) -> Self {
+        let diff = |a: &[u64], b: &[u64], keep: &dyn Fn(u64) -> bool| -> Vec<u64> {
+            a.iter()
+                .copied()
+                .filter(|id| !b.contains(id) && keep(*id))
+                .collect()
+        };
+        // Closed windows silently drop out of the sets, they are not a state change
+        Self {
+            staged: diff(&new.staged, &old.staged, &|_| true),
+            unstaged: diff(&old.staged, &new.staged, &|id| new.sticky.contains(&id)),
+            stuck: diff(&new.sticky, &old.sticky, &|id| !old.staged.contains(&id)),
+            unstuck: diff(&old.sticky, &new.sticky, &|id| {
+                !new.staged.contains(&id) && is_open(id)
+            }),
+        }
+    }
+
+    /// What changed, e.g. "staged 42, 57"
+    fn describe(&self) -> String {
+        let list = |ids: &[u64]| {
+            ids.iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        [
+            ("stuck", &self.stuck),
+            ("unstuck", &self.unstuck),
+            ("staged", &self.staged),
+            ("unstaged", &self.unstaged),
+        ]
+        .iter()
+        .filter(|(_, ids)| !ids.is_empty())
+        .map(|(verb, ids)| format!("{} {}", verb, list(ids)))
+        .collect::<Vec<_>>()
+        .join(", ")
+    }
+}
+
+/// Operations `undo` can revert
+const UNDO_DEPTH: usize = 16;
+
+/// State captured before an operation
+pub struct UndoCheckpoint {
+    before: StateSnapshot,
+    /// Workspace of each tracked window, only captured for operations that move windows
+    workspaces: HashMap<u64, u64>,
+}
+
+/// A completed operation on the undo stack
+struct UndoEntry {
+    /// What the operation changed, e.g. "staged 42, 57"
+    description: String,
+    checkpoint: UndoCheckpoint,
+}
+
+/// A `wait-for` request waiting for a window to open
+struct WindowWaiter {
+    selector: WindowSelector,
+    /// Windows already open when the request came in, they never match
+    existing: HashSet<u64>,
+    found: oneshot::Sender<WindowInfo>,
+}
 
 #[derive(Clone)]
 pub struct BusinessLogic {
     sticky_windows: std::sync::Arc<Mutex<HashSet<u64>>>,
     staged_set: std::sync::Arc<Mutex<HashSet<u64>>>,
+    /// Output each sticky window belongs to, sticky windows only follow workspaces on it
+    output_affinity: std::sync::Arc<Mutex<HashMap<u64, String>>>,
+    /// Original output of windows displaced by an output disconnect
+    home_outputs: std::sync::Arc<Mutex<HashMap<u64, String>>>,
+    /// Workspace each tracked window was on when it became sticky, for `remove --home`
+    home_workspaces: std::sync::Arc<Mutex<HashMap<u64, u64>>>,
+    follow_mode: std::sync::Arc<Mutex<FollowMode>>,
+    primary_output: std::sync::Arc<Mutex<Option<String>>>,
+    paused: std::sync::Arc<Mutex<PauseState>>,
+    /// Focused window as reported by the event stream
+    focused_window: std::sync::Arc<Mutex<Option<u64>>>,
+    /// Windows staged because the session went idle, Some while idle
+    idle_staged: std::sync::Arc<Mutex<Option<Vec<u64>>>>,
+    /// Follower window -> leader window it is moved along with
+    attachments: std::sync::Arc<Mutex<HashMap<u64, u64>>>,
+    /// Windows marked with `protect`, sticky-follow leaves them where they are
+    protected: std::sync::Arc<Mutex<HashSet<u64>>>,
+    /// Label -> window, the CLI accepts labels in place of window IDs
+    labels: std::sync::Arc<Mutex<BTreeMap<String, u64>>>,
+    /// Slot number -> window, for `focus-slot`
+    slots: std::sync::Arc<Mutex<BTreeMap<u32, u64>>>,
+    /// Named groups of sticky windows that follow together, in placement order
+    groups: std::sync::Arc<Mutex<BTreeMap<String, Vec<u64>>>>,
+    /// Windows staged by focus mode, Some while focus mode is on
+    focus_mode: std::sync::Arc<Mutex<Option<Vec<u64>>>>,
+    /// Rules applied to newly opened windows
+    rules: std::sync::Arc<Mutex<RuleSet>>,
+    /// Most recent operation last
+    undo_stack: std::sync::Arc<Mutex<VecDeque<UndoEntry>>>,
+    /// Deadline and timer task of windows added with `--for`
+    expiries: std::sync::Arc<Mutex<HashMap<u64, (Instant, AbortHandle)>>>,
+    window_waiters: std::sync::Arc<Mutex<Vec<WindowWaiter>>>,
+    state_changed: broadcast::Sender<()>,
+    config: std::sync::Arc<Config>,
 }
 
 impl BusinessLogic {
     pub fn new(
         sticky_windows: std::sync::Arc<Mutex<HashSet<u64>>>,
         staged_set: std::sync::Arc<Mutex<HashSet<u64>>>,
+        rules: RuleSet,
+        config: std::sync::Arc<Config>,
     ) -> Self {
         Self {
             sticky_windows,
             staged_set,
+            output_affinity: std::sync::Arc::new(Mutex::new(HashMap::new())),
+            home_outputs: std::sync::Arc::new(Mutex::new(HashMap::new())),
+            home_workspaces: std::sync::Arc::new(Mutex::new(HashMap::new())),
+            follow_mode: std::sync::Arc::new(Mutex::new(config.follow_mode)),
+            primary_output: std::sync::Arc::new(Mutex::new(config.primary_output.clone())),
+            paused: std::sync::Arc::new(Mutex::new(PauseState::default())),
+            focused_window: std::sync::Arc::new(Mutex::new(None)),
+            idle_staged: std::sync::Arc::new(Mutex::new(None)),
+            focus_mode: std::sync::Arc::new(Mutex::new(None)),
+            attachments: std::sync::Arc::new(Mutex::new(HashMap::new())),
+            groups: std::sync::Arc::new(Mutex::new(BTreeMap::new())),
+            slots: std::sync::Arc::new(Mutex::new(BTreeMap::new())),
+            labels: std::sync::Arc::new(Mutex::new(BTreeMap::new())),
+            protected: std::sync::Arc::new(Mutex::new(HashSet::new())),
+            rules: std::sync::Arc::new(Mutex::new(rules)),
+            undo_stack: std::sync::Arc::new(Mutex::new(VecDeque::new())),
+            expiries: std::sync::Arc::new(Mutex::new(HashMap::new())),
+            window_waiters: std::sync::Arc::new(Mutex::new(Vec::new())),
+            state_changed: broadcast::channel(16).0,
+            config,
+        }
+    }
+
+    /// Wake up subscribers after state may have changed
+    pub fn notify_state_changed(&self) {
+        // No receivers is fine, nobody is subscribed
+        let _ = self.state_changed.send(());
+    }
+
+    /// Subscribe to state change notifications
+    pub fn subscribe(&self) -> broadcast::Receiver<()> {
+        self.state_changed.subscribe()
+    }
+
+    /// Take a snapshot of sticky, staged and focused windows without querying Niri
+    pub async fn snapshot(&self) -> StateSnapshot {
+        let mut sticky: Vec<u64> = self.sticky_windows.lock().await.iter().copied().collect();
+        let mut staged: Vec<u64> = self.staged_set.lock().await.iter().copied().collect();
+        sticky.sort_unstable();
+        staged.sort_unstable();
+        StateSnapshot {
+            sticky,
+            staged,
+            focused: *self.focused_window.lock().await,
+        }
+    }
+
+    /// Record the focused window reported by Niri
+    pub async fn set_focused_window(&self, window_id: Option<u64>) {
+        let mut focused = self.focused_window.lock().await;
+        if *focused != window_id {
+            *focused = window_id;
+            drop(focused);
+            self.notify_state_changed();
+        }
+    }
+
+    /// Remember the output a sticky window currently lives on, and its workspace as home unless it has one
+    async fn track_output(&self, window_id: u64) {
+        let workspace_id = match crate::system_integration::get_full_window_info().await {
+            Ok(windows) => windows
+                .into_iter()
+                .find(|w| w.id == window_id)
+                .and_then(|w| w.workspace_id),
+            Err(e) => {
+                tracing::warn!(
+                    operation = "track_output",
+                    window_id,
+                    "Failed to resolve workspace of window {}: {:?}",
+                    window_id,
+                    e
+                );
+                return;
+            }
+        };
+        let Some(workspace_id) = workspace_id else {
+            return;
+        };
+        self.home_workspaces
+            .lock()
+            .await
+            .entry(window_id)
+            .or_insert(workspace_id);
+        match crate::system_integration::get_workspace_output(workspace_id).await {
+            Ok(Some(output)) => {
+                let mut affinity = self.output_affinity.lock().await;
+                affinity.insert(window_id, output);
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!(
+                operation = "track_output",
+                window_id,
+                "Failed to resolve output of window {}: {:?}",
+                window_id,
+                e
+            ),
+        }
+    }
+
+    /// Forget the output of a window that is no longer tracked
+    async fn forget_output(&self, window_id: u64) {
+        let mut affinity = self.output_affinity.lock().await;
+        let mut home = self.home_outputs.lock().await;
+        affinity.remove(&window_id);
+        home.remove(&window_id);
+        self.home_workspaces.lock().await.remove(&window_id);
+    }
+
+    /// Run compositor moves for many windows, paced by the bulk config
+    /// Returns the IDs whose move succeeded
+    async fn run_bulk_moves<F, Fut>(&self, ids: Vec<u64>, mover: F) -> Vec<u64>
+    where
+        F: Fn(u64) -> Fut,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        if ids.is_empty() {
+            return Vec::new();
+        }
+        self.keep_focus(self.run_bulk_moves_inner(ids, mover)).await
+    }
+
+    async fn run_bulk_moves_inner<F, Fut>(&self, ids: Vec<u64>, mover: F) -> Vec<u64>
+    where
+        F: Fn(u64) -> Fut,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let concurrency = self.config.bulk.max_concurrency.max(1);
+        let delay = Duration::from_millis(self.config.bulk.delay_ms);
+
+        let started = Instant::now();
+        let mut succeeded = Vec::new();
+        for (i, batch) in ids.chunks(concurrency).enumerate() {
+            if i > 0 && !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+
+            let mut moves = JoinSet::new();
+            for &id in batch {
+                let fut = mover(id);
+                moves.spawn(async move { (id, fut.await) });
+            }
+            while let Some(joined) = moves.join_next().await {
+                match joined {
+                    Ok((id, Ok(()))) => succeeded.push(id),
+                    Ok((id, Err(e))) => tracing::error!(
+                        operation = "bulk_move",
+                        window_id = id,
+                        "Failed to move window {}: {:?}",
+                        id,
+                        e
+                    ),
+                    Err(e) => {
+                        tracing::error!(operation = "bulk_move", "Bulk move task failed: {:?}", e)
+                    }
+                }
+            }
+        }
+        crate::metrics::record_latency(crate::metrics::Operation::BulkMove, started.elapsed());
+        succeeded
+    }
+
+    /// Run bulk moves as one transaction, moving windows back where they were if any move fails
+    /// Returns the IDs that ended up moved with the batch result: all of them on success, only
+    /// the ones that couldn't be moved back on failure
+    async fn run_bulk_transaction<F, Fut>(
+        &self,
+        windows: &[WindowInfo],
+        mover: F,
+    ) -> (Vec<u64>, Result<()>)
+    where
+        F: Fn(u64) -> Fut,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let ids: Vec<u64> = windows.iter().map(|w| w.id).collect();
+        let moved = self.run_bulk_moves(ids.clone(), mover).await;
+        if moved.len() == ids.len() {
+            return (moved, Ok(()));
+        }
+
+        let failed: Vec<String> = ids
+            .iter()
+            .filter(|id| !moved.contains(id))
+            .map(|id| id.to_string())
+            .collect();
+        let origins: HashMap<u64, u64> = windows
+            .iter()
+            .filter_map(|w| Some((w.id, w.workspace_id?)))
+            .collect();
+        let returnable: Vec<u64> = moved
+            .iter()
+            .copied()
+            .filter(|id| origins.contains_key(id))
+            .collect();
+        let returned = self
+            .run_bulk_moves(returnable, |id| {
+                crate::system_integration::move_to_workspace(id, origins[&id])
+            })
+            .await;
+        let stuck: Vec<u64> = moved
+            .into_iter()
+            .filter(|id| !returned.contains(id))
+            .collect();
+
+        let error = if stuck.is_empty() {
+            anyhow::anyhow!(
+                "Failed to move windows {}, nothing was changed",
+                failed.join(", ")
+            )
+        } else {
+            let stuck_list: Vec<String> = stuck.iter().map(|id| id.to_string()).collect();
+            tracing::error!(
+                operation = "bulk_move",
+                "Failed to move windows {} back after a failed bulk move",
+                stuck_list.join(", ")
+            );
+            anyhow::anyhow!(
+                "Failed to move windows {}, windows {} could not be moved back",
+                failed.join(", "),
+                stuck_list.join(", ")
+            )
+        };
+        (stuck, Err(error))
+    }
+
+    /// Run an operation moving several windows and restore keyboard focus if it changed
+    /// A focused window that the operation staged is expected to lose focus and left alone
+    async fn keep_focus<T>(&self, operation: impl Future<Output = T>) -> T {
+        if !self.config.bulk.restore_focus {
+            return operation.await;
+        }
+        let before = crate::system_integration::get_active_window_id().await.ok();
+        let result = operation.await;
+        let Some(focused) = before else {
+            return result;
+        };
+        if self.staged_set.lock().await.contains(&focused) {
+            return result;
+        }
+        let after = crate::system_integration::get_active_window_id().await.ok();
+        if after == Some(focused) {
+            return result;
+        }
+        let still_open = crate::system_integration::get_full_window_list()
+            .await
+            .is_ok_and(|windows| windows.contains(&focused));
+        if still_open {
+            tracing::info!(
+                operation = "restore_focus",
+                window_id = focused,
+                "Focus moved to {:?} during a bulk move, restoring window {}",
+                after,
+                focused
+            );
+            if let Err(e) = crate::system_integration::focus_window(focused).await {
+                tracing::warn!(
+                    operation = "restore_focus",
+                    window_id = focused,
+                    "Failed to restore focus: {:?}",
+                    e
+                );
+            }
         }
+        result
     }
 
-    /// Add window to sticky list
-    pub async fn add_sticky_window(&self, window_id: u64) -> Result<bool> {
+    /// Add window to sticky list, removing it again after `expires_in`
+    /// Adding an already sticky window replaces its timer, without `expires_in` it stays for good
+    pub async fn add_sticky_window(
+        &self,
+        window_id: u64,
+        expires_in: Option<Duration>,
+    ) -> Result<bool> {
         let full_window_list = crate::system_integration::get_full_window_list().await?;
         if !full_window_list.contains(&window_id) {
             return Err(anyhow::anyhow!("Window not found in Niri"));
         }
 
-        let mut sticky = self.sticky_windows.lock().await;
-        Ok(sticky.insert(window_id))
+        let is_new = self.sticky_windows.lock().await.insert(window_id);
+        self.track_output(window_id).await;
+        self.set_expiry(window_id, expires_in).await;
+        Ok(is_new)
+    }
+
+    /// Schedule removal of a sticky window, cancelling any earlier schedule
+    async fn set_expiry(&self, window_id: u64, expires_in: Option<Duration>) {
+        let mut expiries = self.expiries.lock().await;
+        if let Some((_, timer)) = expiries.remove(&window_id) {
+            timer.abort();
+        }
+        let Some(expires_in) = expires_in else {
+            return;
+        };
+
+        let business_logic = self.clone();
+        let timer = tokio::spawn(async move {
+            tokio::time::sleep(expires_in).await;
+            business_logic.expire_window(window_id, expires_in).await;
+        });
+        expiries.insert(
+            window_id,
+            (Instant::now() + expires_in, timer.abort_handle()),
+        );
+    }
+
+    /// Stop tracking a window whose `--for` time ran out
+    /// A staged window is first brought back to the active workspace, unstage can't reach it afterwards
+    async fn expire_window(&self, window_id: u64, expires_in: Duration) {
+        self.expiries.lock().await.remove(&window_id);
+        if self.staged_set.lock().await.contains(&window_id) {
+            let moved = match crate::system_integration::get_active_workspace_id().await {
+                Ok(ws_id) => crate::system_integration::move_to_workspace(window_id, ws_id).await,
+                Err(e) => Err(e),
+            };
+            // Left staged, so it can still be unstaged by hand
+            if let Err(e) = moved {
+                tracing::warn!(
+                    operation = "expire",
+                    window_id,
+                    "Failed to bring back staged window {}, keeping it staged: {:?}",
+                    window_id,
+                    e
+                );
+                return;
+            }
+        }
+        let was_sticky = self.sticky_windows.lock().await.remove(&window_id);
+        let was_staged = self.staged_set.lock().await.remove(&window_id);
+        if !was_sticky && !was_staged {
+            return;
+        }
+        self.forget_output(window_id).await;
+        self.forget_handles(window_id).await;
+
+        let result = format!(
+            "Removed after {}",
+            crate::protocol::format_duration(expires_in)
+        );
+        tracing::info!(
+            operation = "expire",
+            window_id,
+            "Window {}: {}",
+            window_id,
+            result
+        );
+        crate::audit::record("timer", &format!("remove {}", window_id), true, &result);
+        self.notify_state_changed();
+    }
+
+    /// Cancel timers of windows that are neither sticky nor staged anymore
+    pub async fn prune_expiries(&self) {
+        let sticky = self.sticky_windows.lock().await;
+        let staged = self.staged_set.lock().await;
+        self.expiries.lock().await.retain(|id, (_, timer)| {
+            let tracked = sticky.contains(id) || staged.contains(id);
+            if !tracked {
+                timer.abort();
+            }
+            tracked
+        });
+    }
+
+    /// Move any window to the active workspace and make it sticky, staged windows are unstaged
+    /// Returns false if the window was already sticky
+    pub async fn bring_window(&self, selector: &WindowSelector) -> Result<bool> {
+        let window_id = crate::system_integration::find_window(selector)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Window not found in Niri"))?;
+        let ws_id = crate::system_integration::get_active_workspace_id().await?;
+
+        if self.staged_set.lock().await.contains(&window_id) {
+            self.unstage_window(window_id, ws_id).await?;
+            return Ok(true);
+        }
+        // Record where it came from before moving it here
+        let was_sticky = self.sticky_windows.lock().await.contains(&window_id);
+        self.track_output(window_id).await;
+        if let Err(e) = crate::system_integration::move_to_workspace(window_id, ws_id).await {
+            if !was_sticky {
+                self.forget_output(window_id).await;
+            }
+            return Err(e);
+        }
+        let is_new = self.sticky_windows.lock().await.insert(window_id);
+        self.track_output(window_id).await;
+        Ok(is_new)
     }
 
-    /// Remove window from sticky list
-    pub async fn remove_sticky_window(&self, window_id: u64) -> Result<bool> {
+    /// Remove window from sticky list, with `return_home` (remove_returns_home when None) also
+    /// move it back to its home workspace
+    /// Returns false if it wasn't sticky, and where it went when it was sent home
+    pub async fn remove_sticky_window(
+        &self,
+        window_id: u64,
+        return_home: Option<bool>,
+    ) -> Result<(bool, Option<HomeReturn>)> {
+        let return_home = return_home.unwrap_or(self.config.remove_returns_home);
         let full_window_list = crate::system_integration::get_full_window_list().await?;
         if !full_window_list.contains(&window_id) {
             return Err(anyhow::anyhow!("Window not found in Niri"));
         }
 
-        let mut sticky = self.sticky_windows.lock().await;
-        Ok(sticky.remove(&window_id))
+        let home = self.home_workspaces.lock().await.get(&window_id).copied();
+        let was_present = self.sticky_windows.lock().await.remove(&window_id);
+        self.forget_output(window_id).await;
+        self.forget_if_untracked(window_id).await;
+        if !was_present || !return_home {
+            return Ok((was_present, None));
+        }
+
+        let Some(home) = home else {
+            return Ok((true, Some(HomeReturn::Unknown)));
+        };
+        let workspaces = crate::system_integration::get_workspaces().await?;
+        let Some(workspace) = workspaces.into_iter().find(|ws| ws.id == home) else {
+            return Ok((true, Some(HomeReturn::Gone)));
+        };
+        crate::system_integration::move_to_workspace(window_id, home).await?;
+        Ok((true, Some(HomeReturn::Moved(workspace))))
     }
 
     /// List all sticky windows
@@ -55,6 +655,50 @@ impl BusinessLogic {
         Ok(valid_snapshot)
     }
 
+    /// List sticky and/or staged windows with their workspace and output
+    pub async fn list_tracked_windows(
+        &self,
+        include_sticky: bool,
+        include_staged: bool,
+    ) -> Result<Vec<TrackedWindow>> {
+        let sticky = self.sticky_windows.lock().await.clone();
+        let staged = self.staged_set.lock().await.clone();
+        let now = Instant::now();
+        let expires_in: HashMap<u64, Duration> = self
+            .expiries
+            .lock()
+            .await
+            .iter()
+            .map(|(id, (deadline, _))| (*id, deadline.saturating_duration_since(now)))
+            .collect();
+
+        let labels = self.window_labels().await;
+
+        let windows = crate::system_integration::get_full_window_info().await?;
+        let workspaces = crate::system_integration::get_workspaces().await?;
+
+        let mut tracked = Vec::new();
+        for window in windows {
+            let is_staged = staged.contains(&window.id);
+            let wanted =
+                (include_sticky && sticky.contains(&window.id)) || (include_staged && is_staged);
+            if !wanted {
+                continue;
+            }
+            let workspace = window
+                .workspace_id
+                .and_then(|ws_id| workspaces.iter().find(|ws| ws.id == ws_id).cloned());
+            tracked.push(TrackedWindow {
+                expires_in: expires_in.get(&window.id).copied(),
+                label: labels.get(&window.id).cloned(),
+                window,
+                staged: is_staged,
+                workspace,
+            });
+        }
+        Ok(tracked)
+    }
+
     /// Toggle active window sticky status
     /// Cases: active window in sticky -> remove from sticky, active window not in sticky -> add to sticky
     pub async fn toggle_active_window(&self) -> Result<bool> {
@@ -67,9 +711,14 @@ impl BusinessLogic {
         let mut sticky = self.sticky_windows.lock().await;
         if sticky.contains(&active_id) {
             sticky.remove(&active_id);
+            drop(sticky);
+            self.forget_output(active_id).await;
+            self.forget_if_untracked(active_id).await;
             Ok(false) // Removed from sticky
         } else {
             sticky.insert(active_id);
+            drop(sticky);
+            self.track_output(active_id).await;
             Ok(true) // Added to sticky
         }
     }
@@ -101,21 +750,36 @@ impl BusinessLogic {
                     let mut staged = self.staged_set.lock().await;
                     staged.remove(&id);
                     sticky.insert(id);
+                    drop(sticky);
+                    drop(staged);
+                    self.track_output(id).await;
                     Ok(true)
                 } else if sticky.contains(&id) {
                     drop(sticky);
                     drop(staged);
                     let mut sticky = self.sticky_windows.lock().await;
                     sticky.remove(&id);
+                    drop(sticky);
+                    self.forget_output(id).await;
+                    self.forget_if_untracked(id).await;
                     Ok(false)
                 } else {
                     drop(sticky);
                     drop(staged);
+                    // Record where it came from before moving it here
+                    self.track_output(id).await;
                     let current_ws_id =
                         crate::system_integration::get_active_workspace_id().await?;
-                    crate::system_integration::move_to_workspace(id, current_ws_id).await?;
+                    if let Err(e) =
+                        crate::system_integration::move_to_workspace(id, current_ws_id).await
+                    {
+                        self.forget_output(id).await;
+                        return Err(e);
+                    }
                     let mut sticky = self.sticky_windows.lock().await;
                     sticky.insert(id);
+                    drop(sticky);
+                    self.track_output(id).await;
                     Ok(true)
                 }
             }
@@ -150,21 +814,36 @@ impl BusinessLogic {
                     let mut staged = self.staged_set.lock().await;
                     staged.remove(&id);
                     sticky.insert(id);
+                    drop(sticky);
+                    drop(staged);
+                    self.track_output(id).await;
                     Ok(true)
                 } else if sticky.contains(&id) {
                     drop(sticky);
                     drop(staged);
                     let mut sticky = self.sticky_windows.lock().await;
                     sticky.remove(&id);
+                    drop(sticky);
+                    self.forget_output(id).await;
+                    self.forget_if_untracked(id).await;
                     Ok(false)
                 } else {
                     drop(sticky);
                     drop(staged);
+                    // Record where it came from before moving it here
+                    self.track_output(id).await;
                     let current_ws_id =
                         crate::system_integration::get_active_workspace_id().await?;
-                    crate::system_integration::move_to_workspace(id, current_ws_id).await?;
+                    if let Err(e) =
+                        crate::system_integration::move_to_workspace(id, current_ws_id).await
+                    {
+                        self.forget_output(id).await;
+                        return Err(e);
+                    }
                     let mut sticky = self.sticky_windows.lock().await;
                     sticky.insert(id);
+                    drop(sticky);
+                    self.track_output(id).await;
                     Ok(true)
                 }
             }
@@ -216,6 +895,9 @@ impl BusinessLogic {
                     let mut staged = self.staged_set.lock().await;
                     staged.remove(&id);
                     sticky.insert(id);
+                    drop(sticky);
+                    drop(staged);
+                    self.track_output(id).await;
                     Ok(())
                 } else {
                     drop(sticky);
@@ -271,6 +953,9 @@ impl BusinessLogic {
                     let mut staged = self.staged_set.lock().await;
                     staged.remove(&id);
                     sticky.insert(id);
+                    drop(sticky);
+                    drop(staged);
+                    self.track_output(id).await;
                     Ok(())
                 } else {
                     drop(sticky);
@@ -387,24 +1072,14 @@ impl BusinessLogic {
             return Ok(0);
         }
 
-        let mut successfully_staged = Vec::new();
+        let mut valid_sticky_windows = crate::system_integration::get_full_window_info().await?;
+        valid_sticky_windows.retain(|w| sticky_ids.contains(&w.id));
 
-        let full_window_list = crate::system_integration::get_full_window_list().await?;
-        let valid_sticky_ids: Vec<u64> = sticky_ids
-            .into_iter()
-            .filter(|id| full_window_list.contains(id))
-            .collect();
-
-        for id in valid_sticky_ids {
-            if crate::system_integration::move_to_named_workspace(id, "stage")
-                .await
-                .is_ok()
-            {
-                successfully_staged.push(id);
-            } else {
-                eprintln!("Failed to move window {} to stage", id);
-            }
-        }
+        let (successfully_staged, result) = self
+            .run_bulk_transaction(&valid_sticky_windows, |id| {
+                crate::system_integration::move_to_named_workspace(id, "stage")
+            })
+            .await;
 
         let mut sticky = self.sticky_windows.lock().await;
         let mut staged = self.staged_set.lock().await;
@@ -413,6 +1088,7 @@ impl BusinessLogic {
             staged.insert(*id);
         }
 
+        result?;
         Ok(successfully_staged.len())
     }
 
@@ -455,6 +1131,9 @@ impl BusinessLogic {
             let mut sticky = self.sticky_windows.lock().await;
             staged.remove(&window_id);
             sticky.insert(window_id);
+            drop(staged);
+            drop(sticky);
+            self.track_output(window_id).await;
 
             Ok(())
         } else {
@@ -499,6 +1178,9 @@ impl BusinessLogic {
             let mut sticky = self.sticky_windows.lock().await;
             staged.remove(&id);
             sticky.insert(id);
+            drop(staged);
+            drop(sticky);
+            self.track_output(id).await;
 
             Ok(())
         } else {
@@ -520,54 +1202,1398 @@ impl BusinessLogic {
             staged.iter().copied().collect()
         };
 
+        self.unstage_windows(ids_to_unstage, workspace_id).await
+    }
+
+    /// Unstage `staged_id` to a workspace and stage the sticky `sticky_id` in its place
+    /// If the second move fails the first one is reverted, so either both windows swap or neither
+    pub async fn swap_windows(
+        &self,
+        staged_id: u64,
+        sticky_id: u64,
+        workspace_id: u64,
+    ) -> Result<()> {
+        let windows = crate::system_integration::get_full_window_info().await?;
+        let find = |id: u64| windows.iter().find(|w| w.id == id);
+        let (Some(_), Some(sticky_window)) = (find(staged_id), find(sticky_id)) else {
+            return Err(anyhow::anyhow!("Window not found in Niri"));
+        };
+        if !self.staged_set.lock().await.contains(&staged_id) {
+            return Err(anyhow::anyhow!("Window {} is not staged", staged_id));
+        }
+        if !self.sticky_windows.lock().await.contains(&sticky_id) {
+            return Err(anyhow::anyhow!("Window {} is not sticky", sticky_id));
+        }
+
+        crate::system_integration::move_to_named_workspace(sticky_id, "stage").await?;
+        if let Err(e) = crate::system_integration::move_to_workspace(staged_id, workspace_id).await
+        {
+            let rollback = match sticky_window.workspace_id {
+                Some(ws_id) => crate::system_integration::move_to_workspace(sticky_id, ws_id).await,
+                None => Ok(()),
+            };
+            if let Err(rollback_error) = rollback {
+                tracing::error!(
+                    operation = "swap",
+                    window_id = sticky_id,
+                    "Failed to move window {} back after a failed swap: {:?}",
+                    sticky_id,
+                    rollback_error
+                );
+            }
+            return Err(e);
+        }
+
+        {
+            let mut sticky = self.sticky_windows.lock().await;
+            let mut staged = self.staged_set.lock().await;
+            sticky.remove(&sticky_id);
+            staged.insert(sticky_id);
+            staged.remove(&staged_id);
+            sticky.insert(staged_id);
+        }
+        self.track_output(staged_id).await;
+        Ok(())
+    }
+
+    /// Move the given staged windows back to sticky and to a workspace
+    async fn unstage_windows(&self, ids_to_unstage: Vec<u64>, workspace_id: u64) -> Result<usize> {
+        let mut valid_windows_to_unstage =
+            crate::system_integration::get_full_window_info().await?;
+        valid_windows_to_unstage.retain(|w| ids_to_unstage.contains(&w.id));
+
+        let (successfully_unstaged, result) = self
+            .run_bulk_transaction(&valid_windows_to_unstage, |id| {
+                crate::system_integration::move_to_workspace(id, workspace_id)
+            })
+            .await;
+
+        {
+            let mut staged = self.staged_set.lock().await;
+            let mut sticky = self.sticky_windows.lock().await;
+            for id in &successfully_unstaged {
+                staged.remove(id);
+                sticky.insert(*id);
+            }
+        }
+
+        if let Ok(Some(output)) =
+            crate::system_integration::get_workspace_output(workspace_id).await
+        {
+            let mut affinity = self.output_affinity.lock().await;
+            for id in &successfully_unstaged {
+                affinity.insert(*id, output.clone());
+            }
+        }
+
+        result?;
+        Ok(successfully_unstaged.len())
+    }
+
+    /// Remove every window from the sticky list, leaving it where it is
+    /// Staged windows stay staged
+    pub async fn clear_sticky_windows(&self) -> usize {
+        let cleared: Vec<u64> = self.sticky_windows.lock().await.drain().collect();
+        for id in &cleared {
+            self.forget_output(*id).await;
+            self.forget_if_untracked(*id).await;
+        }
+        cleared.len()
+    }
+
+    /// Reconcile the sticky list with the windows matched by `selectors`, returns (added, removed)
+    /// App ID and title selectors match every such window, one selector matching nothing fails the
+    /// whole request before anything changes. Staged windows are left alone
+    pub async fn set_sticky_windows(
+        &self,
+        mode: SetMode,
+        selectors: &[WindowSelector],
+    ) -> Result<(usize, usize)> {
+        let windows = crate::system_integration::get_full_window_info().await?;
+        let mut targets = HashSet::new();
+        for selector in selectors {
+            let matched: Vec<u64> = windows
+                .iter()
+                .filter(|w| selector.matches(w))
+                .map(|w| w.id)
+                .collect();
+            if matched.is_empty() {
+                match selector {
+                    WindowSelector::Id(id) => anyhow::bail!("Window {} not found", id),
+                    WindowSelector::AppId(app_id) => {
+                        anyhow::bail!("No window with app ID {}", app_id)
+                    }
+                    WindowSelector::Title(title) => {
+                        anyhow::bail!("No window with title containing {:?}", title)
+                    }
+                }
+            }
+            targets.extend(matched);
+        }
+        let staged = self.staged_set.lock().await.clone();
+        targets.retain(|id| !staged.contains(id));
+
+        let (added, removed) = {
+            let mut sticky = self.sticky_windows.lock().await;
+            let removed: Vec<u64> = match mode {
+                SetMode::Replace => sticky.difference(&targets).copied().collect(),
+                SetMode::Add => Vec::new(),
+                SetMode::Remove => sticky.intersection(&targets).copied().collect(),
+            };
+            let added: Vec<u64> = match mode {
+                SetMode::Replace | SetMode::Add => targets.difference(&sticky).copied().collect(),
+                SetMode::Remove => Vec::new(),
+            };
+            for id in &removed {
+                sticky.remove(id);
+            }
+            sticky.extend(&added);
+            (added, removed)
+        };
+        for id in &removed {
+            self.forget_output(*id).await;
+            self.forget_if_untracked(*id).await;
+        }
+        for id in &added {
+            self.track_output(*id).await;
+        }
+        Ok((added.len(), removed.len()))
+    }
+
+    /// Stage all sticky windows because the session went idle
+    /// Cases: already idle -> nothing, otherwise remember what was staged for idle_leave
+    pub async fn idle_enter(&self) -> Result<usize> {
+        let mut idle_staged = self.idle_staged.lock().await;
+        if idle_staged.is_some() {
+            return Ok(0);
+        }
+
+        let before = self.staged_set.lock().await.clone();
+        self.stage_all_windows().await?;
+        let newly_staged: Vec<u64> = self
+            .staged_set
+            .lock()
+            .await
+            .iter()
+            .filter(|id| !before.contains(id))
+            .copied()
+            .collect();
+
+        let count = newly_staged.len();
+        *idle_staged = Some(newly_staged);
+        Ok(count)
+    }
+
+    /// Bring back the windows staged by idle_enter, windows staged by hand stay staged
+    pub async fn idle_leave(&self, workspace_id: u64) -> Result<usize> {
+        let Some(ids) = self.idle_staged.lock().await.take() else {
+            return Ok(0);
+        };
+
+        let still_staged: Vec<u64> = {
+            let staged = self.staged_set.lock().await;
+            ids.into_iter().filter(|id| staged.contains(id)).collect()
+        };
+        if still_staged.is_empty() {
+            return Ok(0);
+        }
+        self.unstage_windows(still_staged, workspace_id).await
+    }
+
+    pub async fn focus_mode_enabled(&self) -> bool {
+        self.focus_mode.lock().await.is_some()
+    }
+
+    /// Poll the compositor until windows staged or unstaged since `before` are on their new workspace
+    /// Staged windows belong on the stage workspace, unstaged ones on `unstage_ws_id`
+    /// Windows closed in the meantime count as arrived
+    pub async fn wait_for_arrival(
+        &self,
+        before: &StateSnapshot,
+        unstage_ws_id: u64,
+        timeout: Duration,
+    ) -> Result<()> {
+        let changes = StateChanges::between(before, &self.snapshot().await, |_| true);
+        if changes.staged.is_empty() && changes.unstaged.is_empty() {
+            return Ok(());
+        }
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let workspaces = crate::system_integration::get_workspaces().await?;
+            let stage_ws_id = workspaces
+                .iter()
+                .find(|ws| ws.name.as_deref() == Some("stage"))
+                .map(|ws| ws.id);
+            let windows = crate::system_integration::get_full_window_info().await?;
+            let arrived = |id: &u64, target: Option<u64>| {
+                windows
+                    .iter()
+                    .find(|w| w.id == *id)
+                    .is_none_or(|w| target.is_some() && w.workspace_id == target)
+            };
+            let pending: Vec<u64> = changes
+                .staged
+                .iter()
+                .filter(|id| !arrived(id, stage_ws_id))
+                .chain(
+                    changes
+                        .unstaged
+                        .iter()
+                        .filter(|id| !arrived(id, Some(unstage_ws_id))),
+                )
+                .copied()
+                .collect();
+            if pending.is_empty() {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(anyhow::anyhow!(
+                    "Windows {:?} did not arrive within {}s",
+                    pending,
+                    timeout.as_secs()
+                ));
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    /// Stage all sticky windows, remembering which ones for focus_mode_off
+    /// Returns None if focus mode was already on
+    pub async fn focus_mode_on(&self) -> Result<Option<usize>> {
+        let mut focus_mode = self.focus_mode.lock().await;
+        if focus_mode.is_some() {
+            return Ok(None);
+        }
+
+        let before = self.staged_set.lock().await.clone();
+        self.stage_all_windows().await?;
+        let staged: Vec<u64> = self
+            .staged_set
+            .lock()
+            .await
+            .iter()
+            .filter(|id| !before.contains(id))
+            .copied()
+            .collect();
+
+        let count = staged.len();
+        *focus_mode = Some(staged);
+        Ok(Some(count))
+    }
+
+    /// Bring the windows staged by focus_mode_on back to the active workspace of their own output
+    /// Staged windows keep their output affinity, so each returns to the output it followed before
+    /// Windows staged, unstaged or stuck while focus mode was on keep their state
+    /// Returns None if focus mode was already off
+    pub async fn focus_mode_off(&self) -> Result<Option<usize>> {
+        let Some(ids) = self.focus_mode.lock().await.take() else {
+            return Ok(None);
+        };
+
+        let still_staged: Vec<u64> = {
+            let staged = self.staged_set.lock().await;
+            ids.into_iter().filter(|id| staged.contains(id)).collect()
+        };
+        if still_staged.is_empty() {
+            return Ok(Some(0));
+        }
+
+        // Windows whose output is gone go to the focused output, unstage_windows updates their affinity
+        let affinity = self.output_affinity.lock().await.clone();
+        let workspaces = crate::system_integration::get_workspaces().await?;
+        let fallback = crate::system_integration::get_active_workspace_id().await?;
+        let mut targets: HashMap<u64, Vec<u64>> = HashMap::new();
+        for id in still_staged {
+            let ws_id = affinity
+                .get(&id)
+                .and_then(|output| {
+                    workspaces
+                        .iter()
+                        .find(|ws| ws.is_active && ws.output.as_deref() == Some(output))
+                })
+                .map_or(fallback, |ws| ws.id);
+            targets.entry(ws_id).or_default().push(id);
+        }
+
+        let mut restored = 0;
+        for (ws_id, ids) in targets {
+            restored += self.unstage_windows(ids, ws_id).await?;
+        }
+        Ok(Some(restored))
+    }
+
+    /// Make `follower` move to whatever workspace `leader` is on, starting now
+    /// Cases: same window -> error, follower sticky or staged -> error, already attached -> re-attach
+    /// Returns false if the follower was already attached to this leader
+    pub async fn attach_window(&self, follower: u64, leader: u64) -> Result<bool> {
+        if follower == leader {
+            return Err(anyhow::anyhow!("A window cannot follow itself"));
+        }
+        let windows = crate::system_integration::get_full_window_info().await?;
+        let find = |id: u64| windows.iter().find(|w| w.id == id);
+        let (Some(follower_window), Some(leader_window)) = (find(follower), find(leader)) else {
+            return Err(anyhow::anyhow!("Window not found in Niri"));
+        };
+        if self.sticky_windows.lock().await.contains(&follower)
+            || self.staged_set.lock().await.contains(&follower)
+        {
+            return Err(anyhow::anyhow!(
+                "Window is sticky or staged, remove it before attaching"
+            ));
+        }
+
+        if let Some(ws_id) = leader_window.workspace_id
+            && follower_window.workspace_id != Some(ws_id)
+        {
+            crate::system_integration::move_to_workspace(follower, ws_id).await?;
+        }
+        let previous = self.attachments.lock().await.insert(follower, leader);
+        Ok(previous != Some(leader))
+    }
+
+    /// Stop a window following its leader, returns false if it was not attached
+    pub async fn detach_window(&self, follower: u64) -> bool {
+        self.attachments.lock().await.remove(&follower).is_some()
+    }
+
+    /// Move the followers of `leader` to its workspace after it was moved or focused
+    /// `workspace_id` comes from the event when the compositor reports it, otherwise it is queried
+    /// Followers made sticky or staged since attaching are left where they are
+    pub async fn follow_leader(&self, leader: u64, workspace_id: Option<u64>) -> Result<()> {
+        let followers: Vec<u64> = {
+            let attachments = self.attachments.lock().await;
+            let sticky = self.sticky_windows.lock().await;
+            let staged = self.staged_set.lock().await;
+            attachments
+                .iter()
+                .filter(|(f, l)| **l == leader && !sticky.contains(f) && !staged.contains(f))
+                .map(|(f, _)| *f)
+                .collect()
+        };
+        if followers.is_empty() {
+            return Ok(());
+        }
+
+        let windows = crate::system_integration::get_full_window_info().await?;
+        let workspace_of = |id: u64| windows.iter().find(|w| w.id == id)?.workspace_id;
+        let Some(ws_id) = workspace_id.or_else(|| workspace_of(leader)) else {
+            return Ok(());
+        };
+        for follower in followers {
+            if workspace_of(follower).is_none_or(|current| current == ws_id) {
+                continue;
+            }
+            match crate::system_integration::move_to_workspace(follower, ws_id).await {
+                Ok(()) => tracing::info!(
+                    operation = "attach",
+                    window_id = follower,
+                    workspace_id = ws_id,
+                    "Window {} followed window {} to workspace {}",
+                    follower,
+                    leader,
+                    ws_id
+                ),
+                Err(e) => tracing::error!(
+                    operation = "attach",
+                    window_id = follower,
+                    workspace_id = ws_id,
+                    "Failed to move window {} after window {}: {:?}",
+                    follower,
+                    leader,
+                    e
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop the handles of a window that is neither sticky nor staged anymore
+    async fn forget_if_untracked(&self, window_id: u64) {
+        let tracked = self.sticky_windows.lock().await.contains(&window_id)
+            || self.staged_set.lock().await.contains(&window_id);
+        if !tracked {
+            self.forget_handles(window_id).await;
+        }
+    }
+
+    /// Drop the group membership, slot and label of a window that is no longer tracked
+    async fn forget_handles(&self, window_id: u64) {
+        let mut groups = self.groups.lock().await;
+        for members in groups.values_mut() {
+            members.retain(|id| *id != window_id);
+        }
+        groups.retain(|_, members| !members.is_empty());
+        drop(groups);
+        self.slots.lock().await.retain(|_, id| *id != window_id);
+        self.labels.lock().await.retain(|_, id| *id != window_id);
+    }
+
+    /// Drop attachments and group membership of a closed window
+    pub async fn forget_closed_window(&self, window_id: u64) {
+        self.attachments
+            .lock()
+            .await
+            .retain(|follower, leader| *follower != window_id && *leader != window_id);
+        self.forget_handles(window_id).await;
+        self.home_workspaces.lock().await.remove(&window_id);
+        self.protected.lock().await.remove(&window_id);
+    }
+
+    /// Label a sticky or staged window, a label moves over from the window that had it
+    /// A window has one label at a time, labels can't be numbers so they never shadow window IDs
+    pub async fn set_label(&self, window_id: u64, label: &str) -> Result<()> {
+        if label.parse::<u64>().is_ok() || label.starts_with('-') {
+            anyhow::bail!(
+                "Invalid label {}, labels can't be numbers or start with -",
+                label
+            );
+        }
+        let tracked = self.sticky_windows.lock().await.contains(&window_id)
+            || self.staged_set.lock().await.contains(&window_id);
+        if !tracked {
+            anyhow::bail!("Window {} is neither sticky nor staged", window_id);
+        }
+        let mut labels = self.labels.lock().await;
+        labels.retain(|_, id| *id != window_id);
+        labels.insert(label.to_string(), window_id);
+        Ok(())
+    }
+
+    /// Remove the label of a window, returns the label it had
+    pub async fn remove_label(&self, window_id: u64) -> Option<String> {
+        let mut labels = self.labels.lock().await;
+        let label = labels
+            .iter()
+            .find(|(_, id)| **id == window_id)
+            .map(|(label, _)| label.clone())?;
+        labels.remove(&label);
+        Some(label)
+    }
+
+    /// Label of each labelled window
+    pub async fn window_labels(&self) -> HashMap<u64, String> {
+        self.labels
+            .lock()
+            .await
+            .iter()
+            .map(|(label, id)| (*id, label.clone()))
+            .collect()
+    }
+
+    /// Window a label belongs to
+    pub async fn resolve_label(&self, label: &str) -> Option<u64> {
+        self.labels.lock().await.get(label).copied()
+    }
+
+    /// Mark a window (the focused one when None) as protected or clear the mark
+    /// Returns the window ID and false if it already was in that state
+    pub async fn protect_window(
+        &self,
+        window_id: Option<u64>,
+        protect: bool,
+    ) -> Result<(u64, bool)> {
+        let window_id = match window_id {
+            Some(id) => id,
+            None => crate::system_integration::get_active_window_id().await?,
+        };
+        let mut protected = self.protected.lock().await;
+        if !protect {
+            return Ok((window_id, protected.remove(&window_id)));
+        }
+        let full_window_list = crate::system_integration::get_full_window_list().await?;
+        if !full_window_list.contains(&window_id) {
+            return Err(anyhow::anyhow!("Window not found in Niri"));
+        }
+        Ok((window_id, protected.insert(window_id)))
+    }
+
+    /// Put a window (the focused one when None) in a slot, making it sticky unless already tracked
+    /// A window has one slot at a time, moving it frees its old slot
+    /// Returns the window ID
+    pub async fn set_slot(&self, slot: u32, window_id: Option<u64>) -> Result<u64> {
+        let window_id = match window_id {
+            Some(id) => id,
+            None => crate::system_integration::get_active_window_id().await?,
+        };
+        let full_window_list = crate::system_integration::get_full_window_list().await?;
+        if !full_window_list.contains(&window_id) {
+            return Err(anyhow::anyhow!("Window not found in Niri"));
+        }
+
+        let newly_sticky = {
+            let mut sticky = self.sticky_windows.lock().await;
+            let staged = self.staged_set.lock().await;
+            !staged.contains(&window_id) && sticky.insert(window_id)
+        };
+        if newly_sticky {
+            self.track_output(window_id).await;
+        }
+
+        let mut slots = self.slots.lock().await;
+        slots.retain(|_, id| *id != window_id);
+        slots.insert(slot, window_id);
+        Ok(window_id)
+    }
+
+    /// Empty a slot, returns false if it was empty already
+    pub async fn clear_slot(&self, slot: u32) -> bool {
+        self.slots.lock().await.remove(&slot).is_some()
+    }
+
+    /// Occupied slots with their window, in slot order, windows no longer tracked left out
+    pub async fn list_slots(&self) -> Vec<(u32, u64)> {
+        let tracked = self.tracked_windows().await;
+        self.slots
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, id)| tracked.contains(id))
+            .map(|(slot, id)| (*slot, *id))
+            .collect()
+    }
+
+    /// Windows that are sticky or staged
+    async fn tracked_windows(&self) -> HashSet<u64> {
+        let mut tracked = self.sticky_windows.lock().await.clone();
+        tracked.extend(self.staged_set.lock().await.iter());
+        tracked
+    }
+
+    /// Focus the window in a slot, a staged window is brought back to the active workspace first
+    /// Returns the window ID
+    pub async fn focus_slot(&self, slot: u32) -> Result<u64> {
+        let window_id = self
+            .slots
+            .lock()
+            .await
+            .get(&slot)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("Slot {} is empty", slot))?;
+        if !self.tracked_windows().await.contains(&window_id) {
+            self.slots.lock().await.remove(&slot);
+            anyhow::bail!("Slot {} is empty, its window is no longer sticky", slot);
+        }
+        if self.staged_set.lock().await.contains(&window_id) {
+            let ws_id = crate::system_integration::get_active_workspace_id().await?;
+            self.unstage_window(window_id, ws_id).await?;
+        }
+        crate::system_integration::focus_window(window_id).await?;
+        Ok(window_id)
+    }
+
+    /// Define a group of windows following workspace switches together, placed side by side in this order
+    /// Members become sticky unless staged, windows in another group leave it
+    /// Returns false if a group with this name was replaced
+    pub async fn create_group(&self, name: &str, window_ids: &[u64]) -> Result<bool> {
+        let full_window_list = crate::system_integration::get_full_window_list().await?;
+        if let Some(missing) = window_ids.iter().find(|id| !full_window_list.contains(id)) {
+            return Err(anyhow::anyhow!("Window {} not found in Niri", missing));
+        }
+        let mut members: Vec<u64> = Vec::new();
+        for id in window_ids {
+            if !members.contains(id) {
+                members.push(*id);
+            }
+        }
+
+        let newly_sticky: Vec<u64> = {
+            let mut sticky = self.sticky_windows.lock().await;
+            let staged = self.staged_set.lock().await;
+            members
+                .iter()
+                .filter(|id| !staged.contains(id) && sticky.insert(**id))
+                .copied()
+                .collect()
+        };
+        for id in newly_sticky {
+            self.track_output(id).await;
+        }
+
+        let mut groups = self.groups.lock().await;
+        for (other, other_members) in groups.iter_mut() {
+            if other != name {
+                other_members.retain(|id| !members.contains(id));
+            }
+        }
+        groups.retain(|_, other_members| !other_members.is_empty());
+        Ok(groups.insert(name.to_string(), members).is_none())
+    }
+
+    /// Dissolve a group, its windows stay sticky
+    pub async fn remove_group(&self, name: &str) -> Result<Vec<u64>> {
+        self.groups
+            .lock()
+            .await
+            .remove(name)
+            .ok_or_else(|| anyhow::anyhow!("No group named {}", name))
+    }
+
+    /// All groups with their members in placement order, sorted by name
+    /// Members no longer tracked are left out, as are groups left without members
+    pub async fn list_groups(&self) -> Vec<(String, Vec<u64>)> {
+        let tracked = self.tracked_windows().await;
+        self.groups
+            .lock()
+            .await
+            .iter()
+            .map(|(name, members)| {
+                let members: Vec<u64> = members
+                    .iter()
+                    .copied()
+                    .filter(|id| tracked.contains(id))
+                    .collect();
+                (name.clone(), members)
+            })
+            .filter(|(_, members)| !members.is_empty())
+            .collect()
+    }
+
+    /// Check that a window is tracked and an output exists before changing its affinity
+    async fn check_output_target(&self, window_id: u64, output: &str) -> Result<()> {
+        let full_window_list = crate::system_integration::get_full_window_list().await?;
+        if !full_window_list.contains(&window_id) {
+            return Err(anyhow::anyhow!("Window not found in Niri"));
+        }
+
+        let is_tracked = self.sticky_windows.lock().await.contains(&window_id)
+            || self.staged_set.lock().await.contains(&window_id);
+        if !is_tracked {
+            return Err(anyhow::anyhow!("Window is not in sticky or staged list"));
+        }
+
+        let workspaces = crate::system_integration::get_workspaces().await?;
+        if !workspaces
+            .iter()
+            .any(|ws| ws.output.as_deref() == Some(output))
+        {
+            return Err(anyhow::anyhow!("Output {} not found in Niri", output));
+        }
+        Ok(())
+    }
+
+    /// Set the output a sticky or staged window follows workspace switches on
+    /// Cases: window not tracked -> error, output unknown -> error, otherwise store affinity
+    pub async fn set_output_affinity(&self, window_id: u64, output: &str) -> Result<()> {
+        self.check_output_target(window_id, output).await?;
+
+        let mut affinity = self.output_affinity.lock().await;
+        let mut home = self.home_outputs.lock().await;
+        home.remove(&window_id);
+        affinity.insert(window_id, output.to_string());
+        Ok(())
+    }
+
+    /// Move a sticky or staged window to another output and make it follow that output
+    /// The affinity lock is held across the move so workspace switches never see a half-updated state
+    pub async fn move_to_output(&self, window_id: u64, output: &str) -> Result<()> {
+        self.check_output_target(window_id, output).await?;
+
+        let mut affinity = self.output_affinity.lock().await;
+        crate::system_integration::move_to_output(window_id, output).await?;
+        let mut home = self.home_outputs.lock().await;
+        home.remove(&window_id);
+        affinity.insert(window_id, output.to_string());
+        Ok(())
+    }
+
+    /// Get the current follow mode
+    pub async fn get_follow_mode(&self) -> FollowMode {
+        *self.follow_mode.lock().await
+    }
+
+    /// Get the output followed in primary mode
+    pub async fn get_primary_output(&self) -> Option<String> {
+        self.primary_output.lock().await.clone()
+    }
+
+    /// Switch follow mode at runtime, output affinities are kept either way
+    /// Cases: primary mode with a new primary output -> store it, primary mode without any primary output -> error
+    pub async fn set_follow_mode(&self, mode: FollowMode, primary: Option<&str>) -> Result<()> {
+        let mut primary_output = self.primary_output.lock().await;
+        if let Some(output) = primary {
+            *primary_output = Some(output.to_string());
+        }
+        if mode == FollowMode::Primary && primary_output.is_none() {
+            return Err(anyhow::anyhow!("No primary output configured"));
+        }
+
+        let mut follow_mode = self.follow_mode.lock().await;
+        *follow_mode = mode;
+        Ok(())
+    }
+
+    /// Pause sticky-follow everywhere or on a single output
+    pub async fn pause(&self, output: Option<&str>) {
+        let mut paused = self.paused.lock().await;
+        match output {
+            Some(output) => {
+                paused.outputs.insert(output.to_string());
+            }
+            None => paused.all = true,
+        }
+    }
+
+    /// Resume sticky-follow everywhere or on a single output
+    /// Resuming everywhere also clears all per-output pauses
+    pub async fn resume(&self, output: Option<&str>) {
+        let mut paused = self.paused.lock().await;
+        match output {
+            Some(output) => {
+                paused.outputs.remove(output);
+            }
+            None => {
+                *paused = PauseState {
+                    locked: paused.locked,
+                    ..Default::default()
+                }
+            }
+        }
+    }
+
+    /// Pause sticky-follow while the session is locked
+    /// Unlocking drops closed windows and brings sticky windows to the workspace switched to meanwhile
+    pub async fn set_session_locked(&self, locked: bool) -> Result<()> {
+        self.paused.lock().await.locked = locked;
+        tracing::info!(
+            operation = "session_lock",
+            "Session {}, sticky-follow {}",
+            if locked { "locked" } else { "unlocked" },
+            if locked { "paused" } else { "resumed" }
+        );
+        if locked {
+            return Ok(());
+        }
+
+        self.reconcile_state().await?;
+        let ws_id = crate::system_integration::get_active_workspace_id().await?;
+        let workspace = crate::system_integration::get_workspaces()
+            .await?
+            .into_iter()
+            .find(|ws| ws.id == ws_id);
+        let ws_output = workspace.as_ref().and_then(|ws| ws.output.as_deref());
+        let ws_name = workspace.as_ref().and_then(|ws| ws.name.as_deref());
+        self.handle_workspace_activation(ws_id, ws_output, ws_name)
+            .await
+    }
+
+    /// List all rules with their ID and source
+    pub async fn list_rules(&self) -> Vec<(usize, Rule, RuleSource)> {
+        self.rules.lock().await.list()
+    }
+
+    /// Add a runtime rule, persisted across restarts
+    pub async fn add_rule(&self, rule: Rule) -> Result<usize> {
+        self.rules.lock().await.add(rule)
+    }
+
+    /// Remove a runtime rule by ID
+    pub async fn remove_rule(&self, id: usize) -> Result<Rule> {
+        self.rules.lock().await.remove(id)
+    }
+
+    /// Run a window through the rules without applying them
+    /// Returns the window, what the rules would do and whether it is already tracked
+    pub async fn test_rules(&self, window_id: u64) -> Result<(WindowInfo, Option<Outcome>, bool)> {
+        let window = crate::system_integration::get_full_window_info()
+            .await?
+            .into_iter()
+            .find(|w| w.id == window_id)
+            .ok_or_else(|| anyhow::anyhow!("Window not found in Niri"))?;
+
+        let is_tracked = self.sticky_windows.lock().await.contains(&window_id)
+            || self.staged_set.lock().await.contains(&window_id);
+        let outcome = self.rules.lock().await.evaluate(&window);
+        Ok((window, outcome, is_tracked))
+    }
+
+    /// Run a newly opened window through the rules
+    /// Cases: already tracked -> nothing, matching rules -> apply their actions
+    pub async fn apply_rules(&self, window: &WindowInfo) -> Result<()> {
+        let is_tracked = self.sticky_windows.lock().await.contains(&window.id)
+            || self.staged_set.lock().await.contains(&window.id);
+        if is_tracked {
+            return Ok(());
+        }
+
+        let Some(rule) = self.rules.lock().await.evaluate(window) else {
+            return Ok(());
+        };
+        let rule_ids: Vec<String> = rule.rule_ids.iter().map(|id| id.to_string()).collect();
+        tracing::info!(
+            operation = "rule",
+            window_id = window.id,
+            workspace_id = window.workspace_id,
+            "{} {} matched window {}: {}",
+            if rule_ids.len() == 1 { "Rule" } else { "Rules" },
+            rule_ids.join(", "),
+            window.id,
+            rule.describe()
+        );
+        if rule.has_action(Action::Ignore) {
+            return Ok(());
+        }
+
+        let result = self.apply_outcome(window, &rule).await;
+        let outcome = match &result {
+            Ok(()) => rule.describe(),
+            Err(e) => format!("{e:#}"),
+        };
+        crate::audit::record(
+            &format!("rule {}", rule_ids.join(",")),
+            &format!("apply rules to window {}", window.id),
+            result.is_ok(),
+            &outcome,
+        );
+        result
+    }
+
+    /// Wait for a window matching `selector` to open, None when none did within `timeout`
+    /// Windows open already don't count, a new window counts once its app ID or title matches
+    pub async fn wait_for_window(
+        &self,
+        selector: WindowSelector,
+        timeout: Duration,
+    ) -> Result<Option<WindowInfo>> {
+        let existing = crate::system_integration::get_full_window_list().await?;
+        let (found, receiver) = oneshot::channel();
+        self.window_waiters.lock().await.push(WindowWaiter {
+            selector,
+            existing,
+            found,
+        });
+        match tokio::time::timeout(timeout, receiver).await {
+            Ok(Ok(window)) => Ok(Some(window)),
+            _ => {
+                self.window_waiters
+                    .lock()
+                    .await
+                    .retain(|waiter| !waiter.found.is_closed());
+                Ok(None)
+            }
+        }
+    }
+
+    /// Hand a window that opened or changed to the `wait-for` requests it matches
+    /// Returns true when one of them took it, rules then leave the window alone
+    pub async fn claim_for_waiters(&self, window: &WindowInfo) -> bool {
+        let mut waiters = self.window_waiters.lock().await;
+        if waiters.is_empty() {
+            return false;
+        }
+        let (matched, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut *waiters)
+            .into_iter()
+            .partition(|waiter| {
+                !waiter.existing.contains(&window.id) && waiter.selector.matches(window)
+            });
+        // Requests whose CLI went away are dropped too
+        *waiters = waiting
+            .into_iter()
+            .filter(|waiter| !waiter.found.is_closed())
+            .collect();
+        drop(waiters);
+
+        let mut claimed = false;
+        for waiter in matched {
+            claimed |= waiter.found.send(window.clone()).is_ok();
+        }
+        claimed
+    }
+
+    /// Apply a single rule action to a window, as `wait-for` does once the window opened
+    pub async fn apply_action(&self, window: &WindowInfo, action: Action) -> Result<()> {
+        let outcome = Outcome {
+            rule_ids: Vec::new(),
+            actions: vec![action],
+            output: None,
+        };
+        self.apply_outcome(window, &outcome).await
+    }
+
+    /// Perform the actions of matching rules on a window
+    async fn apply_outcome(&self, window: &WindowInfo, rule: &Outcome) -> Result<()> {
+        if rule.has_action(Action::Float) && !window.is_floating {
+            crate::system_integration::move_to_floating(window.id).await?;
+        }
+
+        let sticky = rule.has_action(Action::Sticky) || rule.has_action(Action::Stage);
+        if sticky {
+            self.add_sticky_window(window.id, None).await?;
+        }
+        if let Some(output) = &rule.output {
+            if sticky {
+                self.move_to_output(window.id, output).await?;
+            } else {
+                crate::system_integration::move_to_output(window.id, output).await?;
+            }
+        }
+        if rule.has_action(Action::Stage) {
+            self.stage_window(window.id).await?;
+        }
+        Ok(())
+    }
+
+    /// Capture the state before an undoable operation
+    /// `with_workspaces` also records where tracked windows are, so moves can be reverted
+    pub async fn undo_checkpoint(&self, with_workspaces: bool) -> UndoCheckpoint {
+        let before = self.snapshot().await;
+        let mut workspaces = HashMap::new();
+        if with_workspaces
+            && let Ok(windows) = crate::system_integration::get_full_window_info().await
+        {
+            for window in windows {
+                if let Some(ws_id) = window.workspace_id
+                    && (before.sticky.contains(&window.id) || before.staged.contains(&window.id))
+                {
+                    workspaces.insert(window.id, ws_id);
+                }
+            }
+        }
+        UndoCheckpoint { before, workspaces }
+    }
+
+    /// Put a finished operation on the undo stack, unless it changed nothing
+    pub async fn push_undo(&self, checkpoint: UndoCheckpoint) {
+        let after = self.snapshot().await;
+        let changes = StateChanges::between(&checkpoint.before, &after, |_| true);
+        let description = changes.describe();
+        if description.is_empty() {
+            return;
+        }
+        let mut stack = self.undo_stack.lock().await;
+        if stack.len() == UNDO_DEPTH {
+            stack.pop_front();
+        }
+        stack.push_back(UndoEntry {
+            description,
+            checkpoint,
+        });
+    }
+
+    /// Revert the most recent operation, moving windows back where they were
+    /// Returns what was undone
+    pub async fn undo(&self) -> Result<String> {
+        let entry = self
+            .undo_stack
+            .lock()
+            .await
+            .pop_back()
+            .ok_or_else(|| anyhow::anyhow!("Nothing to undo"))?;
+        let before = &entry.checkpoint.before;
         let full_window_list = crate::system_integration::get_full_window_list().await?;
-        let valid_ids_to_unstage: Vec<u64> = ids_to_unstage
+        let now = self.snapshot().await;
+
+        let mut tracked: Vec<u64> = [&before.sticky, &before.staged, &now.sticky, &now.staged]
             .into_iter()
+            .flatten()
+            .copied()
             .filter(|id| full_window_list.contains(id))
             .collect();
+        tracked.sort_unstable();
+        tracked.dedup();
 
-        let mut successfully_unstaged = Vec::new();
-        for id in &valid_ids_to_unstage {
-            if crate::system_integration::move_to_workspace(*id, workspace_id)
-                .await
-                .is_ok()
-            {
-                successfully_unstaged.push(*id);
-            } else {
-                eprintln!("Failed to move window {} to workspace {}", id, workspace_id);
+        let mut failed = Vec::new();
+        for id in tracked {
+            let was_staged = before.staged.contains(&id);
+            let result = match (was_staged, now.staged.contains(&id)) {
+                (true, false) => {
+                    crate::system_integration::move_to_named_workspace(id, "stage").await
+                }
+                (false, true) => match entry.checkpoint.workspaces.get(&id) {
+                    Some(&ws_id) => crate::system_integration::move_to_workspace(id, ws_id).await,
+                    None => match crate::system_integration::get_active_workspace_id().await {
+                        Ok(ws_id) => crate::system_integration::move_to_workspace(id, ws_id).await,
+                        Err(e) => Err(e),
+                    },
+                },
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                tracing::error!(
+                    operation = "undo",
+                    window_id = id,
+                    "Failed to move window {} back: {:?}",
+                    id,
+                    e
+                );
+                failed.push(id);
             }
         }
 
-        let mut staged = self.staged_set.lock().await;
+        let open = |ids: &[u64]| -> HashSet<u64> {
+            ids.iter()
+                .copied()
+                .filter(|id| full_window_list.contains(id))
+                .collect()
+        };
+        *self.sticky_windows.lock().await = open(&before.sticky);
+        *self.staged_set.lock().await = open(&before.staged);
+        for id in now.sticky.iter().chain(&now.staged) {
+            self.forget_if_untracked(*id).await;
+        }
+
+        if failed.is_empty() {
+            Ok(entry.description)
+        } else {
+            Err(anyhow::anyhow!(
+                "Undid {}, but failed to move windows {:?} back",
+                entry.description,
+                failed
+            ))
+        }
+    }
+
+    /// Get the current pause state
+    pub async fn get_pause_state(&self) -> PauseState {
+        self.paused.lock().await.clone()
+    }
+
+    /// Collect the daemon status for `nsticky status`
+    pub async fn status(&self) -> DaemonStatus {
+        let snapshot = self.snapshot().await;
+        DaemonStatus {
+            backend: crate::system_integration::backend(),
+            follow_mode: self.get_follow_mode().await,
+            primary_output: self.get_primary_output().await,
+            paused: self.get_pause_state().await,
+            sticky: snapshot.sticky.len(),
+            staged: snapshot.staged.len(),
+            event_stream_connected: METRICS.event_stream_connected.load(Ordering::Relaxed),
+            queued_requests: crate::offline::queued(),
+            moves: METRICS.moves.load(Ordering::Relaxed),
+            move_failures: METRICS.move_failures.load(Ordering::Relaxed),
+            query_failures: METRICS.query_failures.load(Ordering::Relaxed),
+            event_parse_errors: METRICS.event_parse_errors.load(Ordering::Relaxed),
+            recent_errors: crate::metrics::recent_errors(),
+            events: METRICS.events.load(Ordering::Relaxed),
+            trace_ipc: crate::logging::trace_ipc_enabled(),
+            focus_mode: self.focus_mode_enabled().await,
+            attachments: {
+                let mut attachments: Vec<(u64, u64)> =
+                    self.attachments.lock().await.clone().into_iter().collect();
+                attachments.sort_unstable();
+                attachments
+            },
+            protected: {
+                let mut protected: Vec<u64> = self.protected.lock().await.iter().copied().collect();
+                protected.sort_unstable();
+                protected
+            },
+            tasks: crate::supervisor::health(),
+            latencies: crate::metrics::latencies(),
+        }
+    }
+
+    /// Drop tracked windows that no longer exist in Niri
+    /// Runs in the background at startup so CLI requests are not blocked on the first query
+    pub async fn reconcile_state(&self) -> Result<()> {
+        let full_window_list = crate::system_integration::get_full_window_list().await?;
+
         let mut sticky = self.sticky_windows.lock().await;
-        for id in &successfully_unstaged {
-            staged.remove(id);
-            sticky.insert(*id);
+        let mut staged = self.staged_set.lock().await;
+        sticky.retain(|id| full_window_list.contains(id));
+        staged.retain(|id| full_window_list.contains(id));
+        let mut affinity = self.output_affinity.lock().await;
+        let mut home = self.home_outputs.lock().await;
+        affinity.retain(|id, _| sticky.contains(id) || staged.contains(id));
+        home.retain(|id, _| sticky.contains(id) || staged.contains(id));
+        tracing::info!(
+            operation = "reconcile",
+            "Reconciled state: {} sticky, {} staged",
+            sticky.len(),
+            staged.len()
+        );
+
+        Ok(())
+    }
+
+    /// Handle output hotplug by remapping output affinities
+    /// Cases: affinity output removed -> follow the output niri moved the window to and remember the old one,
+    /// remembered output reconnected -> move sticky windows back to its active workspace and restore affinity
+    pub async fn handle_outputs_changed(&self, outputs: &HashSet<String>) -> Result<()> {
+        let affinity_snapshot = self.output_affinity.lock().await.clone();
+
+        // Windows whose output disappeared have been migrated by niri, follow them
+        for (id, old_output) in affinity_snapshot {
+            if outputs.contains(&old_output) {
+                continue;
+            }
+            match crate::system_integration::get_window_output(id).await {
+                Ok(Some(new_output)) => {
+                    tracing::info!(
+                        operation = "output_removed",
+                        window_id = id,
+                        "Output {} removed, window {} now on {}",
+                        old_output,
+                        id,
+                        new_output
+                    );
+                    let mut affinity = self.output_affinity.lock().await;
+                    let mut home = self.home_outputs.lock().await;
+                    home.entry(id).or_insert(old_output);
+                    affinity.insert(id, new_output);
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!(
+                    operation = "output_removed",
+                    window_id = id,
+                    "Failed to resolve output of window {}: {:?}",
+                    id,
+                    e
+                ),
+            }
         }
 
-        Ok(successfully_unstaged.len())
+        // Windows whose original output came back are re-homed there
+        let returning: Vec<(u64, String)> = {
+            let home = self.home_outputs.lock().await;
+            home.iter()
+                .filter(|(_, output)| outputs.contains(*output))
+                .map(|(id, output)| (*id, output.clone()))
+                .collect()
+        };
+        if returning.is_empty() {
+            return Ok(());
+        }
+
+        let workspaces = crate::system_integration::get_workspaces().await?;
+        for (id, output) in returning {
+            let is_sticky = self.sticky_windows.lock().await.contains(&id);
+            // Staged windows stay on the stage workspace, they only get their affinity back
+            if is_sticky
+                && let Some(ws) = workspaces
+                    .iter()
+                    .find(|ws| ws.is_active && ws.output.as_deref() == Some(output.as_str()))
+                && let Err(e) = crate::system_integration::move_to_workspace(id, ws.id).await
+            {
+                tracing::error!(
+                    operation = "output_added",
+                    window_id = id,
+                    workspace_id = ws.id,
+                    "Failed to move window {} back to {}: {:?}",
+                    id,
+                    output,
+                    e
+                );
+                continue;
+            }
+
+            tracing::info!(
+                operation = "output_added",
+                window_id = id,
+                "Output {} reconnected, window {} re-homed",
+                output,
+                id
+            );
+            let mut affinity = self.output_affinity.lock().await;
+            let mut home = self.home_outputs.lock().await;
+            home.remove(&id);
+            affinity.insert(id, output);
+        }
+
+        Ok(())
     }
 
     /// Handle workspace activation by moving sticky windows to new workspace
-    pub async fn handle_workspace_activation(&self, ws_id: u64) -> Result<()> {
+    /// In output mode only windows belonging to the activated workspace's output follow it,
+    /// in primary mode all sticky windows follow switches on the primary output only
+    /// Paused outputs neither pull windows in nor lose their windows to other outputs
+    /// Protected windows (marked, fullscreen or by app ID) stay where they are
+    /// Private workspaces, named with one of `private_workspace_prefixes`, are never followed into
+    pub async fn handle_workspace_activation(
+        &self,
+        ws_id: u64,
+        ws_output: Option<&str>,
+        ws_name: Option<&str>,
+    ) -> Result<()> {
+        if let Some(name) = ws_name
+            && let Some(prefix) = self
+                .config
+                .private_workspace_prefixes
+                .iter()
+                .find(|prefix| !prefix.is_empty() && name.starts_with(prefix.as_str()))
+        {
+            tracing::info!(
+                operation = "workspace_activation",
+                workspace_id = ws_id,
+                "Workspace {} is private (prefix {:?}), sticky windows stay out",
+                name,
+                prefix
+            );
+            return Ok(());
+        }
+
+        let paused = self.get_pause_state().await;
+        if paused.all
+            || paused.locked
+            || ws_output.is_some_and(|output| paused.outputs.contains(output))
+        {
+            tracing::info!(
+                operation = "workspace_activation",
+                workspace_id = ws_id,
+                "Sticky-follow paused, ignoring workspace {}",
+                ws_id
+            );
+            return Ok(());
+        }
+
         // Update sticky window list, removing non-existent windows
+        // A failed query (counted by system_integration) must not look like every window closed
+        let windows = match crate::system_integration::get_full_window_info().await {
+            Ok(windows) => windows,
+            Err(e) => {
+                tracing::warn!(
+                    operation = "workspace_activation",
+                    workspace_id = ws_id,
+                    "Failed to list windows, not following workspace {}: {:?}",
+                    ws_id,
+                    e
+                );
+                return Ok(());
+            }
+        };
         let sticky_snapshot = {
             let mut sticky = self.sticky_windows.lock().await;
-            let full_window_list = crate::system_integration::get_full_window_list()
-                .await
-                .unwrap_or_default();
-            sticky.retain(|win_id| full_window_list.contains(win_id));
-            println!("Updated sticky windows: {:?}", *sticky);
+            sticky.retain(|win_id| windows.iter().any(|w| w.id == *win_id));
+            tracing::info!(
+                operation = "workspace_activation",
+                workspace_id = ws_id,
+                "Updated sticky windows: {:?}",
+                *sticky
+            );
             sticky.clone()
         };
 
-        // Move sticky windows to new workspace
-        for win_id in sticky_snapshot.iter() {
-            if let Err(_e) = crate::system_integration::move_to_workspace(*win_id, ws_id).await {
-                eprintln!("Failed to move window {}: {:?}", win_id, _e);
+        let follow_mode = self.get_follow_mode().await;
+        if follow_mode == FollowMode::Primary {
+            let primary = self.get_primary_output().await;
+            if let (Some(primary), Some(ws_output)) = (primary, ws_output)
+                && primary != ws_output
+            {
+                tracing::info!(
+                    operation = "workspace_activation",
+                    workspace_id = ws_id,
+                    "Ignoring workspace {} on secondary output {}",
+                    ws_id,
+                    ws_output
+                );
+                return Ok(());
             }
         }
+        if sticky_snapshot.is_empty() {
+            return Ok(());
+        }
+        let affinity = self.output_affinity.lock().await.clone();
+
+        let follows = |win_id: &u64| {
+            let other_output = follow_mode == FollowMode::Output
+                && matches!((ws_output, affinity.get(win_id)), (Some(ws_output), Some(win_output)) if ws_output != win_output);
+            let paused_output = affinity
+                .get(win_id)
+                .is_some_and(|output| paused.outputs.contains(output));
+            !other_output && !paused_output
+        };
+
+        let marked = self.protected.lock().await.clone();
+        let protection = &self.config.protect;
+        let held_back: HashSet<u64> = sticky_snapshot
+            .iter()
+            .copied()
+            .filter(|id| follows(id))
+            .filter(|id| {
+                let window = windows.iter().find(|w| w.id == *id);
+                let reason = if marked.contains(id) {
+                    "protected"
+                } else if protection.fullscreen && window.is_some_and(|w| w.is_fullscreen) {
+                    "fullscreen"
+                } else if window
+                    .and_then(|w| w.app_id.as_ref())
+                    .is_some_and(|app_id| protection.app_ids.contains(app_id))
+                {
+                    "protected by app ID"
+                } else {
+                    return false;
+                };
+                tracing::info!(
+                    operation = "workspace_activation",
+                    window_id = *id,
+                    workspace_id = ws_id,
+                    "Window {} is {}, leaving it in place",
+                    id,
+                    reason
+                );
+                true
+            })
+            .collect();
+        let follows = |win_id: &u64| follows(win_id) && !held_back.contains(win_id);
 
+        // Ungrouped windows move on their own, groups as one unit in their defined order
+        let groups = self.groups.lock().await.clone();
+        let grouped: HashSet<u64> = groups.values().flatten().copied().collect();
+        let mut units: Vec<Vec<u64>> = sticky_snapshot
+            .iter()
+            .filter(|id| !grouped.contains(id) && follows(id))
+            .map(|id| vec![*id])
+            .collect();
+        units.extend(groups.into_values().map(|members| {
+            members
+                .into_iter()
+                .filter(|id| sticky_snapshot.contains(id) && follows(id))
+                .collect()
+        }));
+
+        // Move sticky windows to new workspace
+        let (moved, failed) = self
+            .keep_focus(async {
+                let mut moved = Vec::new();
+                let mut failed = Vec::new();
+                for unit in &units {
+                    // Each group member goes right after the previous one that arrived
+                    let mut anchor = None;
+                    for win_id in unit {
+                        let result = match anchor {
+                            None => {
+                                crate::system_integration::move_to_workspace(*win_id, ws_id).await
+                            }
+                            Some(anchor) => {
+                                crate::system_integration::move_next_to(*win_id, anchor, ws_id)
+                                    .await
+                            }
+                        };
+                        if let Err(_e) = result {
+                            failed.push(*win_id);
+                            tracing::error!(
+                                operation = "workspace_activation",
+                                window_id = *win_id,
+                                workspace_id = ws_id,
+                                "Failed to move window {}: {:?}",
+                                win_id,
+                                _e
+                            );
+                        } else {
+                            moved.push(*win_id);
+                            anchor = Some(*win_id);
+                        }
+                    }
+                }
+                (moved, failed)
+            })
+            .await;
+
+        crate::stats::record_follows(&moved, &windows);
+        if !moved.is_empty() || !failed.is_empty() {
+            let result = if failed.is_empty() {
+                format!("moved {:?}", moved)
+            } else {
+                format!("moved {:?}, failed {:?}", moved, failed)
+            };
+            crate::audit::record(
+                "workspace follow",
+                &format!("follow to workspace {}", ws_id),
+                failed.is_empty(),
+                &result,
+            );
+        }
         Ok(())
     }
 }
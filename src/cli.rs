@@ -1,7 +1,8 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use std::collections::BTreeMap;
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    io::{AsyncReadExt, AsyncWriteExt, BufReader},
     net::UnixStream,
 };
 
@@ -10,9 +11,87 @@ use tokio::{
 #[command(name = "nsticky")]
 #[command(version)]
 #[command(about = "Manage sticky windows via CLI", long_about = None)]
-struct Cli {
+pub struct Cli {
+    /// Compositor backend, detected from the environment by default
+    #[arg(long, global = true, value_enum)]
+    pub backend: Option<BackendArg>,
+    /// Log every message exchanged with the compositor, daemon only
+    #[arg(long)]
+    pub trace_ipc: bool,
+    /// Output format of list-type commands (sticky/stage/slot/group/rule list, status) and bar
+    #[arg(short, long, global = true, value_enum)]
+    format: Option<FormatArg>,
+    /// Talk to the daemon's read-only socket, needs read_only_socket in the config
+    #[arg(long, global = true)]
+    read_only: bool,
+    /// Runs the daemon when omitted
     #[command(subcommand)]
-    command: Commands,
+    command: Option<Commands>,
+}
+
+impl Cli {
+    pub fn runs_daemon(&self) -> bool {
+        self.command.is_none()
+    }
+
+    /// Parse the command line, expanding aliases from the config first
+    pub fn parse_with_aliases() -> Self {
+        let args: Vec<String> = std::env::args().collect();
+        let aliases = crate::config::load()
+            .map(|config| config.aliases)
+            .unwrap_or_default();
+        Self::parse_from(expand_alias(args, &aliases))
+    }
+}
+
+/// Replace the command with its alias definition, arguments after it are kept
+/// Built-in commands always win over aliases of the same name
+fn expand_alias(mut args: Vec<String>, aliases: &BTreeMap<String, String>) -> Vec<String> {
+    // The command is the first argument that is neither an option nor the value of --backend/--format
+    let mut index = 1;
+    while let Some(arg) = args.get(index) {
+        match arg.as_str() {
+            "--backend" | "--format" | "-f" => index += 2,
+            arg if arg.starts_with('-') => index += 1,
+            _ => break,
+        }
+    }
+    let Some(name) = args.get(index) else {
+        return args;
+    };
+    let builtin = Cli::command()
+        .get_subcommands()
+        .any(|command| command.get_name() == name || command.get_all_aliases().any(|a| a == name));
+    if builtin {
+        return args;
+    }
+    if let Some(definition) = aliases.get(name) {
+        let expansion: Vec<String> = definition.split_whitespace().map(String::from).collect();
+        args.splice(index..=index, expansion);
+    }
+    args
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum BackendArg {
+    /// niri, over NIRI_SOCKET
+    Niri,
+    /// sway or i3, over SWAYSOCK or I3SOCK
+    Sway,
+    /// Any Wayland compositor with ext-foreign-toplevel-list, window tracking only
+    #[cfg(feature = "foreign-toplevel")]
+    ForeignToplevel,
+}
+
+impl From<BackendArg> for crate::system_integration::Backend {
+    fn from(backend: BackendArg) -> Self {
+        match backend {
+            BackendArg::Niri => Self::Niri,
+            BackendArg::Sway => Self::Sway,
+            #[cfg(feature = "foreign-toplevel")]
+            BackendArg::ForeignToplevel => Self::ForeignToplevel,
+        }
+    }
 }
 
 #[derive(Subcommand, Debug)]
@@ -24,9 +103,249 @@ enum Commands {
     },
     /// Manage staged windows
     Stage {
+        /// Only return once the compositor shows the windows on their new workspace
+        #[arg(long, global = true)]
+        wait: bool,
         #[command(subcommand)]
         action: StageAction,
     },
+    /// Manage rules applied to windows when they open
+    Rule {
+        #[command(subcommand)]
+        action: RuleAction,
+    },
+    /// Manage groups of sticky windows that follow workspace switches side by side
+    Group {
+        #[command(subcommand)]
+        action: GroupAction,
+    },
+    /// Assign sticky windows to numbered slots for focus-slot
+    Slot {
+        #[command(subcommand)]
+        action: SlotAction,
+    },
+    /// Focus the window in a slot from any workspace
+    FocusSlot {
+        /// Slot number
+        slot: u32,
+    },
+    /// Pause sticky windows following workspace switches
+    Pause {
+        /// Only pause on this output, e.g. HDMI-A-1
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Resume sticky windows following workspace switches
+    Resume {
+        /// Only resume on this output, e.g. HDMI-A-1
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Stage all sticky windows while the session is idle, e.g. from swayidle
+    Idle {
+        /// enter stages all sticky windows, leave restores them
+        state: IdleArg,
+    },
+    /// Run as a waybar custom module, printing JSON on every state change
+    Waybar,
+    /// Print a status line on every state change, for polybar, i3bar and similar bars
+    /// `--format text` (default) prints plain lines, `--format i3bar` speaks the i3bar protocol
+    Bar,
+    /// Show daemon state, counters and latency percentiles of recent operations
+    Status {
+        /// One line like "📌3 ⬇2" for shell prompts and minimal bars
+        #[arg(long)]
+        short: bool,
+    },
+    /// Summarize which apps are made sticky, staged and followed most, needs usage_stats in the config
+    Stats,
+    /// Revert the last sticky/stage change, moving windows back where they were
+    Undo,
+    /// Make a window follow another window to whatever workspace it is on
+    Attach {
+        /// Window ID of the window to move along
+        follower: WindowArg,
+        /// Window ID of the window to follow
+        leader: WindowArg,
+    },
+    /// Stop a window following the window it was attached to
+    Detach {
+        /// Window ID of the attached window
+        follower: WindowArg,
+    },
+    /// Bring a staged window back and stage a sticky one in its place, both or neither
+    Swap {
+        /// Window ID of the staged window to bring to the current workspace
+        staged_id: WindowArg,
+        /// Window ID of the sticky window to stage
+        sticky_id: WindowArg,
+    },
+    /// Give a sticky or staged window a label, usable wherever a window ID is, e.g. `nsticky stage add notes`
+    Label {
+        /// Window ID or label
+        window_id: WindowArg,
+        /// New label, not a number
+        label: String,
+    },
+    /// Remove the label of a window
+    Unlabel {
+        /// Window ID or label
+        window_id: WindowArg,
+    },
+    /// Wait for a window to open and apply an action to it, e.g. `nsticky wait-for --app-id spotify --then stage & spotify`
+    WaitFor {
+        /// Wait for a window with this app ID
+        #[arg(
+            long,
+            visible_alias = "app-id",
+            required_unless_present = "title",
+            conflicts_with = "title"
+        )]
+        appid: Option<String>,
+        /// Wait for a window whose title contains this text
+        #[arg(long)]
+        title: Option<String>,
+        /// What to do with the window
+        #[arg(long, value_name = "ACTION", default_value = "sticky")]
+        then: WaitActionArg,
+        /// Give up if no such window opened in time
+        #[arg(long, value_name = "DURATION", default_value = "30s", value_parser = crate::protocol::parse_duration)]
+        timeout: std::time::Duration,
+    },
+    /// Reconcile the sticky list with windows read from stdin in one step
+    /// One per line: a window ID or label, `--appid <app_id>` or `--title <text>`, `#` starts a comment
+    #[command(group(clap::ArgGroup::new("mode").required(true)))]
+    Set {
+        /// Make the sticky list exactly these windows
+        #[arg(long, group = "mode")]
+        replace: bool,
+        /// Add these windows to the sticky list
+        #[arg(long, group = "mode")]
+        add: bool,
+        /// Remove these windows from the sticky list
+        #[arg(long, group = "mode")]
+        remove: bool,
+        /// `-` to read from stdin
+        #[arg(value_parser = ["-"])]
+        input: String,
+    },
+    /// Keep a sticky window where it is on workspace switches, e.g. while a menu is open
+    Protect {
+        /// Window ID, the focused window when omitted
+        window_id: Option<WindowArg>,
+    },
+    /// Let a protected window follow workspace switches again
+    Unprotect {
+        /// Window ID, the focused window when omitted
+        window_id: Option<WindowArg>,
+    },
+    /// Move a window to the active workspace and make it sticky
+    Bring {
+        /// Window ID to bring
+        #[arg(required_unless_present_any = ["appid", "title"], conflicts_with_all = ["appid", "title"])]
+        window_id: Option<WindowArg>,
+        /// Bring the first window with this app ID
+        #[arg(long, conflicts_with = "title")]
+        appid: Option<String>,
+        /// Bring the first window whose title contains this text
+        #[arg(long)]
+        title: Option<String>,
+    },
+    /// Switch logging of raw compositor IPC on or off, prints the current state when omitted
+    TraceIpc {
+        /// on or off
+        state: Option<SwitchArg>,
+    },
+    /// Stage all sticky windows, `off` brings them back to their own outputs, prints the state when omitted
+    FocusMode {
+        /// on or off
+        state: Option<SwitchArg>,
+    },
+    /// Interactive terminal UI listing all windows
+    #[cfg(feature = "tui")]
+    Tui,
+    /// Show the session id and the socket and state file derived from it
+    Session {
+        /// Only print the state export file path
+        #[arg(long)]
+        state_file: bool,
+    },
+    /// Show or switch how sticky windows follow workspace switches
+    Mode {
+        /// Follow mode to switch to, prints the current mode when omitted
+        mode: Option<ModeArg>,
+        /// Primary output to follow, only used with the primary mode
+        #[arg(requires = "mode")]
+        output: Option<String>,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum FormatArg {
+    /// Text meant for people, for bar one line per update (also `text`)
+    #[value(alias = "text")]
+    Plain,
+    /// JSON for jq, for bar the i3bar protocol (also `i3bar`)
+    #[value(alias = "i3bar")]
+    Json,
+    /// Tab-separated rows without a header, for awk and cut
+    Tsv,
+}
+
+impl From<FormatArg> for crate::protocol::OutputFormat {
+    fn from(format: FormatArg) -> Self {
+        match format {
+            FormatArg::Plain => Self::Plain,
+            FormatArg::Json => Self::Json,
+            FormatArg::Tsv => Self::Tsv,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum SwitchArg {
+    On,
+    Off,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum IdleArg {
+    /// The session went idle
+    Enter,
+    /// The session is active again
+    Leave,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum RuleActionArg {
+    /// Make the window sticky
+    Sticky,
+    /// Make the window sticky and stage it
+    Stage,
+    /// Leave the window alone, rules below don't apply to it
+    Ignore,
+    /// Make the window floating
+    Float,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum WaitActionArg {
+    /// Make the window sticky
+    Sticky,
+    /// Make the window sticky and stage it
+    Stage,
+    /// Make the window floating
+    Float,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum ModeArg {
+    /// Sticky windows follow workspace switches on every output
+    Global,
+    /// Sticky windows follow workspace switches only on their own output
+    Output,
+    /// Sticky windows follow workspace switches only on the primary output
+    Primary,
 }
 
 #[derive(Subcommand, Debug)]
@@ -35,17 +354,45 @@ enum StickyAction {
     #[command(alias = "a")]
     Add {
         /// Window ID to add to sticky list
-        window_id: u64,
+        #[arg(required_unless_present = "pick", conflicts_with = "pick")]
+        window_id: Option<WindowArg>,
+        /// Click the window instead of giving its ID (niri 25.05 or newer)
+        #[arg(long)]
+        pick: bool,
+        /// Remove the window from the sticky list again after this long, e.g. 45m or 1h30m
+        #[arg(long = "for", value_name = "DURATION", value_parser = crate::protocol::parse_duration)]
+        duration: Option<std::time::Duration>,
     },
     /// Remove window from sticky list
     #[command(alias = "r")]
     Remove {
         /// Window ID to remove from sticky list
-        window_id: u64,
+        #[arg(required_unless_present = "pick", conflicts_with = "pick")]
+        window_id: Option<WindowArg>,
+        /// Click the window instead of giving its ID (niri 25.05 or newer)
+        #[arg(long)]
+        pick: bool,
+        /// Move the window back to the workspace it was on when it became sticky
+        #[arg(long, conflicts_with = "stay")]
+        home: bool,
+        /// Leave the window where it is, even with remove_returns_home set
+        #[arg(long)]
+        stay: bool,
     },
     /// List all sticky windows
     #[command(alias = "l")]
-    List,
+    List {
+        /// Show sticky and staged windows grouped by output and workspace
+        #[arg(short, long)]
+        detailed: bool,
+    },
+    /// Remove all windows from sticky list, leaving them where they are
+    #[command(alias = "c")]
+    Clear {
+        /// Only list the windows that would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
     /// Toggle active window in sticky list
     #[command(alias = "t")]
     ToggleActive,
@@ -61,24 +408,142 @@ enum StickyAction {
         /// Window title to toggle
         title: String,
     },
+    /// Set the output whose workspace switches a window follows
+    #[command(alias = "so")]
+    SetOutput {
+        /// Window ID to update
+        window_id: WindowArg,
+        /// Output name, e.g. HDMI-A-1
+        output: String,
+    },
+    /// Move a window to another output, keeping it sticky there
+    #[command(alias = "mo")]
+    MoveOutput {
+        /// Window ID to move
+        window_id: WindowArg,
+        /// Output name, e.g. HDMI-A-1
+        output: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SlotAction {
+    /// Put a window in a slot, making it sticky
+    #[command(alias = "s")]
+    Set {
+        /// Slot number
+        slot: u32,
+        /// Window ID, the focused window when omitted
+        window_id: Option<WindowArg>,
+    },
+    /// Empty a slot
+    #[command(alias = "c")]
+    Clear {
+        /// Slot number
+        slot: u32,
+    },
+    /// List occupied slots
+    #[command(alias = "l")]
+    List,
+}
+
+#[derive(Subcommand, Debug)]
+enum GroupAction {
+    /// Create or replace a group, its windows become sticky and arrive in the given order
+    #[command(alias = "c")]
+    Create {
+        /// Group name
+        name: String,
+        /// Window IDs, from left to right
+        #[arg(num_args = 2.., required = true)]
+        window_ids: Vec<WindowArg>,
+    },
+    /// Dissolve a group, its windows stay sticky
+    #[command(alias = "r")]
+    Remove {
+        /// Group name
+        name: String,
+    },
+    /// List groups with their windows
+    #[command(alias = "l")]
+    List,
+}
+
+#[derive(Subcommand, Debug)]
+enum RuleAction {
+    /// Add a rule, kept across daemon restarts
+    #[command(alias = "a")]
+    Add {
+        /// Match windows with this exact app ID
+        #[arg(long, required_unless_present_any = ["title", "title_regex", "floating", "fullscreen"])]
+        appid: Option<String>,
+        /// Match windows whose title contains this text
+        #[arg(long)]
+        title: Option<String>,
+        /// Match windows whose title matches this regular expression
+        #[arg(long)]
+        title_regex: Option<String>,
+        /// Match only floating (true) or tiled (false) windows
+        #[arg(long)]
+        floating: Option<bool>,
+        /// Match only fullscreen (true) or non-fullscreen (false) windows, sway/i3 only
+        #[arg(long)]
+        fullscreen: Option<bool>,
+        /// What to do with matching windows, repeatable, sticky by default
+        #[arg(long = "action", value_name = "ACTION")]
+        actions: Vec<RuleActionArg>,
+        /// Move matching windows to this output
+        #[arg(long)]
+        output: Option<String>,
+        /// Rules with a higher priority are evaluated first
+        #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+        priority: i32,
+    },
+    /// List all rules, from config.toml and added at runtime
+    #[command(alias = "l")]
+    List,
+    /// Remove a rule added at runtime
+    #[command(alias = "r")]
+    Remove {
+        /// Rule ID as shown by `rule list`
+        id: usize,
+    },
+    /// Show which rules match a window and what they would do, without applying them
+    #[command(alias = "t")]
+    Test {
+        /// Window ID to run through the rules
+        window_id: WindowArg,
+    },
 }
 
 #[derive(Subcommand, Debug)]
 enum StageAction {
     /// List all staged windows
     #[command(alias = "l")]
-    List,
+    List {
+        /// Show staged windows grouped by output and workspace
+        #[arg(short, long)]
+        detailed: bool,
+    },
     /// Add window to stage (move from sticky to stage workspace)
     #[command(alias = "a")]
     Add {
         /// Window ID to stage
-        window_id: u64,
+        #[arg(required_unless_present = "pick", conflicts_with = "pick")]
+        window_id: Option<WindowArg>,
+        /// Click the window instead of giving its ID (niri 25.05 or newer)
+        #[arg(long)]
+        pick: bool,
     },
     /// Remove window from stage (move from stage to current workspace)
     #[command(alias = "r")]
     Remove {
         /// Window ID to unstage
-        window_id: u64,
+        #[arg(required_unless_present = "pick", conflicts_with = "pick")]
+        window_id: Option<WindowArg>,
+        /// Click the window instead of giving its ID (niri 25.05 or newer)
+        #[arg(long)]
+        pick: bool,
     },
     /// Toggle active window in stage
     #[command(alias = "t")]
@@ -97,48 +562,444 @@ enum StageAction {
     },
     /// Add all sticky windows to stage
     #[command(alias = "aa")]
-    AddAll,
+    AddAll {
+        /// Only list the windows that would be staged
+        #[arg(long)]
+        dry_run: bool,
+    },
     /// Remove all staged windows
     #[command(alias = "ra")]
-    RemoveAll,
+    RemoveAll {
+        /// Only list the windows that would be unstaged and where they would go
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
 
-pub async fn run_cli() -> Result<()> {
-    let cli = Cli::parse();
+pub async fn run_cli(cli: Cli) -> Result<()> {
+    let Some(command) = cli.command else {
+        anyhow::bail!("No command given");
+    };
+    let socket_path = &match cli.read_only {
+        true => crate::session::read_only_socket_path(),
+        false => crate::session::socket_path(),
+    };
+    match command {
+        Commands::Session { state_file } => {
+            if state_file {
+                let path = crate::export::export_path()
+                    .ok_or_else(|| anyhow::anyhow!("XDG_RUNTIME_DIR is not set"))?;
+                println!("{}", path.display());
+            } else {
+                print!("{}", crate::session::describe());
+            }
+            return Ok(());
+        }
+        Commands::Waybar => return crate::bar::run_waybar(socket_path).await,
+        Commands::Bar => {
+            let format = match cli.format {
+                None | Some(FormatArg::Plain) => crate::bar::StatusFormat::Text,
+                Some(FormatArg::Json) => crate::bar::StatusFormat::I3bar,
+                Some(FormatArg::Tsv) => anyhow::bail!("bar supports --format text or i3bar"),
+            };
+            return crate::bar::run_status(socket_path, format).await;
+        }
+        #[cfg(feature = "tui")]
+        Commands::Tui => {
+            // The UI lists windows itself, talk to the same compositor as the daemon
+            let configured = crate::config::load().ok().and_then(|config| config.backend);
+            crate::system_integration::set_backend(crate::system_integration::resolve_backend(
+                cli.backend.map(Into::into),
+                configured,
+            ));
+            return crate::tui::run_tui(socket_path).await;
+        }
+        _ => {}
+    }
 
-    let socket_path = "/tmp/niri_sticky_cli.sock";
-    let stream = UnixStream::connect(socket_path).await?;
-    let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
+    // Only list-type commands take --format, it is passed on in their protocol line
+    let lists = matches!(
+        &command,
+        Commands::Sticky {
+            action: StickyAction::List { .. }
+        } | Commands::Stage {
+            action: StageAction::List { .. },
+            ..
+        } | Commands::Slot {
+            action: SlotAction::List
+        } | Commands::Group {
+            action: GroupAction::List
+        } | Commands::Rule {
+            action: RuleAction::List
+        } | Commands::Status { short: false }
+            | Commands::Stats
+    );
+    let format_flag = match cli.format.map(crate::protocol::OutputFormat::from) {
+        None | Some(crate::protocol::OutputFormat::Plain) => String::new(),
+        Some(format) if lists => format!(" --format {}", format.as_str()),
+        Some(_) if matches!(command, Commands::Status { short: true }) => {
+            anyhow::bail!("--short can't be combined with --format")
+        }
+        Some(_) => anyhow::bail!("--format only applies to list commands, status, stats and bar"),
+    };
 
     // Generate command string based on subcommand
-    let cmd_str = match cli.command {
+    let cmd_str = match command {
         Commands::Sticky { action } => match action {
-            StickyAction::Add { window_id } => format!("add {window_id}\n"),
-            StickyAction::Remove { window_id } => format!("remove {window_id}\n"),
-            StickyAction::List => "list\n".to_string(),
+            StickyAction::Add {
+                window_id,
+                pick,
+                duration,
+            } => {
+                let window_id = target_window(socket_path, window_id, pick).await?;
+                match duration {
+                    Some(duration) => format!(
+                        "add {window_id} --for {}\n",
+                        crate::protocol::format_duration(duration)
+                    ),
+                    None => format!("add {window_id}\n"),
+                }
+            }
+            StickyAction::Remove {
+                window_id,
+                pick,
+                home,
+                stay,
+            } => {
+                let window_id = target_window(socket_path, window_id, pick).await?;
+                let flag = match (home, stay) {
+                    (true, _) => " --home",
+                    (_, true) => " --stay",
+                    _ => "",
+                };
+                format!("remove {window_id}{flag}\n")
+            }
+            StickyAction::List { detailed } => {
+                format!("list{}{format_flag}\n", detailed_flag(detailed))
+            }
+            StickyAction::Clear { dry_run } => format!("clear{}\n", dry_run_flag(dry_run)),
             StickyAction::ToggleActive => "toggle_active\n".to_string(),
             StickyAction::ToggleAppid { appid } => format!("toggle_appid {appid}\n"),
             StickyAction::ToggleTitle { title } => format!("toggle_title \"{title}\"\n"),
+            StickyAction::SetOutput { window_id, output } => {
+                let window_id = window_id.resolve(socket_path).await?;
+                format!("set_output {window_id} {output}\n")
+            }
+            StickyAction::MoveOutput { window_id, output } => {
+                let window_id = window_id.resolve(socket_path).await?;
+                format!("move_output {window_id} {output}\n")
+            }
+        },
+        Commands::Stage { wait, action } => {
+            let waits = wait
+                && !matches!(
+                    action,
+                    StageAction::List { .. }
+                        | StageAction::AddAll { dry_run: true }
+                        | StageAction::RemoveAll { dry_run: true }
+                );
+            let cmd = match action {
+                StageAction::List { detailed } => {
+                    format!("stage --list{}{format_flag}\n", detailed_flag(detailed))
+                }
+                StageAction::Add { window_id, pick } => {
+                    let window_id = target_window(socket_path, window_id, pick).await?;
+                    format!("stage {window_id}\n")
+                }
+                StageAction::Remove { window_id, pick } => {
+                    let window_id = target_window(socket_path, window_id, pick).await?;
+                    format!("unstage {window_id}\n")
+                }
+                StageAction::ToggleActive => "stage --active\n".to_string(),
+                StageAction::ToggleAppid { appid } => format!("stage --toggle-appid {appid}\n"),
+                StageAction::ToggleTitle { title } => format!("stage --toggle-title \"{title}\"\n"),
+                StageAction::AddAll { dry_run } => {
+                    format!("stage --all{}\n", dry_run_flag(dry_run))
+                }
+                StageAction::RemoveAll { dry_run } => {
+                    format!("unstage --all{}\n", dry_run_flag(dry_run))
+                }
+            };
+            if waits { format!("wait {cmd}") } else { cmd }
+        }
+        Commands::Slot { action } => match action {
+            SlotAction::Set { slot, window_id } => {
+                match resolve_window(socket_path, window_id).await? {
+                    Some(window_id) => format!("slot set {slot} {window_id}\n"),
+                    None => format!("slot set {slot}\n"),
+                }
+            }
+            SlotAction::Clear { slot } => format!("slot clear {slot}\n"),
+            SlotAction::List => format!("slot list{format_flag}\n"),
+        },
+        Commands::FocusSlot { slot } => format!("focus_slot {slot}\n"),
+        Commands::Group { action } => match action {
+            GroupAction::Create { name, window_ids } => {
+                let mut ids: Vec<String> = Vec::new();
+                for window in window_ids {
+                    ids.push(window.resolve(socket_path).await?.to_string());
+                }
+                format!("group create {} {}\n", name, ids.join(" "))
+            }
+            GroupAction::Remove { name } => format!("group remove {name}\n"),
+            GroupAction::List => format!("group list{format_flag}\n"),
+        },
+        Commands::Rule { action } => match action {
+            RuleAction::Add {
+                appid,
+                title,
+                title_regex,
+                floating,
+                fullscreen,
+                actions,
+                output,
+                priority,
+            } => {
+                use crate::rules::Action;
+                let mut actions: Vec<Action> = actions
+                    .into_iter()
+                    .map(|action| match action {
+                        RuleActionArg::Sticky => Action::Sticky,
+                        RuleActionArg::Stage => Action::Stage,
+                        RuleActionArg::Ignore => Action::Ignore,
+                        RuleActionArg::Float => Action::Float,
+                    })
+                    .collect();
+                if actions.is_empty() {
+                    actions.push(Action::Sticky);
+                }
+                let rule = crate::rules::Rule {
+                    app_id: appid,
+                    title,
+                    title_regex,
+                    floating,
+                    fullscreen,
+                    actions,
+                    output,
+                    priority,
+                };
+                format!("rule add {}\n", serde_json::to_string(&rule)?)
+            }
+            RuleAction::List => format!("rule list{format_flag}\n"),
+            RuleAction::Remove { id } => format!("rule remove {id}\n"),
+            RuleAction::Test { window_id } => {
+                let window_id = window_id.resolve(socket_path).await?;
+                format!("rule test {window_id}\n")
+            }
+        },
+        Commands::Pause { output } => match output {
+            Some(output) => format!("pause --output {output}\n"),
+            None => "pause\n".to_string(),
+        },
+        Commands::Resume { output } => match output {
+            Some(output) => format!("resume --output {output}\n"),
+            None => "resume\n".to_string(),
+        },
+        Commands::Status { short: true } => "status --short\n".to_string(),
+        Commands::Status { short: false } => format!("status{format_flag}\n"),
+        Commands::Stats => format!("stats{format_flag}\n"),
+        Commands::Undo => "undo\n".to_string(),
+        Commands::Attach { follower, leader } => {
+            let follower = follower.resolve(socket_path).await?;
+            let leader = leader.resolve(socket_path).await?;
+            format!("attach {follower} {leader}\n")
+        }
+        Commands::Detach { follower } => {
+            let follower = follower.resolve(socket_path).await?;
+            format!("detach {follower}\n")
+        }
+        Commands::Swap {
+            staged_id,
+            sticky_id,
+        } => {
+            let staged_id = staged_id.resolve(socket_path).await?;
+            let sticky_id = sticky_id.resolve(socket_path).await?;
+            format!("swap {staged_id} {sticky_id}\n")
+        }
+        Commands::Label { window_id, label } => {
+            let window_id = window_id.resolve(socket_path).await?;
+            format!("label {window_id} {label}\n")
+        }
+        Commands::Unlabel { window_id } => {
+            let window_id = window_id.resolve(socket_path).await?;
+            format!("unlabel {window_id}\n")
+        }
+        Commands::WaitFor {
+            appid,
+            title,
+            then,
+            timeout,
+        } => {
+            let action = match then {
+                WaitActionArg::Sticky => "sticky",
+                WaitActionArg::Stage => "stage",
+                WaitActionArg::Float => "float",
+            };
+            let selector = match (appid, title) {
+                (Some(appid), _) => format!("--appid {appid}"),
+                (None, Some(title)) => format!("--title {title}"),
+                (None, None) => unreachable!("clap requires a window"),
+            };
+            format!(
+                "wait-for {} {} {}\n",
+                action,
+                crate::protocol::format_duration(timeout),
+                selector
+            )
+        }
+        Commands::Set { replace, add, .. } => {
+            let mode = if replace {
+                "replace"
+            } else if add {
+                "add"
+            } else {
+                "remove"
+            };
+            let mut lines: Vec<String> = Vec::new();
+            for line in std::io::read_to_string(std::io::stdin())?.lines() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                // A single word that isn't an ID or a flag is a label
+                let line = match line.parse::<WindowArg>() {
+                    Ok(window) if !line.starts_with('-') && !line.contains(char::is_whitespace) => {
+                        window.resolve(socket_path).await?.to_string()
+                    }
+                    _ => line.to_string(),
+                };
+                lines.push(line);
+            }
+            format!("set {} {}\n", mode, serde_json::to_string(&lines)?)
+        }
+        Commands::Protect { window_id } => match resolve_window(socket_path, window_id).await? {
+            Some(window_id) => format!("protect {window_id}\n"),
+            None => "protect\n".to_string(),
+        },
+        Commands::Unprotect { window_id } => match resolve_window(socket_path, window_id).await? {
+            Some(window_id) => format!("unprotect {window_id}\n"),
+            None => "unprotect\n".to_string(),
         },
-        Commands::Stage { action } => match action {
-            StageAction::List => "stage --list\n".to_string(),
-            StageAction::Add { window_id } => format!("stage {window_id}\n"),
-            StageAction::Remove { window_id } => format!("unstage {window_id}\n"),
-            StageAction::ToggleActive => "stage --active\n".to_string(),
-            StageAction::ToggleAppid { appid } => format!("stage --toggle-appid {appid}\n"),
-            StageAction::ToggleTitle { title } => format!("stage --toggle-title \"{title}\"\n"),
-            StageAction::AddAll => "stage --all\n".to_string(),
-            StageAction::RemoveAll => "unstage --all\n".to_string(),
+        Commands::Bring {
+            window_id,
+            appid,
+            title,
+        } => match (resolve_window(socket_path, window_id).await?, appid, title) {
+            (Some(window_id), _, _) => format!("bring {window_id}\n"),
+            (None, Some(appid), _) => format!("bring --appid {appid}\n"),
+            (None, None, Some(title)) => format!("bring --title {title}\n"),
+            (None, None, None) => unreachable!("clap requires a window"),
+        },
+        Commands::TraceIpc { state } => match state {
+            Some(SwitchArg::On) => "trace_ipc on\n".to_string(),
+            Some(SwitchArg::Off) => "trace_ipc off\n".to_string(),
+            None => "trace_ipc\n".to_string(),
+        },
+        Commands::FocusMode { state } => match state {
+            Some(SwitchArg::On) => "focus_mode on\n".to_string(),
+            Some(SwitchArg::Off) => "focus_mode off\n".to_string(),
+            None => "focus_mode\n".to_string(),
+        },
+        Commands::Idle { state } => match state {
+            IdleArg::Enter => "idle enter\n".to_string(),
+            IdleArg::Leave => "idle leave\n".to_string(),
+        },
+        Commands::Waybar | Commands::Bar => unreachable!("bar modes are handled above"),
+        #[cfg(feature = "tui")]
+        Commands::Tui => unreachable!("tui is handled above"),
+        Commands::Session { .. } => unreachable!("session is handled above"),
+        Commands::Mode { mode, output } => match (mode, output) {
+            (Some(ModeArg::Global), _) => "mode global\n".to_string(),
+            (Some(ModeArg::Output), _) => "mode output\n".to_string(),
+            (Some(ModeArg::Primary), Some(output)) => format!("mode primary {output}\n"),
+            (Some(ModeArg::Primary), None) => "mode primary\n".to_string(),
+            (None, _) => "mode\n".to_string(),
         },
     };
 
-    writer.write_all(cmd_str.as_bytes()).await?;
-    writer.flush().await?;
-
-    let mut response = String::new();
-    reader.read_line(&mut response).await?;
+    let response = send_command(socket_path, &cmd_str).await?;
     print!("{response}");
 
     Ok(())
 }
+
+/// A window given on the command line by ID or by a label set with `nsticky label`
+#[derive(Clone, Debug)]
+enum WindowArg {
+    Id(u64),
+    Label(String),
+}
+
+impl std::str::FromStr for WindowArg {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.parse::<u64>() {
+            Ok(id) => WindowArg::Id(id),
+            Err(_) => WindowArg::Label(s.to_string()),
+        })
+    }
+}
+
+impl WindowArg {
+    /// Window ID, labels are looked up by the daemon
+    async fn resolve(self, socket_path: &str) -> Result<u64> {
+        match self {
+            WindowArg::Id(id) => Ok(id),
+            WindowArg::Label(label) => {
+                daemon_window_id(socket_path, &format!("resolve {label}\n")).await
+            }
+        }
+    }
+}
+
+/// Resolve an optional window argument, None stays None
+async fn resolve_window(socket_path: &str, window: Option<WindowArg>) -> Result<Option<u64>> {
+    match window {
+        Some(window) => Ok(Some(window.resolve(socket_path).await?)),
+        None => Ok(None),
+    }
+}
+
+/// Window given on the command line, or the one the user clicks with --pick
+async fn target_window(socket_path: &str, window_id: Option<WindowArg>, pick: bool) -> Result<u64> {
+    if let (Some(window), false) = (window_id, pick) {
+        return window.resolve(socket_path).await;
+    }
+    eprintln!("Click a window to pick it, Escape cancels");
+    daemon_window_id(socket_path, "pick\n").await
+}
+
+/// Send a request answered with a window ID
+async fn daemon_window_id(socket_path: &str, cmd: &str) -> Result<u64> {
+    let response = send_command(socket_path, cmd).await?;
+    let response = response.trim();
+    response
+        .parse::<u64>()
+        .map_err(|_| anyhow::anyhow!("{}", response.trim_start_matches("Error: ")))
+}
+
+/// Protocol suffix for list commands run with --detailed
+fn detailed_flag(detailed: bool) -> &'static str {
+    if detailed { " --detailed" } else { "" }
+}
+
+/// Protocol suffix for bulk commands run with --dry-run
+fn dry_run_flag(dry_run: bool) -> &'static str {
+    if dry_run { " --dry-run" } else { "" }
+}
+
+/// Send one command line to the daemon and return its response
+pub async fn send_command(socket_path: &str, cmd: &str) -> Result<String> {
+    let stream = UnixStream::connect(socket_path).await?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    writer.write_all(cmd.as_bytes()).await?;
+    writer.flush().await?;
+
+    // The daemon closes the connection after responding, responses may span several lines
+    let mut response = String::new();
+    reader.read_to_string(&mut response).await?;
+    Ok(response)
+}
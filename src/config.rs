@@ -0,0 +1,213 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::rules::{MatchMode, Rule};
+use crate::system_integration::Backend;
+
+/// Daemon configuration loaded from config.toml
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Compositor to talk to, detected from the environment when unset
+    pub backend: Option<Backend>,
+    /// Log filter such as "info" or "info,nsticky::sway=debug", RUST_LOG takes precedence
+    pub log_level: Option<String>,
+    pub bulk: BulkConfig,
+    pub protect: ProtectConfig,
+    pub offline: OfflineConfig,
+    pub follow_mode: FollowMode,
+    /// Only follow to a workspace after staying on it this many milliseconds, 0 follows at once
+    pub follow_delay_ms: u64,
+    /// Output followed in primary mode
+    pub primary_output: Option<String>,
+    /// `sticky remove` moves windows back to the workspace they were on when they became sticky
+    pub remove_returns_home: bool,
+    /// Sticky windows never follow into workspaces whose name starts with one of these, e.g. ["_", "priv:"]
+    pub private_workspace_prefixes: Vec<String>,
+    /// Expose the daemon on the session bus as org.nsticky.Daemon
+    pub dbus: bool,
+    /// Mirror state into $XDG_RUNTIME_DIR/nsticky/state-<session>.json
+    pub export_state: bool,
+    /// Also listen on a socket that only answers list, status and subscribe requests
+    pub read_only_socket: bool,
+    /// Count sticky, stage and follow events per app and day for `nsticky stats`
+    pub usage_stats: bool,
+    /// Append every state-changing operation to this file as JSON lines
+    pub audit_log: Option<PathBuf>,
+    /// Pause sticky-follow while the logind session is locked
+    pub pause_on_lock: bool,
+    pub notifications: NotificationsConfig,
+    /// Windows made sticky when they open, runtime rules are merged in from the state file
+    pub rules: Vec<Rule>,
+    /// Whether only the first ("first") or every ("all") matching rule applies
+    pub rule_match: MatchMode,
+    /// Extra CLI commands, e.g. pin = "sticky toggle-active"
+    pub aliases: BTreeMap<String, String>,
+    /// Address to serve Prometheus metrics on, e.g. "127.0.0.1:9469"
+    #[cfg(feature = "metrics")]
+    pub metrics_listen: Option<String>,
+    /// Address to serve the HTTP control API on, e.g. "127.0.0.1:9470"
+    #[cfg(feature = "http")]
+    pub http_listen: Option<String>,
+    /// Origins allowed to call the HTTP API besides localhost pages, e.g. "moz-extension://<uuid>"
+    #[cfg(feature = "http")]
+    pub http_allowed_origins: Vec<String>,
+    #[cfg(feature = "osd")]
+    pub osd: OsdConfig,
+    /// Stage all sticky windows after this many minutes idle (ext-idle-notify)
+    #[cfg(feature = "idle")]
+    pub idle_stage_after_min: Option<u64>,
+}
+
+/// How sticky windows follow workspace switches
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FollowMode {
+    /// Follow workspace switches on every output
+    Global,
+    /// Follow workspace switches only on the window's own output
+    #[default]
+    Output,
+    /// Follow workspace switches only on the primary output
+    Primary,
+}
+
+impl FollowMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FollowMode::Global => "global",
+            FollowMode::Output => "output",
+            FollowMode::Primary => "primary",
+        }
+    }
+}
+
+/// Pacing of compositor actions during bulk operations (stage --all, unstage --all)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BulkConfig {
+    /// Delay in milliseconds between batches of moves
+    pub delay_ms: u64,
+    /// Maximum number of moves in flight at once
+    pub max_concurrency: usize,
+    /// Give focus back to the previously focused window if moving windows took it away
+    pub restore_focus: bool,
+}
+
+impl Default for BulkConfig {
+    fn default() -> Self {
+        Self {
+            delay_ms: 0,
+            max_concurrency: 1,
+            restore_focus: true,
+        }
+    }
+}
+
+/// Holding of state-changing requests while the compositor socket is unreachable, e.g. during a reload
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OfflineConfig {
+    /// Give up on a held request after this many seconds, 0 fails requests at once
+    pub queue_secs: u64,
+    /// Requests held at once, further ones fail at once
+    pub max_queued: usize,
+}
+
+impl Default for OfflineConfig {
+    fn default() -> Self {
+        Self {
+            queue_secs: 10,
+            max_queued: 32,
+        }
+    }
+}
+
+/// Sticky windows left in place on workspace switches, on top of those marked with `protect`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ProtectConfig {
+    /// Leave fullscreen windows alone, only works when the compositor reports fullscreen state
+    pub fullscreen: bool,
+    /// App IDs whose windows never follow, e.g. ["mpv"]
+    pub app_ids: Vec<String>,
+}
+
+impl Default for ProtectConfig {
+    fn default() -> Self {
+        Self {
+            fullscreen: true,
+            app_ids: Vec::new(),
+        }
+    }
+}
+
+/// Desktop notifications sent on sticky/stage changes
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    pub enable: bool,
+    /// Notify when windows become sticky or stop being sticky
+    pub sticky: bool,
+    /// Notify when windows are staged or unstaged
+    pub stage: bool,
+    /// Notification timeout in milliseconds, -1 leaves it to the notification server
+    pub timeout_ms: i32,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            sticky: true,
+            stage: true,
+            timeout_ms: 3000,
+        }
+    }
+}
+
+/// Layer-shell overlay flashed on sticky/stage changes
+#[cfg(feature = "osd")]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OsdConfig {
+    pub enable: bool,
+    /// How long the overlay stays up in milliseconds
+    pub timeout_ms: u64,
+}
+
+#[cfg(feature = "osd")]
+impl Default for OsdConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            timeout_ms: 800,
+        }
+    }
+}
+
+/// Get config file path ($XDG_CONFIG_HOME/nsticky/config.toml)
+pub fn config_path() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("nsticky").join("config.toml"))
+}
+
+/// Load config from disk, falling back to defaults when no file exists
+pub fn load() -> Result<Config> {
+    let Some(path) = config_path() else {
+        return Ok(Config::default());
+    };
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+
+    let content = std::fs::read_to_string(&path)?;
+    let config = toml::from_str(&content)
+        .map_err(|e| anyhow::anyhow!("Invalid config {}: {}", path.display(), e))?;
+    Ok(config)
+}
@@ -1,56 +1,288 @@
 use anyhow::Result;
-use serde_json::Value;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::future;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::{
     io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-    net::{UnixListener, UnixStream},
-    sync::Mutex,
+    net::{UnixListener, UnixStream, unix::OwnedWriteHalf},
+    signal::unix::{SignalKind, signal},
+    sync::{Mutex, broadcast, oneshot},
 };
 
-use crate::{business::BusinessLogic, protocol};
+use crate::{
+    business::{BusinessLogic, HomeReturn},
+    config::{Config, FollowMode},
+    protocol,
+    rules::{Action, RuleSet},
+    system_integration::{self, CompositorEvent, WorkspaceInfo},
+};
+
+/// How long `--wait` waits for windows to show up on their new workspace
+const WAIT_TIMEOUT: Duration = Duration::from_secs(5);
 
-pub async fn start(sticky_windows: Arc<Mutex<HashSet<u64>>>) -> Result<()> {
+pub async fn start(sticky_windows: Arc<Mutex<HashSet<u64>>>, config: Config) -> Result<()> {
+    let config = Arc::new(config);
     let staged_set = Arc::new(Mutex::new(HashSet::new()));
-    let business_logic = BusinessLogic::new(sticky_windows, staged_set);
+    let rules = RuleSet::load(config.rules.clone(), config.rule_match).unwrap_or_else(|e| {
+        tracing::warn!("Failed to load rules, runtime rules won't be saved: {e:?}");
+        RuleSet::from_config(config.rules.clone(), config.rule_match)
+    });
+    let business_logic = BusinessLogic::new(sticky_windows, staged_set, rules, config.clone());
+    if let Some(path) = &config.audit_log
+        && let Err(e) = crate::audit::open(path)
+    {
+        tracing::warn!("Failed to open audit log {}: {e:?}", path.display());
+    }
 
-    let cli_business_logic = business_logic.clone();
+    crate::offline::configure(config.offline.clone());
+    if config.protect.fullscreen && !system_integration::backend().reports_fullscreen() {
+        tracing::warn!(
+            "protect.fullscreen has no effect, the {} backend doesn't report fullscreen windows",
+            system_integration::backend().as_str()
+        );
+    }
+
+    // Bind the CLI socket before anything talks to niri, so early clients
+    // are accepted even while the compositor is still slow to answer
+    // Each compositor session gets its own socket, see session::socket_path
+    spawn_cli_server(
+        "cli_server",
+        crate::session::socket_path(),
+        false,
+        business_logic.clone(),
+    )?;
+    if config.read_only_socket {
+        spawn_cli_server(
+            "cli_server_ro",
+            crate::session::read_only_socket_path(),
+            true,
+            business_logic.clone(),
+        )?;
+    }
+
+    let reconcile_business_logic = business_logic.clone();
     tokio::spawn(async move {
-        if let Err(_e) = run_cli_server(cli_business_logic).await {
-            eprintln!("CLI server error: {_e:?}");
+        if let Err(_e) = reconcile_business_logic.reconcile_state().await {
+            tracing::error!("Initial reconciliation error: {_e:?}");
         }
     });
 
+    let (watcher_ready_tx, watcher_ready_rx) = oneshot::channel();
+    let mut watcher_ready_tx = Some(watcher_ready_tx);
     let watcher_business_logic = business_logic.clone();
+    let dwell = Duration::from_millis(config.follow_delay_ms);
+    crate::supervisor::spawn("watcher", move || {
+        let ready = watcher_ready_tx.take();
+        let business_logic = watcher_business_logic.clone();
+        async move {
+            let result = run_watcher(business_logic, dwell, ready).await;
+            crate::metrics::set_event_stream_connected(false);
+            result
+        }
+    });
+
+    // The CLI socket is already bound, report readiness once the event stream is up
     tokio::spawn(async move {
-        if let Err(_e) = run_watcher(watcher_business_logic).await {
-            eprintln!("Watcher error: {_e:?}");
+        if watcher_ready_rx.await.is_ok()
+            && let Err(_e) = crate::systemd::notify("READY=1")
+        {
+            tracing::warn!("Failed to notify service manager: {_e:?}");
         }
     });
 
-    println!("nsticky daemon started.");
-    future::pending::<()>().await;
+    if config.dbus {
+        let dbus_business_logic = business_logic.clone();
+        crate::supervisor::spawn("dbus", move || {
+            crate::dbus::run_service(dbus_business_logic.clone())
+        });
+    }
+
+    if config.notifications.enable {
+        let notifier_business_logic = business_logic.clone();
+        let notifications_config = config.notifications.clone();
+        crate::supervisor::spawn("notifier", move || {
+            crate::notifications::run_notifier(
+                notifier_business_logic.clone(),
+                notifications_config.clone(),
+            )
+        });
+    }
+
+    if config.pause_on_lock {
+        let lock_business_logic = business_logic.clone();
+        crate::supervisor::spawn("session_lock", move || {
+            crate::logind::run_lock_watcher(lock_business_logic.clone())
+        });
+    }
+
+    if config.usage_stats {
+        let stats_business_logic = business_logic.clone();
+        crate::supervisor::spawn("stats", move || {
+            crate::stats::run_stats(stats_business_logic.clone())
+        });
+    }
+
+    if config.export_state {
+        let export_business_logic = business_logic.clone();
+        crate::supervisor::spawn("state_export", move || {
+            crate::export::run_state_export(export_business_logic.clone())
+        });
+    }
+
+    #[cfg(feature = "osd")]
+    if config.osd.enable {
+        let osd_business_logic = business_logic.clone();
+        let osd_config = config.osd.clone();
+        crate::supervisor::spawn("osd", move || {
+            crate::osd::run_osd(osd_business_logic.clone(), osd_config.clone())
+        });
+    }
+
+    #[cfg(feature = "idle")]
+    if let Some(minutes) = config.idle_stage_after_min {
+        let idle_business_logic = business_logic.clone();
+        crate::supervisor::spawn("idle", move || {
+            crate::idle::run_idle_watcher(idle_business_logic.clone(), minutes)
+        });
+    }
+
+    #[cfg(feature = "metrics")]
+    if let Some(addr) = config.metrics_listen.clone() {
+        let metrics_business_logic = business_logic.clone();
+        crate::supervisor::spawn("metrics", move || {
+            let addr = addr.clone();
+            let business_logic = metrics_business_logic.clone();
+            async move { crate::metrics::run_server(&addr, business_logic).await }
+        });
+    }
+
+    #[cfg(feature = "http")]
+    if let Some(addr) = config.http_listen.clone() {
+        let api_business_logic = business_logic.clone();
+        let allowed_origins = config.http_allowed_origins.clone();
+        crate::supervisor::spawn("http_api", move || {
+            let addr = addr.clone();
+            let allowed_origins = allowed_origins.clone();
+            let business_logic = api_business_logic.clone();
+            async move { crate::api::run_server(&addr, allowed_origins, business_logic).await }
+        });
+    }
+
+    tracing::info!(
+        "nsticky daemon started ({} backend).",
+        system_integration::backend().as_str()
+    );
+    let watchdog = async {
+        match crate::systemd::watchdog_interval() {
+            Some(interval) => run_watchdog(business_logic, interval).await,
+            None => future::pending::<()>().await,
+        }
+    };
+    tokio::select! {
+        _ = watchdog => {}
+        result = shutdown_signal() => {
+            result?;
+            tracing::info!("Shutting down");
+        }
+    }
+    crate::stats::flush();
     Ok(())
 }
 
-async fn run_cli_server(business_logic: BusinessLogic) -> Result<()> {
-    let cli_socket_path = "/tmp/niri_sticky_cli.sock";
-    let _ = std::fs::remove_file(cli_socket_path);
-    let listener = UnixListener::bind(cli_socket_path)?;
+/// Wait for SIGTERM (systemctl stop, logout) or SIGINT (Ctrl-C)
+async fn shutdown_signal() -> Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        _ = terminate.recv() => {}
+        result = tokio::signal::ctrl_c() => result?,
+    }
+    Ok(())
+}
 
+/// Ping the systemd watchdog while the daemon state is still responsive
+async fn run_watchdog(business_logic: BusinessLogic, interval: std::time::Duration) {
+    let period = interval / 2;
+    loop {
+        tokio::time::sleep(period).await;
+        // A deadlocked state mutex means a hung daemon, stop pinging and let systemd restart us
+        if tokio::time::timeout(period, business_logic.snapshot())
+            .await
+            .is_err()
+        {
+            tracing::warn!("Daemon state unresponsive, skipping watchdog ping");
+            continue;
+        }
+        if let Err(_e) = crate::systemd::notify("WATCHDOG=1") {
+            tracing::warn!("Failed to ping watchdog: {_e:?}");
+        }
+    }
+}
+
+/// Bind a CLI socket and serve it under the supervisor
+/// The first run uses the socket bound here, restarts bind it again
+fn spawn_cli_server(
+    name: &'static str,
+    path: String,
+    read_only: bool,
+    business_logic: BusinessLogic,
+) -> Result<()> {
+    tracing::info!("Listening on {}", path);
+    let _ = std::fs::remove_file(&path);
+    let mut listener = Some(UnixListener::bind(&path)?);
+    crate::supervisor::spawn(name, move || {
+        let listener = listener.take();
+        let business_logic = business_logic.clone();
+        let path = path.clone();
+        async move {
+            let listener = match listener {
+                Some(listener) => listener,
+                None => {
+                    let _ = std::fs::remove_file(&path);
+                    UnixListener::bind(&path)?
+                }
+            };
+            run_cli_server(listener, read_only, business_logic).await
+        }
+    });
+    Ok(())
+}
+
+/// Serve CLI connections, a read-only server refuses requests that change anything
+async fn run_cli_server(
+    listener: UnixListener,
+    read_only: bool,
+    business_logic: BusinessLogic,
+) -> Result<()> {
     loop {
         let (stream, _) = listener.accept().await?;
         let business_logic_clone = business_logic.clone();
         tokio::spawn(async move {
-            if let Err(_e) = handle_cli_connection(stream, business_logic_clone).await {
-                eprintln!("CLI connection error: {_e:?}");
+            if let Err(_e) = handle_cli_connection(stream, read_only, business_logic_clone).await {
+                tracing::warn!("CLI connection error: {_e:?}");
             }
         });
     }
 }
 
-async fn handle_cli_connection(stream: UnixStream, business_logic: BusinessLogic) -> Result<()> {
+/// Describe the peer of a CLI connection for the audit log, e.g. "cli pid=1234 (waybar)"
+fn cli_origin(stream: &UnixStream) -> String {
+    let Some(pid) = stream.peer_cred().ok().and_then(|cred| cred.pid()) else {
+        return "cli".to_string();
+    };
+    match std::fs::read_to_string(format!("/proc/{}/comm", pid)) {
+        Ok(comm) => format!("cli pid={} ({})", pid, comm.trim()),
+        Err(_) => format!("cli pid={}", pid),
+    }
+}
+
+#[tracing::instrument(name = "cli_connection", skip_all)]
+async fn handle_cli_connection(
+    stream: UnixStream,
+    read_only: bool,
+    business_logic: BusinessLogic,
+) -> Result<()> {
+    let origin = cli_origin(&stream);
     let (reader, mut writer) = stream.into_split();
     let mut reader = BufReader::new(reader);
     let mut line = String::new();
@@ -60,6 +292,7 @@ async fn handle_cli_connection(stream: UnixStream, business_logic: BusinessLogic
         return Ok(());
     }
     let line = line.trim();
+    tracing::debug!(request = line, "CLI request");
 
     // Parse request
     let request = match protocol::parse_request(line) {
@@ -72,36 +305,150 @@ async fn handle_cli_connection(stream: UnixStream, business_logic: BusinessLogic
         }
     };
 
-    // Process request and generate response
-    let response = match request {
-        protocol::Request::Add { window_id } => {
-            match business_logic.add_sticky_window(window_id).await {
-                Ok(is_new) => {
-                    if is_new {
-                        protocol::Response::Success("Added\n".to_string())
-                    } else {
-                        protocol::Response::Success("Already in sticky list\n".to_string())
-                    }
+    if read_only && !request.is_observing() {
+        let response = protocol::Response::Error(
+            "This socket is read-only, use the control socket to change state".to_string(),
+        );
+        writer
+            .write_all(protocol::format_response(response).as_bytes())
+            .await?;
+        return Ok(());
+    }
+
+    // Subscriptions keep the connection open and stream state snapshots
+    if let protocol::Request::Subscribe = request {
+        return run_subscription(writer, business_logic).await;
+    }
+
+    // A wait-for gives up once the CLI goes away, e.g. on Ctrl-C
+    let response = if let protocol::Request::WaitFor { .. } = request {
+        let mut rest = String::new();
+        tokio::select! {
+            response = process_request(request, &origin, &business_logic) => response,
+            _ = reader.read_line(&mut rest) => return Ok(()),
+        }
+    } else {
+        process_request(request, &origin, &business_logic).await
+    };
+
+    // Send response
+    let response_str = protocol::format_response(response);
+    writer.write_all(response_str.as_bytes()).await?;
+
+    Ok(())
+}
+
+/// Process a request and wake up subscribers, shared by all control interfaces
+/// `origin` tells the audit log who sent it
+#[tracing::instrument(level = "debug", skip(business_logic))]
+pub async fn process_request(
+    request: protocol::Request,
+    origin: &str,
+    business_logic: &BusinessLogic,
+) -> protocol::Response {
+    let audited = (!request.is_read_only()).then(|| format!("{:?}", request));
+    // During a compositor reload, hold state-changing requests instead of failing them
+    if !request.is_read_only()
+        && let Err(e) = crate::offline::wait_for_compositor().await
+    {
+        if let Some(operation) = audited {
+            crate::audit::record(origin, &operation, false, &e);
+        }
+        return protocol::Response::Error(e);
+    }
+    let checkpoint = match request.is_undoable() {
+        true => Some(
+            business_logic
+                .undo_checkpoint(request.moves_windows())
+                .await,
+        ),
+        false => None,
+    };
+    let response = dispatch_request(request, business_logic).await;
+    // Failed operations may still have changed some windows, push_undo skips no-ops
+    if let Some(checkpoint) = checkpoint {
+        business_logic.push_undo(checkpoint).await;
+    }
+    // Removing, staging away or toggling off a window added with `--for` cancels its timer
+    business_logic.prune_expiries().await;
+    if let Some(operation) = audited {
+        let (ok, result) = match &response {
+            protocol::Response::Success(msg) | protocol::Response::Data(msg) => (true, msg),
+            protocol::Response::Error(msg) => (false, msg),
+        };
+        crate::audit::record(origin, &operation, ok, result);
+    }
+    business_logic.notify_state_changed();
+    response
+}
+
+/// Run a request against the business logic and build its response
+async fn dispatch_request(
+    request: protocol::Request,
+    business_logic: &BusinessLogic,
+) -> protocol::Response {
+    match request {
+        protocol::Request::Add {
+            window_id,
+            duration,
+        } => match business_logic.add_sticky_window(window_id, duration).await {
+            Ok(is_new) => {
+                let message = if is_new {
+                    "Added"
+                } else {
+                    "Already in sticky list"
+                };
+                match duration {
+                    Some(duration) => protocol::Response::Success(format!(
+                        "{}, removing it in {}\n",
+                        message,
+                        protocol::format_duration(duration)
+                    )),
+                    None => protocol::Response::Success(format!("{}\n", message)),
                 }
-                Err(e) => protocol::Response::Error(e.to_string()),
             }
-        }
-        protocol::Request::Remove { window_id } => {
-            match business_logic.remove_sticky_window(window_id).await {
-                Ok(was_present) => {
-                    if was_present {
-                        protocol::Response::Success("Removed\n".to_string())
-                    } else {
-                        protocol::Response::Success("Not in sticky list\n".to_string())
-                    }
+            Err(e) => protocol::Response::Error(e.to_string()),
+        },
+        protocol::Request::Remove { window_id, home } => {
+            match business_logic.remove_sticky_window(window_id, home).await {
+                Ok((false, _)) => protocol::Response::Success("Not in sticky list\n".to_string()),
+                Ok((true, None)) => protocol::Response::Success("Removed\n".to_string()),
+                Ok((true, Some(HomeReturn::Moved(workspace)))) => {
+                    protocol::Response::Success(format!(
+                        "Removed, moved back to {}\n",
+                        protocol::workspace_label(&workspace)
+                    ))
+                }
+                Ok((true, Some(HomeReturn::Gone))) => protocol::Response::Success(
+                    "Removed, its home workspace no longer exists\n".to_string(),
+                ),
+                Ok((true, Some(HomeReturn::Unknown))) => {
+                    protocol::Response::Success("Removed, no home workspace recorded\n".to_string())
                 }
                 Err(e) => protocol::Response::Error(e.to_string()),
             }
         }
-        protocol::Request::List => match business_logic.list_sticky_windows().await {
-            Ok(windows) => protocol::Response::Data(format!("{:?}\n", windows)),
+        protocol::Request::List {
+            detailed: false,
+            format: protocol::OutputFormat::Plain,
+        } => match business_logic.list_sticky_windows().await {
+            Ok(windows) => protocol::Response::Data(protocol::format_window_ids(
+                &windows,
+                &business_logic.window_labels().await,
+            )),
             Err(e) => protocol::Response::Error(e.to_string()),
         },
+        // JSON and TSV always carry the details, --detailed also includes staged windows
+        protocol::Request::List { detailed, format } => {
+            match business_logic.list_tracked_windows(true, detailed).await {
+                Ok(windows) => protocol::Response::Data(protocol::render_records(
+                    format,
+                    || protocol::format_tracked_windows(&windows),
+                    || protocol::tracked_window_records(&windows),
+                )),
+                Err(e) => protocol::Response::Error(e.to_string()),
+            }
+        }
         protocol::Request::ToggleActive => match business_logic.toggle_active_window().await {
             Ok(was_added) => {
                 if was_added {
@@ -142,7 +489,9 @@ async fn handle_cli_connection(stream: UnixStream, business_logic: BusinessLogic
                 let active_id = match crate::system_integration::get_active_window_id().await {
                     Ok(id) => id,
                     Err(_) => {
-                        return Ok(writer.write_all(b"Failed to get active window\n").await?);
+                        return protocol::Response::Error(
+                            "Failed to get active window".to_string(),
+                        );
                     }
                 };
 
@@ -152,9 +501,9 @@ async fn handle_cli_connection(stream: UnixStream, business_logic: BusinessLogic
                         match crate::system_integration::get_active_workspace_id().await {
                             Ok(id) => id,
                             Err(_) => {
-                                return Ok(writer
-                                    .write_all(b"Failed to get active workspace ID\n")
-                                    .await?);
+                                return protocol::Response::Error(
+                                    "Failed to get active workspace ID".to_string(),
+                                );
                             }
                         };
                     match business_logic.unstage_active_window(current_ws_id).await {
@@ -174,9 +523,9 @@ async fn handle_cli_connection(stream: UnixStream, business_logic: BusinessLogic
                 {
                     Ok(id) => id,
                     Err(_) => {
-                        return Ok(writer
-                            .write_all(b"Failed to get active workspace ID\n")
-                            .await?);
+                        return protocol::Response::Error(
+                            "Failed to get active workspace ID".to_string(),
+                        );
                     }
                 };
                 match business_logic
@@ -193,9 +542,9 @@ async fn handle_cli_connection(stream: UnixStream, business_logic: BusinessLogic
                 {
                     Ok(id) => id,
                     Err(_) => {
-                        return Ok(writer
-                            .write_all(b"Failed to get active workspace ID\n")
-                            .await?);
+                        return protocol::Response::Error(
+                            "Failed to get active workspace ID".to_string(),
+                        );
                     }
                 };
                 match business_logic
@@ -207,14 +556,37 @@ async fn handle_cli_connection(stream: UnixStream, business_logic: BusinessLogic
                     }
                     Err(e) => protocol::Response::Error(e.to_string()),
                 }
+            } else if stage_args.all && stage_args.dry_run {
+                match business_logic.list_tracked_windows(true, false).await {
+                    Ok(windows) => protocol::Response::Data(protocol::format_dry_run(
+                        "stage",
+                        &windows,
+                        Some("workspace \"stage\""),
+                    )),
+                    Err(e) => protocol::Response::Error(e.to_string()),
+                }
             } else if stage_args.all {
                 match business_logic.stage_all_windows().await {
                     Ok(count) => protocol::Response::Success(format!("Staged {} windows\n", count)),
                     Err(e) => protocol::Response::Error(e.to_string()),
                 }
+            } else if stage_args.list
+                && (stage_args.detailed || stage_args.format != protocol::OutputFormat::Plain)
+            {
+                match business_logic.list_tracked_windows(false, true).await {
+                    Ok(windows) => protocol::Response::Data(protocol::render_records(
+                        stage_args.format,
+                        || protocol::format_tracked_windows(&windows),
+                        || protocol::tracked_window_records(&windows),
+                    )),
+                    Err(e) => protocol::Response::Error(e.to_string()),
+                }
             } else if stage_args.list {
                 match business_logic.list_staged_windows().await {
-                    Ok(windows) => protocol::Response::Data(format!("{:?}\n", windows)),
+                    Ok(windows) => protocol::Response::Data(protocol::format_window_ids(
+                        &windows,
+                        &business_logic.window_labels().await,
+                    )),
                     Err(e) => protocol::Response::Error(e.to_string()),
                 }
             } else if let Some(window_id) = stage_args.window_id {
@@ -230,13 +602,30 @@ async fn handle_cli_connection(stream: UnixStream, business_logic: BusinessLogic
             let current_ws_id = match crate::system_integration::get_active_workspace_id().await {
                 Ok(id) => id,
                 Err(_) => {
-                    return Ok(writer
-                        .write_all(b"Failed to get active workspace ID\n")
-                        .await?);
+                    return protocol::Response::Error(
+                        "Failed to get active workspace ID".to_string(),
+                    );
                 }
             };
 
-            if unstage_args.all {
+            if unstage_args.all && unstage_args.dry_run {
+                let destination = match crate::system_integration::get_workspaces().await {
+                    Ok(workspaces) => workspaces
+                        .iter()
+                        .find(|ws| ws.id == current_ws_id)
+                        .map(protocol::workspace_label)
+                        .unwrap_or_else(|| format!("workspace id {}", current_ws_id)),
+                    Err(e) => return protocol::Response::Error(e.to_string()),
+                };
+                match business_logic.list_tracked_windows(false, true).await {
+                    Ok(windows) => protocol::Response::Data(protocol::format_dry_run(
+                        "unstage",
+                        &windows,
+                        Some(&destination),
+                    )),
+                    Err(e) => protocol::Response::Error(e.to_string()),
+                }
+            } else if unstage_args.all {
                 match business_logic.unstage_all_windows(current_ws_id).await {
                     Ok(count) => {
                         protocol::Response::Success(format!("Unstaged {} windows\n", count))
@@ -260,37 +649,589 @@ async fn handle_cli_connection(stream: UnixStream, business_logic: BusinessLogic
                 protocol::Response::Error("Invalid unstage command".to_string())
             }
         }
-    };
+        protocol::Request::Clear { dry_run: true } => {
+            match business_logic.list_tracked_windows(true, false).await {
+                Ok(windows) => protocol::Response::Data(protocol::format_dry_run(
+                    "remove from sticky list",
+                    &windows,
+                    None,
+                )),
+                Err(e) => protocol::Response::Error(e.to_string()),
+            }
+        }
+        protocol::Request::Clear { dry_run: false } => {
+            let count = business_logic.clear_sticky_windows().await;
+            protocol::Response::Success(format!("Removed {} windows from sticky list\n", count))
+        }
+        protocol::Request::SetOutput { window_id, output } => {
+            match business_logic.set_output_affinity(window_id, &output).await {
+                Ok(()) => protocol::Response::Success(format!("Window now follows {}\n", output)),
+                Err(e) => protocol::Response::Error(e.to_string()),
+            }
+        }
+        protocol::Request::MoveOutput { window_id, output } => {
+            match business_logic.move_to_output(window_id, &output).await {
+                Ok(()) => protocol::Response::Success(format!("Moved window to {}\n", output)),
+                Err(e) => protocol::Response::Error(e.to_string()),
+            }
+        }
+        protocol::Request::Mode {
+            mode,
+            primary_output,
+        } => match mode {
+            Some(mode) => match business_logic
+                .set_follow_mode(mode, primary_output.as_deref())
+                .await
+            {
+                Ok(()) => {
+                    protocol::Response::Success(format!("Follow mode set to {}\n", mode.as_str()))
+                }
+                Err(e) => protocol::Response::Error(e.to_string()),
+            },
+            None => {
+                let mode = business_logic.get_follow_mode().await;
+                match business_logic.get_primary_output().await {
+                    Some(primary) if mode == FollowMode::Primary => {
+                        protocol::Response::Data(format!("{} ({})\n", mode.as_str(), primary))
+                    }
+                    _ => protocol::Response::Data(format!("{}\n", mode.as_str())),
+                }
+            }
+        },
+        protocol::Request::TraceIpc {
+            enabled: Some(enabled),
+        } => {
+            crate::logging::set_trace_ipc(enabled);
+            let state = if enabled { "enabled" } else { "disabled" };
+            protocol::Response::Success(format!("IPC tracing {}\n", state))
+        }
+        protocol::Request::TraceIpc { enabled: None } => {
+            let state = if crate::logging::trace_ipc_enabled() {
+                "on"
+            } else {
+                "off"
+            };
+            protocol::Response::Data(format!("{}\n", state))
+        }
+        protocol::Request::FocusMode {
+            enabled: Some(true),
+        } => match business_logic.focus_mode_on().await {
+            Ok(Some(count)) => {
+                protocol::Response::Success(format!("Focus mode on, staged {} windows\n", count))
+            }
+            Ok(None) => protocol::Response::Success("Focus mode already on\n".to_string()),
+            Err(e) => protocol::Response::Error(e.to_string()),
+        },
+        protocol::Request::FocusMode {
+            enabled: Some(false),
+        } => match business_logic.focus_mode_off().await {
+            Ok(Some(count)) => {
+                protocol::Response::Success(format!("Focus mode off, restored {} windows\n", count))
+            }
+            Ok(None) => protocol::Response::Success("Focus mode already off\n".to_string()),
+            Err(e) => protocol::Response::Error(e.to_string()),
+        },
+        protocol::Request::FocusMode { enabled: None } => {
+            let state = if business_logic.focus_mode_enabled().await {
+                "on"
+            } else {
+                "off"
+            };
+            protocol::Response::Data(format!("{}\n", state))
+        }
+        protocol::Request::Attach { follower, leader } => {
+            match business_logic.attach_window(follower, leader).await {
+                Ok(true) => protocol::Response::Success(format!(
+                    "Window {} now follows window {}\n",
+                    follower, leader
+                )),
+                Ok(false) => protocol::Response::Success(format!(
+                    "Window {} already follows window {}\n",
+                    follower, leader
+                )),
+                Err(e) => protocol::Response::Error(e.to_string()),
+            }
+        }
+        protocol::Request::Detach { follower } => {
+            if business_logic.detach_window(follower).await {
+                protocol::Response::Success("Detached\n".to_string())
+            } else {
+                protocol::Response::Success("Not attached\n".to_string())
+            }
+        }
+        protocol::Request::Swap {
+            staged_id,
+            sticky_id,
+        } => {
+            let current_ws_id = match crate::system_integration::get_active_workspace_id().await {
+                Ok(id) => id,
+                Err(_) => {
+                    return protocol::Response::Error(
+                        "Failed to get active workspace ID".to_string(),
+                    );
+                }
+            };
+            match business_logic
+                .swap_windows(staged_id, sticky_id, current_ws_id)
+                .await
+            {
+                Ok(()) => protocol::Response::Success(format!(
+                    "Unstaged window {}, staged window {}\n",
+                    staged_id, sticky_id
+                )),
+                Err(e) => protocol::Response::Error(e.to_string()),
+            }
+        }
+        protocol::Request::Label {
+            window_id,
+            label: Some(label),
+        } => match business_logic.set_label(window_id, &label).await {
+            Ok(()) => {
+                protocol::Response::Success(format!("Window {} is labelled {}\n", window_id, label))
+            }
+            Err(e) => protocol::Response::Error(e.to_string()),
+        },
+        protocol::Request::Label {
+            window_id,
+            label: None,
+        } => match business_logic.remove_label(window_id).await {
+            Some(label) => protocol::Response::Success(format!(
+                "Removed label {} from window {}\n",
+                label, window_id
+            )),
+            None => protocol::Response::Success(format!("Window {} has no label\n", window_id)),
+        },
+        protocol::Request::Resolve { label } => match business_logic.resolve_label(&label).await {
+            Some(window_id) => protocol::Response::Data(format!("{}\n", window_id)),
+            None => protocol::Response::Error(format!("No window is labelled {}", label)),
+        },
+        protocol::Request::WaitFor {
+            selector,
+            action,
+            timeout,
+        } => match business_logic.wait_for_window(selector, timeout).await {
+            Ok(Some(window)) => match business_logic.apply_action(&window, action).await {
+                Ok(()) => protocol::Response::Success(format!(
+                    "Window {} opened, applied {}\n",
+                    window.id,
+                    action.as_str()
+                )),
+                Err(e) => protocol::Response::Error(e.to_string()),
+            },
+            Ok(None) => protocol::Response::Error(format!(
+                "No matching window opened within {}",
+                protocol::format_duration(timeout)
+            )),
+            Err(e) => protocol::Response::Error(e.to_string()),
+        },
+        protocol::Request::Set { mode, selectors } => {
+            match business_logic.set_sticky_windows(mode, &selectors).await {
+                Ok((added, removed)) => protocol::Response::Success(format!(
+                    "Sticky list updated: added {}, removed {}\n",
+                    added, removed
+                )),
+                Err(e) => protocol::Response::Error(e.to_string()),
+            }
+        }
+        protocol::Request::Protect { window_id, protect } => {
+            match business_logic.protect_window(window_id, protect).await {
+                Ok((id, true)) if protect => {
+                    protocol::Response::Success(format!("Window {} is protected\n", id))
+                }
+                Ok((id, false)) if protect => {
+                    protocol::Response::Success(format!("Window {} already protected\n", id))
+                }
+                Ok((id, true)) => {
+                    protocol::Response::Success(format!("Window {} no longer protected\n", id))
+                }
+                Ok((id, false)) => {
+                    protocol::Response::Success(format!("Window {} was not protected\n", id))
+                }
+                Err(e) => protocol::Response::Error(e.to_string()),
+            }
+        }
+        protocol::Request::GroupCreate { name, window_ids } => {
+            match business_logic.create_group(&name, &window_ids).await {
+                Ok(true) => protocol::Response::Success(format!("Created group {}\n", name)),
+                Ok(false) => protocol::Response::Success(format!("Replaced group {}\n", name)),
+                Err(e) => protocol::Response::Error(e.to_string()),
+            }
+        }
+        protocol::Request::GroupRemove { name } => match business_logic.remove_group(&name).await {
+            Ok(_) => protocol::Response::Success(format!("Removed group {}\n", name)),
+            Err(e) => protocol::Response::Error(e.to_string()),
+        },
+        protocol::Request::GroupList { format } => {
+            let groups = business_logic.list_groups().await;
+            protocol::Response::Data(protocol::render_records(
+                format,
+                || protocol::format_groups(&groups),
+                || protocol::group_records(&groups),
+            ))
+        }
+        protocol::Request::SlotSet { slot, window_id } => {
+            match business_logic.set_slot(slot, window_id).await {
+                Ok(window_id) => {
+                    protocol::Response::Success(format!("Window {} in slot {}\n", window_id, slot))
+                }
+                Err(e) => protocol::Response::Error(e.to_string()),
+            }
+        }
+        protocol::Request::SlotClear { slot } => {
+            if business_logic.clear_slot(slot).await {
+                protocol::Response::Success(format!("Cleared slot {}\n", slot))
+            } else {
+                protocol::Response::Success(format!("Slot {} is empty\n", slot))
+            }
+        }
+        protocol::Request::SlotList { format } => {
+            let slots = business_logic.list_slots().await;
+            protocol::Response::Data(protocol::render_records(
+                format,
+                || protocol::format_slots(&slots),
+                || protocol::slot_records(&slots),
+            ))
+        }
+        protocol::Request::FocusSlot { slot } => match business_logic.focus_slot(slot).await {
+            Ok(window_id) => protocol::Response::Success(format!("Focused window {}\n", window_id)),
+            Err(e) => protocol::Response::Error(e.to_string()),
+        },
+        protocol::Request::Wait { request } => {
+            let before = business_logic.snapshot().await;
+            let target_ws_id = match crate::system_integration::get_active_workspace_id().await {
+                Ok(id) => id,
+                Err(_) => {
+                    return protocol::Response::Error(
+                        "Failed to get active workspace ID".to_string(),
+                    );
+                }
+            };
+            let response = Box::pin(dispatch_request(*request, business_logic)).await;
+            if let protocol::Response::Error(_) = response {
+                return response;
+            }
+            match business_logic
+                .wait_for_arrival(&before, target_ws_id, WAIT_TIMEOUT)
+                .await
+            {
+                Ok(()) => response,
+                Err(e) => protocol::Response::Error(e.to_string()),
+            }
+        }
+        protocol::Request::Pick => match crate::system_integration::pick_window().await {
+            Ok(Some(window_id)) => protocol::Response::Data(format!("{}\n", window_id)),
+            Ok(None) => protocol::Response::Error("No window picked".to_string()),
+            Err(e) => protocol::Response::Error(e.to_string()),
+        },
+        protocol::Request::Bring { selector } => match business_logic.bring_window(&selector).await
+        {
+            Ok(true) => {
+                protocol::Response::Success("Brought window here, now sticky\n".to_string())
+            }
+            Ok(false) => {
+                protocol::Response::Success("Brought window here, already sticky\n".to_string())
+            }
+            Err(e) => protocol::Response::Error(e.to_string()),
+        },
+        protocol::Request::Undo => match business_logic.undo().await {
+            Ok(description) => protocol::Response::Success(format!("Undid: {}\n", description)),
+            Err(e) => protocol::Response::Error(e.to_string()),
+        },
+        protocol::Request::Status { format, short } => {
+            let status = business_logic.status().await;
+            protocol::Response::Data(if short {
+                protocol::format_status_short(&status)
+            } else {
+                protocol::render_status(format, &status)
+            })
+        }
+        protocol::Request::Stats { format } => match crate::stats::current() {
+            Some(stats) => {
+                // Apps with a sticky rule don't need one suggested
+                let ruled: HashSet<String> = business_logic
+                    .list_rules()
+                    .await
+                    .into_iter()
+                    .filter(|(_, rule, _)| rule.actions.contains(&Action::Sticky))
+                    .filter_map(|(_, rule, _)| rule.app_id)
+                    .collect();
+                protocol::Response::Data(protocol::render_records(
+                    format,
+                    || protocol::format_stats(&stats, &ruled),
+                    || protocol::stats_records(&stats, &ruled),
+                ))
+            }
+            None => protocol::Response::Error(
+                "Usage statistics are off, set usage_stats = true in the config".to_string(),
+            ),
+        },
+        protocol::Request::Subscribe => {
+            protocol::Response::Error("Subscriptions need a streaming connection".to_string())
+        }
+        protocol::Request::Pause { output } => {
+            business_logic.pause(output.as_deref()).await;
+            match output {
+                Some(output) => {
+                    protocol::Response::Success(format!("Paused sticky-follow on {}\n", output))
+                }
+                None => protocol::Response::Success("Paused sticky-follow\n".to_string()),
+            }
+        }
+        protocol::Request::Resume { output } => {
+            business_logic.resume(output.as_deref()).await;
+            match output {
+                Some(output) => {
+                    protocol::Response::Success(format!("Resumed sticky-follow on {}\n", output))
+                }
+                None => protocol::Response::Success("Resumed sticky-follow\n".to_string()),
+            }
+        }
+        protocol::Request::RuleAdd { rule } => {
+            let description = rule.describe();
+            match business_logic.add_rule(rule).await {
+                Ok(id) => {
+                    protocol::Response::Success(format!("Added rule {}: {}\n", id, description))
+                }
+                Err(e) => protocol::Response::Error(e.to_string()),
+            }
+        }
+        protocol::Request::RuleList { format } => {
+            let rules = business_logic.list_rules().await;
+            protocol::Response::Data(protocol::render_records(
+                format,
+                || protocol::format_rules(&rules),
+                || protocol::rule_records(&rules),
+            ))
+        }
+        protocol::Request::RuleRemove { id } => match business_logic.remove_rule(id).await {
+            Ok(rule) => {
+                protocol::Response::Success(format!("Removed rule {}: {}\n", id, rule.describe()))
+            }
+            Err(e) => protocol::Response::Error(e.to_string()),
+        },
+        protocol::Request::RuleTest { window_id } => {
+            match business_logic.test_rules(window_id).await {
+                Ok((window, outcome, tracked)) => protocol::Response::Data(
+                    protocol::format_rule_test(&window, outcome.as_ref(), tracked),
+                ),
+                Err(e) => protocol::Response::Error(e.to_string()),
+            }
+        }
+        protocol::Request::Idle { idle: true } => match business_logic.idle_enter().await {
+            Ok(count) => {
+                protocol::Response::Success(format!("Staged {} windows while idle\n", count))
+            }
+            Err(e) => protocol::Response::Error(e.to_string()),
+        },
+        protocol::Request::Idle { idle: false } => {
+            let current_ws_id = match crate::system_integration::get_active_workspace_id().await {
+                Ok(id) => id,
+                Err(_) => {
+                    return protocol::Response::Error(
+                        "Failed to get active workspace ID".to_string(),
+                    );
+                }
+            };
+            match business_logic.idle_leave(current_ws_id).await {
+                Ok(count) => protocol::Response::Success(format!("Restored {} windows\n", count)),
+                Err(e) => protocol::Response::Error(e.to_string()),
+            }
+        }
+    }
+}
 
-    // Send response
-    let response_str = protocol::format_response(response);
-    writer.write_all(response_str.as_bytes()).await?;
+/// Stream a JSON state snapshot line on connect and after every change
+async fn run_subscription(mut writer: OwnedWriteHalf, business_logic: BusinessLogic) -> Result<()> {
+    let mut changes = business_logic.subscribe();
+    let mut last = None;
 
-    Ok(())
+    loop {
+        let snapshot = business_logic.snapshot().await;
+        if last.as_ref() != Some(&snapshot) {
+            let line = serde_json::to_string(&snapshot)? + "\n";
+            writer.write_all(line.as_bytes()).await?;
+            writer.flush().await?;
+            last = Some(snapshot);
+        }
+
+        match changes.recv().await {
+            Ok(()) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        }
+    }
 }
 
-async fn run_watcher(business_logic: BusinessLogic) -> Result<()> {
-    let socket_path = std::env::var("NIRI_SOCKET").expect("NIRI_SOCKET env var not set");
-    let stream = UnixStream::connect(&socket_path).await?;
-    let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
+/// Apply rules to a new window, logging failures instead of stopping the watcher
+async fn apply_rules(business_logic: &BusinessLogic, window: &system_integration::WindowInfo) {
+    if let Err(_e) = business_logic.apply_rules(window).await {
+        tracing::error!(
+            operation = "rule",
+            window_id = window.id,
+            workspace_id = window.workspace_id,
+            "Failed to apply rules: {_e:?}"
+        );
+    }
+    business_logic.notify_state_changed();
+}
 
-    writer.write_all(b"\"EventStream\"\n").await?;
-    writer.flush().await?;
+/// Move windows attached to a window that was moved or focused
+async fn follow_leader(business_logic: &BusinessLogic, leader: u64, workspace_id: Option<u64>) {
+    if let Err(_e) = business_logic.follow_leader(leader, workspace_id).await {
+        tracing::error!(
+            operation = "attach",
+            window_id = leader,
+            "Failed to move attached windows: {_e:?}"
+        );
+    }
+}
 
-    let mut line = String::new();
+/// Move sticky windows to a newly activated workspace, logging failures
+async fn follow_workspace(
+    business_logic: &BusinessLogic,
+    ws_id: u64,
+    ws_output: Option<String>,
+    ws_name: Option<String>,
+) {
+    if let Err(_e) = crate::metrics::timed(
+        crate::metrics::Operation::WorkspaceActivation,
+        business_logic.handle_workspace_activation(ws_id, ws_output.as_deref(), ws_name.as_deref()),
+    )
+    .await
+    {
+        tracing::error!(
+            operation = "workspace_activation",
+            workspace_id = ws_id,
+            "Failed to handle workspace activation: {_e:?}"
+        );
+    }
+}
 
-    while reader.read_line(&mut line).await? > 0 {
-        if let Ok(v) = serde_json::from_str::<Value>(&line)
-            && let Some(ws) = v.get("WorkspaceActivated")
-            && let Some(ws_id) = ws.get("id").and_then(|id| id.as_u64())
-        {
-            println!("Workspace switched to: {ws_id}");
-            if let Err(_e) = business_logic.handle_workspace_activation(ws_id).await {
-                eprintln!("Failed to handle workspace activation: {_e:?}");
+#[tracing::instrument(name = "watcher", skip_all, fields(backend = system_integration::backend().as_str()))]
+async fn run_watcher(
+    business_logic: BusinessLogic,
+    dwell: Duration,
+    ready: Option<oneshot::Sender<()>>,
+) -> Result<()> {
+    let mut events = system_integration::EventStream::connect().await?;
+    if let Some(ready) = ready {
+        let _ = ready.send(());
+    }
+    crate::metrics::set_event_stream_connected(true);
+
+    // Workspaces by ID, kept up to date from WorkspacesChanged events
+    let mut known_workspaces: HashMap<u64, WorkspaceInfo> = HashMap::new();
+    // Latest activation per output, a delayed follow only runs if no switch came after it
+    let latest_activation: Arc<std::sync::Mutex<HashMap<Option<String>, u64>>> = Arc::default();
+    let mut activations: u64 = 0;
+    // Connected outputs, used to detect hotplug
+    let mut known_outputs: Option<HashSet<String>> = None;
+    // Windows already run through the rules
+    let mut seen_windows: HashSet<u64> = HashSet::new();
+
+    while let Some(batch) = events.next().await? {
+        tracing::trace!(?batch, "compositor events");
+        crate::metrics::record_event();
+        for event in batch {
+            match event {
+                CompositorEvent::WorkspacesChanged(workspaces) => {
+                    known_workspaces = workspaces.into_iter().map(|ws| (ws.id, ws)).collect();
+
+                    let outputs: HashSet<String> = known_workspaces
+                        .values()
+                        .filter_map(|ws| ws.output.clone())
+                        .collect();
+                    if known_outputs
+                        .as_ref()
+                        .is_some_and(|known| *known != outputs)
+                    {
+                        tracing::info!("Outputs changed: {outputs:?}");
+                        if let Err(_e) = business_logic.handle_outputs_changed(&outputs).await {
+                            tracing::error!("Failed to handle output change: {_e:?}");
+                        }
+                    }
+                    known_outputs = Some(outputs);
+                }
+                CompositorEvent::WorkspaceActivated(ws_id) => {
+                    let workspace = match known_workspaces.get(&ws_id) {
+                        Some(ws) => Some(ws.clone()),
+                        None => system_integration::get_workspaces()
+                            .await
+                            .unwrap_or_default()
+                            .into_iter()
+                            .find(|ws| ws.id == ws_id),
+                    };
+                    let ws_output = workspace.as_ref().and_then(|ws| ws.output.clone());
+                    let ws_name = workspace.and_then(|ws| ws.name);
+                    tracing::info!(
+                        operation = "workspace_activation",
+                        workspace_id = ws_id,
+                        "Workspace switched to: {ws_id} (output {ws_output:?})"
+                    );
+                    if dwell.is_zero() {
+                        follow_workspace(&business_logic, ws_id, ws_output, ws_name).await;
+                        continue;
+                    }
+
+                    // Only follow once the user stayed on the workspace for the dwell time
+                    activations += 1;
+                    let activation = activations;
+                    latest_activation
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .insert(ws_output.clone(), activation);
+                    let latest_activation = latest_activation.clone();
+                    let business_logic = business_logic.clone();
+                    tokio::spawn(async move {
+                        tokio::time::sleep(dwell).await;
+                        let current = latest_activation
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .get(&ws_output)
+                            == Some(&activation);
+                        if !current {
+                            tracing::debug!(
+                                operation = "workspace_activation",
+                                workspace_id = ws_id,
+                                "Left workspace {ws_id} within the dwell time, not following"
+                            );
+                            return;
+                        }
+                        follow_workspace(&business_logic, ws_id, ws_output, ws_name).await;
+                    });
+                }
+                CompositorEvent::WindowFocusChanged(focused) => {
+                    business_logic.set_focused_window(focused).await;
+                    if let Some(id) = focused {
+                        follow_leader(&business_logic, id, None).await;
+                    }
+                }
+                CompositorEvent::WindowsChanged(windows) => {
+                    let focused = windows.iter().find(|w| w.is_focused).map(|w| w.id);
+                    business_logic.set_focused_window(focused).await;
+
+                    // Sent once the stream starts, so rules also apply to windows open at startup
+                    for window in &windows {
+                        if !seen_windows.contains(&window.id) {
+                            apply_rules(&business_logic, window).await;
+                        }
+                    }
+                    seen_windows = windows.into_iter().map(|w| w.id).collect();
+                }
+                CompositorEvent::WindowOpenedOrChanged(window) => {
+                    let first_seen = seen_windows.insert(window.id);
+                    // Windows taken by a wait-for request get its action instead of the rules
+                    if !business_logic.claim_for_waiters(&window).await && first_seen {
+                        apply_rules(&business_logic, &window).await;
+                    }
+                    follow_leader(&business_logic, window.id, window.workspace_id).await;
+                }
+                CompositorEvent::WindowClosed(id) => {
+                    seen_windows.remove(&id);
+                    business_logic.forget_closed_window(id).await;
+                    business_logic.notify_state_changed();
+                }
             }
         }
-        line.clear();
     }
 
     Ok(())
@@ -0,0 +1,177 @@
+use anyhow::Result;
+use tokio::sync::broadcast;
+use zbus::{fdo, interface, object_server::SignalEmitter};
+
+use crate::{
+    business::BusinessLogic,
+    daemon,
+    protocol::{self, Request, StageArgs, UnstageArgs},
+};
+
+const BUS_NAME: &str = "org.nsticky.Daemon";
+const OBJECT_PATH: &str = "/org/nsticky/Daemon";
+
+/// org.nsticky.Daemon interface, a thin wrapper around the CLI protocol
+struct DaemonInterface {
+    business_logic: BusinessLogic,
+}
+
+impl DaemonInterface {
+    /// Run a request and map protocol errors to D-Bus errors
+    async fn call(&self, request: Request) -> fdo::Result<String> {
+        match daemon::process_request(request, "dbus", &self.business_logic).await {
+            protocol::Response::Success(msg) | protocol::Response::Data(msg) => {
+                Ok(msg.trim_end().to_string())
+            }
+            protocol::Response::Error(msg) => Err(fdo::Error::Failed(msg)),
+        }
+    }
+}
+
+#[interface(name = "org.nsticky.Daemon")]
+impl DaemonInterface {
+    /// Add window to sticky list
+    async fn add(&self, window_id: u64) -> fdo::Result<String> {
+        self.call(Request::Add {
+            window_id,
+            duration: None,
+        })
+        .await
+    }
+
+    /// Remove window from sticky list
+    async fn remove(&self, window_id: u64) -> fdo::Result<String> {
+        self.call(Request::Remove {
+            window_id,
+            home: None,
+        })
+        .await
+    }
+
+    /// Toggle active window in sticky list
+    async fn toggle_active(&self) -> fdo::Result<String> {
+        self.call(Request::ToggleActive).await
+    }
+
+    /// Toggle window by app ID in sticky list
+    async fn toggle_appid(&self, appid: String) -> fdo::Result<String> {
+        self.call(Request::ToggleAppid { appid }).await
+    }
+
+    /// Toggle window by title in sticky list
+    async fn toggle_title(&self, title: String) -> fdo::Result<String> {
+        self.call(Request::ToggleTitle { title }).await
+    }
+
+    /// Move a sticky window to the stage workspace
+    async fn stage(&self, window_id: u64) -> fdo::Result<String> {
+        self.call(Request::Stage(StageArgs {
+            window_id: Some(window_id),
+            ..Default::default()
+        }))
+        .await
+    }
+
+    /// Move a staged window back to the current workspace
+    async fn unstage(&self, window_id: u64) -> fdo::Result<String> {
+        self.call(Request::Unstage(UnstageArgs {
+            window_id: Some(window_id),
+            ..Default::default()
+        }))
+        .await
+    }
+
+    /// Toggle active window in stage
+    async fn toggle_stage_active(&self) -> fdo::Result<String> {
+        self.call(Request::Stage(StageArgs {
+            active: true,
+            ..Default::default()
+        }))
+        .await
+    }
+
+    /// Stage all sticky windows
+    async fn stage_all(&self) -> fdo::Result<String> {
+        self.call(Request::Stage(StageArgs {
+            all: true,
+            ..Default::default()
+        }))
+        .await
+    }
+
+    /// Unstage all staged windows
+    async fn unstage_all(&self) -> fdo::Result<String> {
+        self.call(Request::Unstage(UnstageArgs {
+            all: true,
+            ..Default::default()
+        }))
+        .await
+    }
+
+    /// Run any command line of the socket protocol, e.g. "mode global"
+    async fn command(&self, line: String) -> fdo::Result<String> {
+        let request =
+            protocol::parse_request(&line).map_err(|e| fdo::Error::InvalidArgs(e.to_string()))?;
+        self.call(request).await
+    }
+
+    /// Sticky window IDs
+    #[zbus(property)]
+    async fn sticky(&self) -> Vec<u64> {
+        self.business_logic.snapshot().await.sticky
+    }
+
+    /// Staged window IDs
+    #[zbus(property)]
+    async fn staged(&self) -> Vec<u64> {
+        self.business_logic.snapshot().await.staged
+    }
+
+    /// Emitted whenever the sticky or staged set changes
+    #[zbus(signal)]
+    async fn state_changed(
+        emitter: &SignalEmitter<'_>,
+        sticky: Vec<u64>,
+        staged: Vec<u64>,
+    ) -> zbus::Result<()>;
+}
+
+/// Serve org.nsticky.Daemon on the session bus and emit signals on state changes
+pub async fn run_service(business_logic: BusinessLogic) -> Result<()> {
+    let mut changes = business_logic.subscribe();
+    let interface = DaemonInterface {
+        business_logic: business_logic.clone(),
+    };
+    let connection = zbus::connection::Builder::session()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, interface)?
+        .build()
+        .await?;
+    let iface_ref = connection
+        .object_server()
+        .interface::<_, DaemonInterface>(OBJECT_PATH)
+        .await?;
+    tracing::info!("D-Bus service {} started", BUS_NAME);
+
+    let mut last = business_logic.snapshot().await;
+    loop {
+        match changes.recv().await {
+            Ok(()) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        }
+
+        // Focus changes also wake us up, only sticky/staged changes are signalled
+        let snapshot = business_logic.snapshot().await;
+        if snapshot.sticky == last.sticky && snapshot.staged == last.staged {
+            continue;
+        }
+
+        let emitter = iface_ref.signal_emitter();
+        DaemonInterface::state_changed(emitter, snapshot.sticky.clone(), snapshot.staged.clone())
+            .await?;
+        let iface = iface_ref.get().await;
+        iface.sticky_changed(emitter).await?;
+        iface.staged_changed(emitter).await?;
+        last = snapshot;
+    }
+}
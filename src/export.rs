@@ -0,0 +1,47 @@
+use anyhow::Result;
+use std::path::PathBuf;
+use tokio::sync::broadcast;
+
+use crate::business::BusinessLogic;
+
+/// Get state export path ($XDG_RUNTIME_DIR/nsticky/state-<session>.json)
+pub fn export_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty())?;
+    Some(
+        PathBuf::from(dir)
+            .join("nsticky")
+            .join(crate::session::state_file_name()),
+    )
+}
+
+/// Replace the file contents atomically so watchers never read a partial write
+async fn write_atomic(path: &PathBuf, content: &[u8]) -> Result<()> {
+    let tmp_path = path.with_extension("json.tmp");
+    tokio::fs::write(&tmp_path, content).await?;
+    tokio::fs::rename(&tmp_path, path).await?;
+    Ok(())
+}
+
+/// Mirror the current state into the export file on every change
+pub async fn run_state_export(business_logic: BusinessLogic) -> Result<()> {
+    let path = export_path().ok_or_else(|| anyhow::anyhow!("XDG_RUNTIME_DIR is not set"))?;
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+
+    let mut changes = business_logic.subscribe();
+    let mut last = None;
+    loop {
+        let snapshot = business_logic.snapshot().await;
+        if last.as_ref() != Some(&snapshot) {
+            let content = serde_json::to_string(&snapshot)? + "\n";
+            write_atomic(&path, content.as_bytes()).await?;
+            last = Some(snapshot);
+        }
+
+        match changes.recv().await {
+            Ok(()) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use anyhow::Result;
+use smithay_client_toolkit::reexports::{
+    client::{
+        Connection, Dispatch, EventQueue, Proxy, QueueHandle,
+        backend::ObjectId,
+        event_created_child,
+        globals::{GlobalListContents, registry_queue_init},
+        protocol::wl_registry,
+    },
+    protocols::ext::foreign_toplevel_list::v1::client::{
+        ext_foreign_toplevel_handle_v1::{self, ExtForeignToplevelHandleV1},
+        ext_foreign_toplevel_list_v1::{self, ExtForeignToplevelListV1},
+    },
+};
+use std::collections::HashMap;
+use tokio::sync::mpsc;
+
+use crate::system_integration::{CompositorEvent, WindowInfo, WorkspaceInfo};
+
+/// A toplevel as announced by the compositor, complete once `done` arrived
+#[derive(Default)]
+struct Toplevel {
+    identifier: Option<String>,
+    app_id: Option<String>,
+    title: Option<String>,
+    done: bool,
+}
+
+impl Toplevel {
+    fn info(&self) -> Option<WindowInfo> {
+        Some(WindowInfo {
+            id: window_id(self.identifier.as_deref()?),
+            app_id: self.app_id.clone(),
+            title: self.title.clone(),
+            workspace_id: None,
+            is_focused: false,
+            is_floating: false,
+            is_fullscreen: false,
+        })
+    }
+}
+
+/// Numeric window ID derived from the protocol's identifier (FNV-1a)
+/// Identifiers are the same for every client, so the daemon and the CLI agree on IDs
+/// Kept below 2^53 so JSON consumers don't lose precision
+fn window_id(identifier: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in identifier.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash & ((1 << 53) - 1)
+}
+
+struct ToplevelState {
+    toplevels: HashMap<ObjectId, Toplevel>,
+    /// Set once the initial window list was sent, changes are forwarded from then on
+    events: Option<mpsc::UnboundedSender<Vec<CompositorEvent>>>,
+}
+
+impl ToplevelState {
+    fn windows(&self) -> Vec<WindowInfo> {
+        self.toplevels
+            .values()
+            .filter(|toplevel| toplevel.done)
+            .filter_map(Toplevel::info)
+            .collect()
+    }
+
+    fn send(&self, event: CompositorEvent) {
+        if let Some(events) = &self.events {
+            let _ = events.send(vec![event]);
+        }
+    }
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for ToplevelState {
+    fn event(
+        _state: &mut Self,
+        _registry: &wl_registry::WlRegistry,
+        _event: wl_registry::Event,
+        _data: &GlobalListContents,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ExtForeignToplevelListV1, ()> for ToplevelState {
+    fn event(
+        state: &mut Self,
+        _list: &ExtForeignToplevelListV1,
+        event: ext_foreign_toplevel_list_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let ext_foreign_toplevel_list_v1::Event::Toplevel { toplevel } = event {
+            state.toplevels.insert(toplevel.id(), Toplevel::default());
+        }
+    }
+
+    event_created_child!(ToplevelState, ExtForeignToplevelListV1, [
+        ext_foreign_toplevel_list_v1::EVT_TOPLEVEL_OPCODE => (ExtForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ExtForeignToplevelHandleV1, ()> for ToplevelState {
+    fn event(
+        state: &mut Self,
+        handle: &ExtForeignToplevelHandleV1,
+        event: ext_foreign_toplevel_handle_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let Some(toplevel) = state.toplevels.get_mut(&handle.id()) else {
+            return;
+        };
+        match event {
+            ext_foreign_toplevel_handle_v1::Event::Identifier { identifier } => {
+                toplevel.identifier = Some(identifier);
+            }
+            ext_foreign_toplevel_handle_v1::Event::AppId { app_id } => {
+                toplevel.app_id = Some(app_id);
+            }
+            ext_foreign_toplevel_handle_v1::Event::Title { title } => {
+                toplevel.title = Some(title);
+            }
+            ext_foreign_toplevel_handle_v1::Event::Done => {
+                tracing::trace!(identifier = ?toplevel.identifier, app_id = ?toplevel.app_id, "toplevel done");
+                toplevel.done = true;
+                if let Some(window) = toplevel.info() {
+                    state.send(CompositorEvent::WindowOpenedOrChanged(window));
+                }
+            }
+            ext_foreign_toplevel_handle_v1::Event::Closed => {
+                tracing::trace!(identifier = ?toplevel.identifier, "toplevel closed");
+                if let Some(window) = state.toplevels.remove(&handle.id()).and_then(|t| t.info()) {
+                    state.send(CompositorEvent::WindowClosed(window.id));
+                }
+                handle.destroy();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Connect and read the current toplevels
+fn connect() -> Result<(EventQueue<ToplevelState>, ToplevelState)> {
+    let conn = Connection::connect_to_env()?;
+    tracing::debug!("Connecting to the Wayland compositor for ext-foreign-toplevel-list");
+    let (globals, mut queue) = registry_queue_init::<ToplevelState>(&conn)?;
+    let qh = queue.handle();
+    let _list: ExtForeignToplevelListV1 = globals.bind(&qh, 1..=1, ())?;
+
+    let mut state = ToplevelState {
+        toplevels: HashMap::new(),
+        events: None,
+    };
+    // The first roundtrip announces the toplevels, the second delivers their details
+    queue.roundtrip(&mut state)?;
+    queue.roundtrip(&mut state)?;
+    Ok((queue, state))
+}
+
+/// Get full window information from the compositor
+pub async fn get_full_window_info() -> Result<Vec<WindowInfo>> {
+    tokio::task::spawn_blocking(|| {
+        let (_queue, state) = connect()?;
+        Ok(state.windows())
+    })
+    .await?
+}
+
+/// The protocol has no workspaces
+pub async fn get_workspaces() -> Result<Vec<WorkspaceInfo>> {
+    Ok(Vec::new())
+}
+
+pub async fn get_active_window_id() -> Result<u64> {
+    anyhow::bail!("The foreign-toplevel backend can't tell which window is focused")
+}
+
+/// The protocol has no way to move windows
+pub async fn unsupported() -> Result<()> {
+    anyhow::bail!("Moving windows is not supported by the foreign-toplevel backend")
+}
+
+/// Toplevel changes forwarded from a Wayland connection on its own thread
+pub struct EventStream {
+    events: mpsc::UnboundedReceiver<Vec<CompositorEvent>>,
+}
+
+impl EventStream {
+    pub async fn connect() -> Result<Self> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (queue, mut state) = tokio::task::spawn_blocking(connect).await??;
+        tx.send(vec![CompositorEvent::WindowsChanged(state.windows())])?;
+        state.events = Some(tx);
+
+        std::thread::spawn(move || {
+            let mut queue = queue;
+            loop {
+                if let Err(_e) = queue.blocking_dispatch(&mut state) {
+                    tracing::error!("Foreign toplevel watcher error: {_e:?}");
+                    return;
+                }
+                if state
+                    .events
+                    .as_ref()
+                    .is_none_or(|events| events.is_closed())
+                {
+                    return;
+                }
+            }
+        });
+        Ok(Self { events: rx })
+    }
+
+    /// Next toplevel change, None once the Wayland connection is gone
+    pub async fn next(&mut self) -> Result<Option<Vec<CompositorEvent>>> {
+        Ok(self.events.recv().await)
+    }
+}
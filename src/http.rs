@@ -0,0 +1,133 @@
+use anyhow::{Result, bail};
+use std::time::Duration;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+
+/// Largest request body we accept, control requests are tiny
+const MAX_BODY: usize = 64 * 1024;
+
+/// Largest request line plus headers we accept
+const MAX_HEAD: u64 = 16 * 1024;
+
+/// Clients that don't send a whole request in this time are dropped
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Minimal HTTP/1.1 request, enough for the metrics and control endpoints
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub content_type: Option<String>,
+    pub host: Option<String>,
+    pub origin: Option<String>,
+    pub body: Vec<u8>,
+}
+
+/// Read one request from the connection, giving up after READ_TIMEOUT
+pub async fn read_request(
+    reader: &mut BufReader<impl AsyncReadExt + Unpin>,
+) -> Result<HttpRequest> {
+    match tokio::time::timeout(READ_TIMEOUT, read_request_inner(reader)).await {
+        Ok(result) => result,
+        Err(_) => bail!("Timed out reading the request"),
+    }
+}
+
+async fn read_request_inner(
+    reader: &mut BufReader<impl AsyncReadExt + Unpin>,
+) -> Result<HttpRequest> {
+    // A line cut off by the limit has no newline, as does one cut off by EOF
+    let mut head = (&mut *reader).take(MAX_HEAD);
+    let mut request_line = String::new();
+    head.read_line(&mut request_line).await?;
+    if !request_line.ends_with('\n') {
+        bail!("Request line too long or truncated");
+    }
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        bail!("Malformed request line");
+    };
+    let method = method.to_string();
+    let path = path.to_string();
+
+    let mut content_length = 0;
+    let mut content_type = None;
+    let mut host = None;
+    let mut origin = None;
+    let mut header = String::new();
+    loop {
+        head.read_line(&mut header).await?;
+        if !header.ends_with('\n') {
+            bail!("Request headers too large or truncated");
+        }
+        if header.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse()?;
+            } else if name.eq_ignore_ascii_case("content-type") {
+                content_type = Some(value.to_string());
+            } else if name.eq_ignore_ascii_case("host") {
+                host = Some(value.to_string());
+            } else if name.eq_ignore_ascii_case("origin") {
+                origin = Some(value.to_string());
+            }
+        }
+        header.clear();
+    }
+
+    if content_length > MAX_BODY {
+        bail!("Request body too large");
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+
+    Ok(HttpRequest {
+        method,
+        path,
+        content_type,
+        host,
+        origin,
+        body,
+    })
+}
+
+/// Write a complete response and close the connection
+pub async fn write_response(
+    writer: &mut (impl AsyncWriteExt + Unpin),
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    writer.write_all(response.as_bytes()).await?;
+    writer.shutdown().await?;
+    Ok(())
+}
+
+/// Accept connections forever, handling each one on its own task
+pub async fn serve<F, Fut>(listener: tokio::net::TcpListener, handler: F) -> Result<()>
+where
+    F: Fn(TcpStream) -> Fut,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let connection = handler(stream);
+        tokio::spawn(async move {
+            if let Err(_e) = connection.await {
+                tracing::warn!("HTTP connection error: {_e:?}");
+            }
+        });
+    }
+}
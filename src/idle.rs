@@ -0,0 +1,113 @@
+use anyhow::Result;
+use smithay_client_toolkit::reexports::{
+    client::{
+        Connection, Dispatch, QueueHandle,
+        globals::{GlobalListContents, registry_queue_init},
+        protocol::{wl_registry, wl_seat},
+    },
+    protocols::ext::idle_notify::v1::client::{ext_idle_notification_v1, ext_idle_notifier_v1},
+};
+use tokio::sync::mpsc;
+
+use crate::{business::BusinessLogic, protocol};
+
+/// Forwards idle/resume notifications from the compositor
+struct IdleState {
+    /// true when the session went idle, false on activity
+    events: mpsc::UnboundedSender<bool>,
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for IdleState {
+    fn event(
+        _state: &mut Self,
+        _registry: &wl_registry::WlRegistry,
+        _event: wl_registry::Event,
+        _data: &GlobalListContents,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for IdleState {
+    fn event(
+        _state: &mut Self,
+        _seat: &wl_seat::WlSeat,
+        _event: wl_seat::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ext_idle_notifier_v1::ExtIdleNotifierV1, ()> for IdleState {
+    fn event(
+        _state: &mut Self,
+        _notifier: &ext_idle_notifier_v1::ExtIdleNotifierV1,
+        _event: ext_idle_notifier_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ext_idle_notification_v1::ExtIdleNotificationV1, ()> for IdleState {
+    fn event(
+        state: &mut Self,
+        _notification: &ext_idle_notification_v1::ExtIdleNotificationV1,
+        event: ext_idle_notification_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let idle = match event {
+            ext_idle_notification_v1::Event::Idled => true,
+            ext_idle_notification_v1::Event::Resumed => false,
+            _ => return,
+        };
+        let _ = state.events.send(idle);
+    }
+}
+
+/// Watch ext-idle-notify on its own thread, Wayland dispatch blocks
+fn watch_idle(timeout_ms: u32, events: mpsc::UnboundedSender<bool>) -> Result<()> {
+    let conn = Connection::connect_to_env()?;
+    let (globals, mut queue) = registry_queue_init::<IdleState>(&conn)?;
+    let qh = queue.handle();
+
+    let seat: wl_seat::WlSeat = globals.bind(&qh, 1..=1, ())?;
+    let notifier: ext_idle_notifier_v1::ExtIdleNotifierV1 = globals.bind(&qh, 1..=1, ())?;
+    let _notification = notifier.get_idle_notification(timeout_ms, &seat, &qh, ());
+
+    let mut state = IdleState { events };
+    loop {
+        queue.blocking_dispatch(&mut state)?;
+        if state.events.is_closed() {
+            return Ok(());
+        }
+    }
+}
+
+/// Stage sticky windows after `stage_after_min` minutes idle, restoring them on activity
+pub async fn run_idle_watcher(business_logic: BusinessLogic, stage_after_min: u64) -> Result<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let timeout_ms = u32::try_from(stage_after_min.saturating_mul(60_000)).unwrap_or(u32::MAX);
+    std::thread::spawn(move || {
+        if let Err(_e) = watch_idle(timeout_ms, tx) {
+            tracing::error!("Idle watcher error: {_e:?}");
+        }
+    });
+
+    while let Some(idle) = rx.recv().await {
+        let response = crate::daemon::process_request(
+            protocol::Request::Idle { idle },
+            "idle watcher",
+            &business_logic,
+        )
+        .await;
+        tracing::info!("{}", protocol::format_response(response).trim_end());
+    }
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use std::fmt::{self, Write as _};
+use std::io::{IsTerminal, Write};
+use std::os::unix::net::UnixDatagram;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::{EnvFilter, Layer, layer::Context, prelude::*, registry::LookupSpan};
+
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Install the daemon's subscriber
+/// The filter comes from RUST_LOG, then the configured log_level, then "info"
+pub fn init(log_level: Option<&str>) {
+    let filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(log_level.unwrap_or("info")))
+        .unwrap_or_else(|e| {
+            eprintln!("Invalid log_level, using \"info\": {e}");
+            EnvFilter::new("info")
+        });
+
+    let registry = tracing_subscriber::registry().with(filter);
+    match journal() {
+        Some(socket) => registry.with(JournalLayer { socket }).init(),
+        None => registry
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_writer(std::io::stderr)
+                    .with_ansi(std::io::stderr().is_terminal()),
+            )
+            .init(),
+    }
+}
+
+static TRACE_IPC: AtomicBool = AtomicBool::new(false);
+
+/// Log every message exchanged with the compositor, set by --trace-ipc or `nsticky trace-ipc`
+pub fn set_trace_ipc(enabled: bool) {
+    TRACE_IPC.store(enabled, Ordering::Relaxed);
+}
+
+pub fn trace_ipc_enabled() -> bool {
+    TRACE_IPC.load(Ordering::Relaxed)
+}
+
+/// Log raw bytes sent to (">") or received from ("<") a compositor socket
+/// Non-printable bytes are escaped so binary headers stay readable
+pub fn trace_ipc(direction: &str, peer: &str, bytes: &[u8]) {
+    if trace_ipc_enabled() {
+        tracing::info!(target: "nsticky::ipc", direction, peer, "{}", bytes.escape_ascii());
+    }
+}
+
+/// Journal socket, connected only when stdout/stderr already go to the journal
+fn journal() -> Option<UnixDatagram> {
+    std::env::var_os("JOURNAL_STREAM")?;
+    let socket = UnixDatagram::unbound().ok()?;
+    socket.connect(JOURNAL_SOCKET).ok()?;
+    Some(socket)
+}
+
+/// Append a field using the journal native protocol
+fn push_field(buf: &mut Vec<u8>, key: &str, value: &str) {
+    if value.contains('\n') {
+        // Multi-line values use the length-prefixed binary form
+        buf.extend_from_slice(key.as_bytes());
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        buf.extend_from_slice(value.as_bytes());
+        buf.push(b'\n');
+    } else {
+        let _ = writeln!(buf, "{}={}", key, value);
+    }
+}
+
+/// Syslog priority of a tracing level
+fn priority(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 3,
+        Level::WARN => 4,
+        Level::INFO => 6,
+        Level::DEBUG | Level::TRACE => 7,
+    }
+}
+
+/// Sends events to the journal with their fields as journal fields (operation -> OPERATION)
+/// SPAN holds the names of the enclosing spans
+struct JournalLayer {
+    socket: UnixDatagram,
+}
+
+/// Collects the message and the remaining fields of an event
+#[derive(Default)]
+struct Fields {
+    message: String,
+    fields: Vec<(String, String)>,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            self.fields
+                .push((field.name().to_ascii_uppercase(), value.to_string()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            self.fields
+                .push((field.name().to_ascii_uppercase(), format!("{:?}", value)));
+        }
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for JournalLayer {
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+
+        let mut buf = Vec::new();
+        push_field(&mut buf, "MESSAGE", &fields.message);
+        push_field(
+            &mut buf,
+            "PRIORITY",
+            &priority(metadata.level()).to_string(),
+        );
+        push_field(&mut buf, "SYSLOG_IDENTIFIER", "nsticky");
+        push_field(&mut buf, "TARGET", metadata.target());
+        if let Some(scope) = ctx.event_scope(event) {
+            let spans: Vec<_> = scope.from_root().map(|span| span.name()).collect();
+            push_field(&mut buf, "SPAN", &spans.join(":"));
+        }
+        for (key, value) in &fields.fields {
+            push_field(&mut buf, key, value);
+        }
+        if self.socket.send(&buf).is_err() {
+            eprintln!("{}", fields.message);
+        }
+    }
+}
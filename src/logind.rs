@@ -0,0 +1,109 @@
+use anyhow::Result;
+use futures_util::StreamExt;
+use std::os::unix::fs::MetadataExt;
+use zbus::{proxy, zvariant::OwnedObjectPath};
+
+use crate::business::BusinessLogic;
+
+#[proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Manager {
+    fn get_session_by_pid(&self, pid: u32) -> zbus::Result<OwnedObjectPath>;
+    fn get_user(&self, uid: u32) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.login1.User",
+    default_service = "org.freedesktop.login1"
+)]
+trait User {
+    /// Graphical session of the user
+    #[zbus(property)]
+    fn display(&self) -> zbus::Result<(String, OwnedObjectPath)>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.login1.Session",
+    default_service = "org.freedesktop.login1"
+)]
+trait Session {
+    #[zbus(signal)]
+    fn lock(&self) -> zbus::Result<()>;
+    #[zbus(signal)]
+    fn unlock(&self) -> zbus::Result<()>;
+    #[zbus(property)]
+    fn locked_hint(&self) -> zbus::Result<bool>;
+}
+
+/// Find our logind session
+/// A systemd user service is not part of any session, fall back to the user's graphical session
+async fn find_session(connection: &zbus::Connection) -> Result<OwnedObjectPath> {
+    let manager = ManagerProxy::new(connection).await?;
+    if let Ok(path) = manager.get_session_by_pid(std::process::id()).await {
+        return Ok(path);
+    }
+
+    let uid = std::fs::metadata("/proc/self")?.uid();
+    let user_path = manager.get_user(uid).await?;
+    let user = UserProxy::builder(connection)
+        .path(user_path)?
+        .build()
+        .await?;
+    let (id, path) = user.display().await?;
+    if id.is_empty() {
+        anyhow::bail!("No graphical logind session found");
+    }
+    Ok(path)
+}
+
+/// Pause sticky-follow while the logind session is locked
+pub async fn run_lock_watcher(business_logic: BusinessLogic) -> Result<()> {
+    let connection = zbus::Connection::system().await?;
+    let session_path = find_session(&connection).await?;
+    let session = SessionProxy::builder(&connection)
+        .path(session_path.clone())?
+        .build()
+        .await?;
+    tracing::info!(
+        operation = "session_lock",
+        "Watching logind session {}",
+        session_path.as_str()
+    );
+
+    let mut lock = session.receive_lock().await?;
+    let mut unlock = session.receive_unlock().await?;
+    // Lock/Unlock are requests to the screen locker, LockedHint is what actually happened
+    let mut locked_hint = session.receive_locked_hint_changed().await;
+
+    let mut locked = session.locked_hint().await.unwrap_or(false);
+    if locked {
+        business_logic.set_session_locked(true).await?;
+    }
+
+    loop {
+        let now_locked = tokio::select! {
+            Some(_) = lock.next() => true,
+            Some(_) = unlock.next() => false,
+            Some(change) = locked_hint.next() => match change.get().await {
+                Ok(hint) => hint,
+                Err(_) => continue,
+            },
+            else => return Ok(()),
+        };
+        if now_locked == locked {
+            continue;
+        }
+        locked = now_locked;
+
+        if let Err(_e) = business_logic.set_session_locked(locked).await {
+            tracing::error!(
+                operation = "session_lock",
+                "Failed to catch up after unlock: {_e:?}"
+            );
+        }
+        business_logic.notify_state_changed();
+    }
+}
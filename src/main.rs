@@ -1,22 +1,68 @@
+#[cfg(feature = "http")]
+mod api;
+mod audit;
+mod bar;
 mod business;
 mod cli;
+mod config;
 mod daemon;
+mod dbus;
+mod export;
+#[cfg(feature = "foreign-toplevel")]
+mod foreign_toplevel;
+#[cfg(any(feature = "metrics", feature = "http"))]
+mod http;
+#[cfg(feature = "idle")]
+mod idle;
+mod logging;
+mod logind;
+mod metrics;
+mod niri;
+mod notifications;
+mod offline;
+#[cfg(feature = "osd")]
+mod osd;
 mod protocol;
+mod rules;
+mod session;
+mod stats;
+mod supervisor;
+mod sway;
 mod system_integration;
+mod systemd;
+#[cfg(feature = "tui")]
+mod tui;
 
 use anyhow::Result;
-use std::{collections::HashSet, env, sync::Arc};
+use std::{collections::HashSet, sync::Arc};
 use tokio::sync::Mutex;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    if env::args().nth(1).is_some() {
+    let cli = cli::Cli::parse_with_aliases();
+    if !cli.runs_daemon() {
         // Run in CLI mode
-        return cli::run_cli().await;
+        return cli::run_cli(cli).await;
     }
 
     // Run in daemon mode
+    let config = config::load();
+    logging::init(
+        config
+            .as_ref()
+            .ok()
+            .and_then(|config| config.log_level.as_deref()),
+    );
+    logging::set_trace_ipc(cli.trace_ipc);
+    let config = config.unwrap_or_else(|e| {
+        tracing::warn!("Failed to load config, using defaults: {e:?}");
+        config::Config::default()
+    });
+    system_integration::set_backend(system_integration::resolve_backend(
+        cli.backend.map(Into::into),
+        config.backend,
+    ));
     let sticky_windows = Arc::new(Mutex::new(HashSet::<u64>::new()));
 
-    daemon::start(sticky_windows).await
+    daemon::start(sticky_windows, config).await
 }
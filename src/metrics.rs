@@ -0,0 +1,295 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Samples kept per operation for the rolling percentiles
+const LATENCY_WINDOW: usize = 512;
+
+/// Daemon counters, cheap enough to always be collected
+pub struct Metrics {
+    /// Actions sent to the compositor
+    pub moves: AtomicU64,
+    /// Actions the compositor failed or refused
+    pub move_failures: AtomicU64,
+    /// Workspace and window queries that failed
+    pub query_failures: AtomicU64,
+    /// Event stream messages that couldn't be parsed
+    pub event_parse_errors: AtomicU64,
+    /// Event stream messages handled by the watcher
+    pub events: AtomicU64,
+    /// Whether the watcher currently has an open event stream
+    pub event_stream_connected: AtomicBool,
+}
+
+pub static METRICS: Metrics = Metrics {
+    moves: AtomicU64::new(0),
+    move_failures: AtomicU64::new(0),
+    query_failures: AtomicU64::new(0),
+    event_parse_errors: AtomicU64::new(0),
+    events: AtomicU64::new(0),
+    event_stream_connected: AtomicBool::new(false),
+};
+
+/// Count a compositor action, failures are reported with record_error
+pub fn record_move() {
+    METRICS.moves.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Error messages kept for `nsticky status`
+const RECENT_ERRORS: usize = 5;
+
+/// Categories of counted failures
+#[derive(Debug, Clone, Copy)]
+pub enum ErrorKind {
+    MoveFailed,
+    QueryFailed,
+    EventParse,
+}
+
+impl ErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::MoveFailed => "move",
+            ErrorKind::QueryFailed => "query",
+            ErrorKind::EventParse => "event parse",
+        }
+    }
+
+    fn counter(&self) -> &'static AtomicU64 {
+        match self {
+            ErrorKind::MoveFailed => &METRICS.move_failures,
+            ErrorKind::QueryFailed => &METRICS.query_failures,
+            ErrorKind::EventParse => &METRICS.event_parse_errors,
+        }
+    }
+}
+
+/// A recorded failure and when it happened
+#[derive(Debug, Clone)]
+pub struct RecentError {
+    pub kind: ErrorKind,
+    pub at: Instant,
+    pub message: String,
+}
+
+static ERRORS: Mutex<VecDeque<RecentError>> = Mutex::new(VecDeque::new());
+
+/// Count a failure and remember its message
+pub fn record_error(kind: ErrorKind, message: impl std::fmt::Display) {
+    kind.counter().fetch_add(1, Ordering::Relaxed);
+    let mut errors = ERRORS.lock().unwrap_or_else(|e| e.into_inner());
+    if errors.len() == RECENT_ERRORS {
+        errors.pop_front();
+    }
+    errors.push_back(RecentError {
+        kind,
+        at: Instant::now(),
+        message: format!("{:#}", message),
+    });
+}
+
+/// The last few failures, oldest first
+pub fn recent_errors() -> Vec<RecentError> {
+    let errors = ERRORS.lock().unwrap_or_else(|e| e.into_inner());
+    errors.iter().cloned().collect()
+}
+
+/// Count a processed event stream message
+pub fn record_event() {
+    METRICS.events.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn set_event_stream_connected(connected: bool) {
+    METRICS
+        .event_stream_connected
+        .store(connected, Ordering::Relaxed);
+}
+
+/// Operations whose latency is tracked
+#[derive(Debug, Clone, Copy)]
+pub enum Operation {
+    /// Moving sticky windows after a workspace switch
+    WorkspaceActivation,
+    /// stage --all, unstage --all and idle staging
+    BulkMove,
+    /// A single query or action sent to the compositor
+    CompositorRequest,
+}
+
+impl Operation {
+    const ALL: [Operation; 3] = [
+        Operation::WorkspaceActivation,
+        Operation::BulkMove,
+        Operation::CompositorRequest,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::WorkspaceActivation => "workspace_activation",
+            Operation::BulkMove => "bulk_move",
+            Operation::CompositorRequest => "compositor_request",
+        }
+    }
+}
+
+/// Most recent durations of each operation, indexed like Operation::ALL
+static LATENCIES: Mutex<[VecDeque<Duration>; 3]> =
+    Mutex::new([VecDeque::new(), VecDeque::new(), VecDeque::new()]);
+
+/// Percentiles over the last LATENCY_WINDOW runs of an operation
+#[derive(Debug, Clone)]
+pub struct LatencySummary {
+    pub operation: Operation,
+    pub count: usize,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+pub fn record_latency(operation: Operation, elapsed: Duration) {
+    let mut latencies = LATENCIES.lock().unwrap_or_else(|e| e.into_inner());
+    let samples = &mut latencies[operation as usize];
+    if samples.len() == LATENCY_WINDOW {
+        samples.pop_front();
+    }
+    samples.push_back(elapsed);
+}
+
+/// Run a future and record how long it took
+pub async fn timed<T>(operation: Operation, fut: impl Future<Output = T>) -> T {
+    let started = Instant::now();
+    let result = fut.await;
+    record_latency(operation, started.elapsed());
+    result
+}
+
+/// Latency percentiles of every operation that ran at least once
+pub fn latencies() -> Vec<LatencySummary> {
+    let latencies = LATENCIES.lock().unwrap_or_else(|e| e.into_inner());
+    Operation::ALL
+        .iter()
+        .filter_map(|&operation| {
+            let mut samples: Vec<Duration> =
+                latencies[operation as usize].iter().copied().collect();
+            samples.sort_unstable();
+            let max = *samples.last()?;
+            // Nearest-rank percentile
+            let percentile = |p: usize| samples[(samples.len() * p).div_ceil(100).max(1) - 1];
+            Some(LatencySummary {
+                operation,
+                count: samples.len(),
+                p50: percentile(50),
+                p90: percentile(90),
+                p99: percentile(99),
+                max,
+            })
+        })
+        .collect()
+}
+
+#[cfg(feature = "metrics")]
+mod server {
+    use anyhow::Result;
+    use std::fmt::Write as _;
+    use std::sync::atomic::Ordering;
+    use tokio::{
+        io::BufReader,
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::METRICS;
+    use crate::{business::BusinessLogic, http};
+
+    /// Render all metrics in the Prometheus text exposition format
+    async fn render(business_logic: &BusinessLogic) -> String {
+        let snapshot = business_logic.snapshot().await;
+        let metrics: [(&str, &str, &str, u64); 8] = [
+            (
+                "nsticky_moves_total",
+                "counter",
+                "Window actions sent to the compositor",
+                METRICS.moves.load(Ordering::Relaxed),
+            ),
+            (
+                "nsticky_move_failures_total",
+                "counter",
+                "Window actions that failed",
+                METRICS.move_failures.load(Ordering::Relaxed),
+            ),
+            (
+                "nsticky_query_failures_total",
+                "counter",
+                "Compositor queries that failed",
+                METRICS.query_failures.load(Ordering::Relaxed),
+            ),
+            (
+                "nsticky_event_parse_errors_total",
+                "counter",
+                "Compositor events that couldn't be parsed",
+                METRICS.event_parse_errors.load(Ordering::Relaxed),
+            ),
+            (
+                "nsticky_events_total",
+                "counter",
+                "Compositor events processed",
+                METRICS.events.load(Ordering::Relaxed),
+            ),
+            (
+                "nsticky_sticky_windows",
+                "gauge",
+                "Number of sticky windows",
+                snapshot.sticky.len() as u64,
+            ),
+            (
+                "nsticky_staged_windows",
+                "gauge",
+                "Number of staged windows",
+                snapshot.staged.len() as u64,
+            ),
+            (
+                "nsticky_event_stream_connected",
+                "gauge",
+                "Whether the compositor event stream is connected",
+                METRICS.event_stream_connected.load(Ordering::Relaxed) as u64,
+            ),
+        ];
+
+        let mut body = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(body, "# HELP {} {}", name, help);
+            let _ = writeln!(body, "# TYPE {} {}", name, kind);
+            let _ = writeln!(body, "{} {}", name, value);
+        }
+        body
+    }
+
+    async fn handle_connection(stream: TcpStream, business_logic: BusinessLogic) -> Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let request = http::read_request(&mut BufReader::new(reader)).await?;
+
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/metrics") => {
+                let body = render(&business_logic).await;
+                http::write_response(&mut writer, "200 OK", "text/plain; version=0.0.4", &body)
+                    .await
+            }
+            _ => http::write_response(&mut writer, "404 Not Found", "text/plain", "").await,
+        }
+    }
+
+    /// Serve /metrics over HTTP on the given address
+    pub async fn run_server(addr: &str, business_logic: BusinessLogic) -> Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        tracing::info!("Metrics endpoint listening on http://{}/metrics", addr);
+        http::serve(listener, move |stream| {
+            handle_connection(stream, business_logic.clone())
+        })
+        .await
+    }
+}
+
+#[cfg(feature = "metrics")]
+pub use server::run_server;
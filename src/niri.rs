@@ -0,0 +1,354 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::process::Output;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixStream, unix::OwnedReadHalf},
+    process::Command,
+};
+
+use crate::logging;
+use crate::metrics::ErrorKind;
+use crate::system_integration::{CompositorEvent, WindowInfo, WorkspaceInfo};
+
+/// Parse a workspaces array as returned by `niri msg -j workspaces` or `WorkspacesChanged`
+fn parse_workspaces(json: &Value) -> Vec<WorkspaceInfo> {
+    let mut workspaces = Vec::new();
+    if let Some(arr) = json.as_array() {
+        for item in arr {
+            if let Some(id) = item.get("id").and_then(|v| v.as_u64()) {
+                workspaces.push(WorkspaceInfo {
+                    id,
+                    idx: item.get("idx").and_then(|v| v.as_u64()),
+                    name: item
+                        .get("name")
+                        .and_then(|v| v.as_str())
+                        .map(|s| s.to_string()),
+                    output: item
+                        .get("output")
+                        .and_then(|v| v.as_str())
+                        .map(|s| s.to_string()),
+                    is_active: item.get("is_active").and_then(|v| v.as_bool()) == Some(true),
+                    is_focused: item.get("is_focused").and_then(|v| v.as_bool()) == Some(true),
+                });
+            }
+        }
+    }
+    workspaces
+}
+
+/// Parse a window as returned by `niri msg -j windows` or window events
+fn parse_window(item: &Value) -> Option<WindowInfo> {
+    let id = item.get("id").and_then(|v| v.as_u64())?;
+    let app_id = item
+        .get("app_id")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+    let title = item
+        .get("title")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+    let workspace_id = item.get("workspace_id").and_then(|v| v.as_u64());
+    let flag = |key: &str| item.get(key).and_then(|v| v.as_bool()).unwrap_or(false);
+    Some(WindowInfo {
+        id,
+        app_id,
+        title,
+        workspace_id,
+        is_focused: flag("is_focused"),
+        is_floating: flag("is_floating"),
+        // niri's IPC has no fullscreen state
+        is_fullscreen: false,
+    })
+}
+
+/// Run `niri msg` with the given arguments
+async fn msg(args: &[&str]) -> Result<Output> {
+    logging::trace_ipc(">", "niri msg", args.join(" ").as_bytes());
+    let output = Command::new("niri").arg("msg").args(args).output().await?;
+    logging::trace_ipc("<", "niri msg", &output.stdout);
+    Ok(output)
+}
+
+/// Get all workspaces from Niri
+pub async fn get_workspaces() -> Result<Vec<WorkspaceInfo>> {
+    let output = msg(&["-j", "workspaces"]).await?;
+
+    if !output.status.success() {
+        anyhow::bail!("Failed to get workspaces");
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let json: Value = serde_json::from_str(&stdout)?;
+    Ok(parse_workspaces(&json))
+}
+
+/// Get active window ID from Niri
+pub async fn get_active_window_id() -> Result<u64> {
+    let output = msg(&["--json", "focused-window"]).await?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to get focused window");
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let json: serde_json::Value = serde_json::from_str(&stdout)?;
+    if let Some(id) = json.get("id").and_then(|v| v.as_u64()) {
+        Ok(id)
+    } else {
+        anyhow::bail!("Focused window id not found");
+    }
+}
+
+/// Get full window information from Niri
+pub async fn get_full_window_info() -> Result<Vec<WindowInfo>> {
+    let output = msg(&["--json", "windows"]).await?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to get windows list");
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let json: Value = serde_json::from_str(&stdout)?;
+    let mut windows = Vec::new();
+    if let Some(arr) = json.as_array() {
+        windows.extend(arr.iter().filter_map(parse_window));
+    }
+    Ok(windows)
+}
+
+/// Let the user click a window, None when picking was cancelled
+pub async fn pick_window() -> Result<Option<u64>> {
+    let output = msg(&["--json", "pick-window"]).await?;
+    if !output.status.success() {
+        anyhow::bail!("niri msg pick-window failed, picking windows needs niri 25.05 or newer");
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let json: Value = serde_json::from_str(&stdout)?;
+    Ok(json.get("id").and_then(|v| v.as_u64()))
+}
+
+/// Whether niri's socket accepts connections, false while niri reloads or restarts
+pub async fn is_reachable() -> bool {
+    match std::env::var("NIRI_SOCKET") {
+        Ok(socket_path) => UnixStream::connect(&socket_path).await.is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Send an action request to Niri and return its raw response, an action niri refused is an error
+async fn send_action(action: Value) -> Result<String> {
+    let result = send_action_inner(action).await;
+    crate::metrics::record_move();
+    // niri answers {"Ok": ...} or {"Err": ...}
+    match result {
+        Ok(response) if response.starts_with("{\"Err\"") => {
+            crate::metrics::record_error(ErrorKind::MoveFailed, &response);
+            anyhow::bail!("niri refused the action: {}", response)
+        }
+        Ok(response) => Ok(response),
+        Err(e) => {
+            crate::metrics::record_error(ErrorKind::MoveFailed, &e);
+            Err(e)
+        }
+    }
+}
+
+async fn send_action_inner(action: Value) -> Result<String> {
+    let socket_path = std::env::var("NIRI_SOCKET")?;
+
+    let stream = UnixStream::connect(&socket_path).await?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    let cmd = json!({ "Action": action });
+    let cmd_str = serde_json::to_string(&cmd)? + "\n";
+    tracing::debug!(request = cmd_str.trim_end(), "niri request");
+
+    logging::trace_ipc(">", "niri", cmd_str.as_bytes());
+    writer.write_all(cmd_str.as_bytes()).await?;
+    writer.flush().await?;
+
+    let mut response = String::new();
+    reader.read_line(&mut response).await?;
+    logging::trace_ipc("<", "niri", response.as_bytes());
+    tracing::debug!(response = response.trim(), "niri response");
+    Ok(response.trim().to_string())
+}
+
+/// Move window to workspace
+pub async fn move_to_workspace(win_id: u64, ws_id: u64) -> Result<()> {
+    let response = send_action(json!({
+        "MoveWindowToWorkspace": {
+            "window_id": win_id,
+            "focus": false,
+            "reference": { "Id": ws_id }
+        }
+    }))
+    .await?;
+    tracing::info!(
+        operation = "move_to_workspace",
+        window_id = win_id,
+        workspace_id = ws_id,
+        "move_to_workspace response: {}",
+        response
+    );
+    Ok(())
+}
+
+/// Move window to named workspace
+pub async fn move_to_named_workspace(win_id: u64, workspace_name: &str) -> Result<()> {
+    let response = send_action(json!({
+        "MoveWindowToWorkspace": {
+            "window_id": win_id,
+            "focus": false,
+            "reference": { "Name": workspace_name }
+        }
+    }))
+    .await?;
+    tracing::info!(
+        operation = "move_to_named_workspace",
+        window_id = win_id,
+        "move_to_named_workspace {} response: {}",
+        workspace_name,
+        response
+    );
+    Ok(())
+}
+
+/// Make a window floating
+pub async fn move_to_floating(win_id: u64) -> Result<()> {
+    let response = send_action(json!({
+        "MoveWindowToFloating": {
+            "id": win_id
+        }
+    }))
+    .await?;
+    tracing::info!(
+        operation = "move_to_floating",
+        window_id = win_id,
+        "move_to_floating response: {}",
+        response
+    );
+    Ok(())
+}
+
+/// Focus a window
+pub async fn focus_window(win_id: u64) -> Result<()> {
+    let response = send_action(json!({
+        "FocusWindow": {
+            "id": win_id
+        }
+    }))
+    .await?;
+    tracing::info!(
+        operation = "focus_window",
+        window_id = win_id,
+        "focus_window response: {}",
+        response
+    );
+    Ok(())
+}
+
+/// Move window to a workspace, into the column right of `anchor_id`
+/// niri puts moved windows next to the active column, so the anchor is focused first
+pub async fn move_next_to(win_id: u64, anchor_id: u64, ws_id: u64) -> Result<()> {
+    focus_window(anchor_id).await?;
+    move_to_workspace(win_id, ws_id).await
+}
+
+/// Move window to the active workspace of another output
+pub async fn move_to_output(win_id: u64, output_name: &str) -> Result<()> {
+    let response = send_action(json!({
+        "MoveWindowToMonitor": {
+            "id": win_id,
+            "output": output_name
+        }
+    }))
+    .await?;
+    tracing::info!(
+        operation = "move_to_output",
+        window_id = win_id,
+        "move_to_output {} response: {}",
+        output_name,
+        response
+    );
+    Ok(())
+}
+
+/// Niri event stream, one JSON event per line
+pub struct EventStream {
+    reader: BufReader<OwnedReadHalf>,
+    line: String,
+}
+
+impl EventStream {
+    pub async fn connect() -> Result<Self> {
+        let socket_path = std::env::var("NIRI_SOCKET")
+            .map_err(|_| anyhow::anyhow!("NIRI_SOCKET env var not set"))?;
+        let stream = UnixStream::connect(&socket_path).await?;
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+
+        logging::trace_ipc(">", "niri events", b"\"EventStream\"\n");
+        writer.write_all(b"\"EventStream\"\n").await?;
+        writer.flush().await?;
+
+        // Niri acknowledges the request before streaming events
+        let mut line = String::new();
+        reader.read_line(&mut line).await?;
+        logging::trace_ipc("<", "niri events", line.as_bytes());
+        line.clear();
+        Ok(Self { reader, line })
+    }
+
+    /// Next event from niri, None once the stream ends
+    /// Events nsticky doesn't care about come back as an empty list
+    pub async fn next(&mut self) -> Result<Option<Vec<CompositorEvent>>> {
+        self.line.clear();
+        if self.reader.read_line(&mut self.line).await? == 0 {
+            return Ok(None);
+        }
+        logging::trace_ipc("<", "niri events", self.line.as_bytes());
+        tracing::trace!(event = self.line.trim_end(), "niri event");
+        let v = match serde_json::from_str::<Value>(&self.line) {
+            Ok(v) => v,
+            Err(e) => {
+                crate::metrics::record_error(
+                    ErrorKind::EventParse,
+                    format_args!("niri event: {}", e),
+                );
+                return Ok(Some(Vec::new()));
+            }
+        };
+
+        let event = if let Some(changed) = v.get("WorkspacesChanged")
+            && let Some(workspaces) = changed.get("workspaces")
+        {
+            Some(CompositorEvent::WorkspacesChanged(parse_workspaces(
+                workspaces,
+            )))
+        } else if let Some(ws) = v.get("WorkspaceActivated")
+            && let Some(ws_id) = ws.get("id").and_then(|id| id.as_u64())
+        {
+            Some(CompositorEvent::WorkspaceActivated(ws_id))
+        } else if let Some(focus) = v.get("WindowFocusChanged") {
+            Some(CompositorEvent::WindowFocusChanged(
+                focus.get("id").and_then(|id| id.as_u64()),
+            ))
+        } else if let Some(changed) = v.get("WindowsChanged")
+            && let Some(windows) = changed.get("windows").and_then(|w| w.as_array())
+        {
+            Some(CompositorEvent::WindowsChanged(
+                windows.iter().filter_map(parse_window).collect(),
+            ))
+        } else if let Some(opened) = v.get("WindowOpenedOrChanged")
+            && let Some(window) = opened.get("window").and_then(parse_window)
+        {
+            Some(CompositorEvent::WindowOpenedOrChanged(window))
+        } else if let Some(closed) = v.get("WindowClosed")
+            && let Some(id) = closed.get("id").and_then(|id| id.as_u64())
+        {
+            Some(CompositorEvent::WindowClosed(id))
+        } else {
+            None
+        };
+        Ok(Some(event.into_iter().collect()))
+    }
+}
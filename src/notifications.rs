@@ -0,0 +1,143 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use tokio::sync::broadcast;
+use zbus::{proxy, zvariant::Value};
+
+use crate::{
+    business::{BusinessLogic, StateChanges},
+    config::NotificationsConfig,
+};
+
+#[proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+}
+
+/// Describe a batch of windows, by name when there is only one
+fn describe(ids: &[u64], names: &HashMap<u64, String>, single: &str, many: &str) -> String {
+    match ids {
+        [id] => format!(
+            "{} {}",
+            names
+                .get(id)
+                .cloned()
+                .unwrap_or_else(|| format!("Window {}", id)),
+            single
+        ),
+        _ => format!("{} windows {}", ids.len(), many),
+    }
+}
+
+/// Build notification texts from a batch of state changes
+fn changes_to_messages(
+    changes: &StateChanges,
+    existing: &HashMap<u64, String>,
+    config: &NotificationsConfig,
+) -> Vec<String> {
+    let mut messages = Vec::new();
+    if config.sticky && !changes.stuck.is_empty() {
+        messages.push(describe(
+            &changes.stuck,
+            existing,
+            "is now sticky",
+            "are now sticky",
+        ));
+    }
+    if config.sticky && !changes.unstuck.is_empty() {
+        messages.push(describe(
+            &changes.unstuck,
+            existing,
+            "is no longer sticky",
+            "are no longer sticky",
+        ));
+    }
+    if config.stage && !changes.staged.is_empty() {
+        messages.push(describe(&changes.staged, existing, "staged", "staged"));
+    }
+    if config.stage && !changes.unstaged.is_empty() {
+        messages.push(describe(
+            &changes.unstaged,
+            existing,
+            "unstaged",
+            "unstaged",
+        ));
+    }
+    messages
+}
+
+/// Send desktop notifications whenever the sticky or staged set changes
+pub async fn run_notifier(
+    business_logic: BusinessLogic,
+    config: NotificationsConfig,
+) -> Result<()> {
+    let connection = zbus::Connection::session().await?;
+    let proxy = NotificationsProxy::new(&connection).await?;
+    let mut changes = business_logic.subscribe();
+
+    let mut last = business_logic.snapshot().await;
+    // Replace our previous notification instead of stacking them up
+    let mut replaces_id = 0;
+    loop {
+        match changes.recv().await {
+            Ok(()) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        }
+
+        let snapshot = business_logic.snapshot().await;
+        if snapshot.sticky == last.sticky && snapshot.staged == last.staged {
+            continue;
+        }
+
+        let names: HashMap<u64, String> = crate::system_integration::get_full_window_info()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(|w| {
+                let name = w
+                    .app_id
+                    .or(w.title)
+                    .unwrap_or_else(|| format!("Window {}", w.id));
+                (w.id, name)
+            })
+            .collect();
+
+        let changes = StateChanges::between(&last, &snapshot, |id| names.contains_key(&id));
+        let messages = changes_to_messages(&changes, &names, &config);
+        last = snapshot;
+        if messages.is_empty() {
+            continue;
+        }
+
+        match proxy
+            .notify(
+                "nsticky",
+                replaces_id,
+                "",
+                &messages.join("\n"),
+                "",
+                &[],
+                HashMap::new(),
+                config.timeout_ms,
+            )
+            .await
+        {
+            Ok(id) => replaces_id = id,
+            Err(e) => tracing::warn!("Failed to send notification: {e:?}"),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use crate::config::OfflineConfig;
+
+/// How often a held request checks whether the compositor is back
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+static CONFIG: OnceLock<OfflineConfig> = OnceLock::new();
+
+/// Held requests take this in arrival order, so they replay in the order they were sent
+static QUEUE: Mutex<()> = Mutex::const_new(());
+
+/// Requests waiting for the compositor, including the one at the head of the queue
+static QUEUED: AtomicUsize = AtomicUsize::new(0);
+
+/// Hold state-changing requests while the compositor is unreachable, only the first call has an effect
+pub fn configure(config: OfflineConfig) {
+    let _ = CONFIG.set(config);
+}
+
+/// Requests currently held until the compositor is reachable again
+pub fn queued() -> usize {
+    QUEUED.load(Ordering::Relaxed)
+}
+
+/// Wait until the compositor is reachable, behind any request held before this one
+/// Errors once the queue is full or the compositor stays away longer than `queue_secs`
+pub async fn wait_for_compositor() -> Result<(), String> {
+    let Some(config) = CONFIG.get().filter(|config| config.queue_secs > 0) else {
+        return Ok(());
+    };
+    // Fast path, nothing is held and the compositor answers
+    if QUEUED.load(Ordering::Relaxed) == 0 && crate::system_integration::is_reachable().await {
+        return Ok(());
+    }
+    if QUEUED.fetch_add(1, Ordering::Relaxed) >= config.max_queued {
+        QUEUED.fetch_sub(1, Ordering::Relaxed);
+        return Err(format!(
+            "Compositor unreachable and {} requests are already queued",
+            config.max_queued
+        ));
+    }
+
+    let limit = Duration::from_secs(config.queue_secs);
+    let deadline = Instant::now() + limit;
+    let result = async {
+        let _turn = tokio::time::timeout_at(deadline, QUEUE.lock())
+            .await
+            .map_err(|_| ())?;
+        let mut logged = false;
+        loop {
+            if crate::system_integration::is_reachable().await {
+                if logged {
+                    tracing::info!("Compositor reachable again, replaying queued request");
+                }
+                return Ok(());
+            }
+            if !logged {
+                tracing::info!(
+                    queued = queued(),
+                    "Compositor unreachable, queueing request"
+                );
+                logged = true;
+            }
+            if Instant::now() + POLL_INTERVAL > deadline {
+                return Err(());
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+    .await;
+    QUEUED.fetch_sub(1, Ordering::Relaxed);
+
+    result.map_err(|()| {
+        format!(
+            "Compositor unreachable for {}s, request dropped",
+            limit.as_secs()
+        )
+    })
+}
@@ -0,0 +1,421 @@
+use anyhow::Result;
+use smithay_client_toolkit::{
+    compositor::{CompositorHandler, CompositorState, Region},
+    delegate_registry,
+    output::{OutputHandler, OutputState},
+    reexports::client::{
+        Connection, EventQueue, QueueHandle,
+        globals::registry_queue_init,
+        protocol::{wl_output, wl_shm, wl_surface},
+    },
+    registry::{ProvidesRegistryState, RegistryState},
+    registry_handlers,
+    shell::{
+        WaylandSurface,
+        wlr_layer::{
+            Anchor, KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface,
+            LayerSurfaceConfigure,
+        },
+    },
+    shm::{Shm, ShmHandler, slot::SlotPool},
+};
+use std::collections::HashSet;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+use crate::{
+    business::{BusinessLogic, StateChanges},
+    config::OsdConfig,
+};
+
+/// Size of one font pixel on screen
+const SCALE: u32 = 4;
+const PADDING: u32 = 16;
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+/// Premultiplied ARGB colors
+const BACKGROUND: u32 = 0xE01A_1A1A;
+const FOREGROUND: u32 = 0xFFFF_FFFF;
+
+/// 5x7 bitmap of a character, one byte per row, low 5 bits used
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_lowercase() {
+        'a' => [0x00, 0x00, 0x0E, 0x01, 0x0F, 0x11, 0x0F],
+        'b' => [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1E],
+        'c' => [0x00, 0x00, 0x0E, 0x10, 0x10, 0x11, 0x0E],
+        'd' => [0x01, 0x01, 0x0D, 0x13, 0x11, 0x11, 0x0F],
+        'e' => [0x00, 0x00, 0x0E, 0x11, 0x1F, 0x10, 0x0E],
+        'f' => [0x06, 0x09, 0x08, 0x1C, 0x08, 0x08, 0x08],
+        'g' => [0x00, 0x0F, 0x11, 0x11, 0x0F, 0x01, 0x0E],
+        'h' => [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11],
+        'i' => [0x04, 0x00, 0x0C, 0x04, 0x04, 0x04, 0x0E],
+        'j' => [0x02, 0x00, 0x06, 0x02, 0x02, 0x12, 0x0C],
+        'k' => [0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12],
+        'l' => [0x0C, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'm' => [0x00, 0x00, 0x1A, 0x15, 0x15, 0x11, 0x11],
+        'n' => [0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11],
+        'o' => [0x00, 0x00, 0x0E, 0x11, 0x11, 0x11, 0x0E],
+        'p' => [0x00, 0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10],
+        'q' => [0x00, 0x0F, 0x11, 0x11, 0x0F, 0x01, 0x01],
+        'r' => [0x00, 0x00, 0x16, 0x19, 0x10, 0x10, 0x10],
+        's' => [0x00, 0x00, 0x0E, 0x10, 0x0E, 0x01, 0x1E],
+        't' => [0x08, 0x08, 0x1C, 0x08, 0x08, 0x09, 0x06],
+        'u' => [0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0D],
+        'v' => [0x00, 0x00, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'w' => [0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0A],
+        'x' => [0x00, 0x00, 0x11, 0x0A, 0x04, 0x0A, 0x11],
+        'y' => [0x00, 0x11, 0x11, 0x0F, 0x01, 0x11, 0x0E],
+        'z' => [0x00, 0x00, 0x1F, 0x02, 0x04, 0x08, 0x1F],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '📌' => [0x0E, 0x0E, 0x0E, 0x1F, 0x04, 0x04, 0x04],
+        '⬇' => [0x04, 0x04, 0x04, 0x15, 0x0E, 0x04, 0x00],
+        _ => [0; 7],
+    }
+}
+
+/// Surface size needed to show a text
+fn text_size(text: &str) -> (u32, u32) {
+    let chars = text.chars().count().max(1) as u32;
+    let width = PADDING * 2 + (chars * (GLYPH_WIDTH + 1) - 1) * SCALE;
+    let height = PADDING * 2 + GLYPH_HEIGHT * SCALE;
+    (width, height)
+}
+
+/// Short OSD text for a batch of state changes
+fn changes_to_text(changes: &StateChanges) -> Option<String> {
+    let describe = |ids: &[u64], marker: &str, what: &str| match ids.len() {
+        0 => None,
+        1 => Some(format!("{}{}", marker, what)),
+        n => Some(format!("{}{} {}", marker, n, what)),
+    };
+    let parts: Vec<String> = [
+        describe(&changes.stuck, "📌 ", "pinned"),
+        describe(&changes.unstuck, "", "unpinned"),
+        describe(&changes.staged, "⬇ ", "staged"),
+        describe(&changes.unstaged, "", "unstaged"),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join("  "))
+    }
+}
+
+/// Wayland side of the OSD, owns one layer surface while something is shown
+struct Osd {
+    registry_state: RegistryState,
+    output_state: OutputState,
+    compositor: CompositorState,
+    layer_shell: LayerShell,
+    shm: Shm,
+    pool: Option<SlotPool>,
+    layer: Option<LayerSurface>,
+    configured: bool,
+    text: String,
+}
+
+impl Osd {
+    fn show(&mut self, text: String, queue: &mut EventQueue<Self>) -> Result<()> {
+        let qh = queue.handle();
+        let (width, height) = text_size(&text);
+        self.text = text;
+
+        // A fresh surface per message, its size depends on the text
+        self.layer = None;
+        let surface = self.compositor.create_surface(&qh);
+        // Empty input region, clicks go through to the windows below
+        let region = Region::new(&self.compositor)?;
+        surface.set_input_region(Some(region.wl_region()));
+        let layer = self.layer_shell.create_layer_surface(
+            &qh,
+            surface,
+            Layer::Overlay,
+            Some("nsticky-osd"),
+            None,
+        );
+        layer.set_anchor(Anchor::BOTTOM);
+        layer.set_margin(0, 0, 96, 0);
+        layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+        layer.set_size(width, height);
+        layer.commit();
+        self.layer = Some(layer);
+        self.configured = false;
+
+        while !self.configured && self.layer.is_some() {
+            queue.blocking_dispatch(self)?;
+        }
+        queue.flush()?;
+        Ok(())
+    }
+
+    fn hide(&mut self, queue: &mut EventQueue<Self>) -> Result<()> {
+        self.layer = None;
+        queue.roundtrip(self)?;
+        Ok(())
+    }
+
+    fn draw(&mut self, width: u32, height: u32) -> Result<()> {
+        let Some(layer) = &self.layer else {
+            return Ok(());
+        };
+        let stride = width as i32 * 4;
+        let pool = match &mut self.pool {
+            Some(pool) => pool,
+            None => self
+                .pool
+                .insert(SlotPool::new((stride * height as i32) as usize, &self.shm)?),
+        };
+        let (buffer, canvas) = pool.create_buffer(
+            width as i32,
+            height as i32,
+            stride,
+            wl_shm::Format::Argb8888,
+        )?;
+
+        for pixel in canvas.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&BACKGROUND.to_le_bytes());
+        }
+        for (i, c) in self.text.chars().enumerate() {
+            let origin_x = PADDING + i as u32 * (GLYPH_WIDTH + 1) * SCALE;
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for col in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                        continue;
+                    }
+                    for dy in 0..SCALE {
+                        for dx in 0..SCALE {
+                            let x = origin_x + col * SCALE + dx;
+                            let y = PADDING + row as u32 * SCALE + dy;
+                            if x >= width || y >= height {
+                                continue;
+                            }
+                            let offset = ((y * width + x) * 4) as usize;
+                            canvas[offset..offset + 4].copy_from_slice(&FOREGROUND.to_le_bytes());
+                        }
+                    }
+                }
+            }
+        }
+
+        layer
+            .wl_surface()
+            .damage_buffer(0, 0, width as i32, height as i32);
+        buffer.attach_to(layer.wl_surface())?;
+        layer.commit();
+        Ok(())
+    }
+}
+
+impl CompositorHandler for Osd {
+    fn scale_factor_changed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _new_factor: i32,
+    ) {
+    }
+
+    fn transform_changed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _new_transform: wl_output::Transform,
+    ) {
+    }
+
+    fn frame(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _time: u32,
+    ) {
+    }
+
+    fn surface_enter(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _output: &wl_output::WlOutput,
+    ) {
+    }
+
+    fn surface_leave(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _output: &wl_output::WlOutput,
+    ) {
+    }
+}
+
+impl OutputHandler for Osd {
+    fn output_state(&mut self) -> &mut OutputState {
+        &mut self.output_state
+    }
+
+    fn new_output(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _output: wl_output::WlOutput,
+    ) {
+    }
+
+    fn update_output(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _output: wl_output::WlOutput,
+    ) {
+    }
+
+    fn output_destroyed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _output: wl_output::WlOutput,
+    ) {
+    }
+}
+
+impl LayerShellHandler for Osd {
+    fn closed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _layer: &LayerSurface) {
+        self.layer = None;
+    }
+
+    fn configure(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _layer: &LayerSurface,
+        configure: LayerSurfaceConfigure,
+        _serial: u32,
+    ) {
+        let (default_width, default_height) = text_size(&self.text);
+        let width = if configure.new_size.0 == 0 {
+            default_width
+        } else {
+            configure.new_size.0
+        };
+        let height = if configure.new_size.1 == 0 {
+            default_height
+        } else {
+            configure.new_size.1
+        };
+        self.configured = true;
+        if let Err(_e) = self.draw(width, height) {
+            tracing::warn!("Failed to draw OSD: {_e:?}");
+        }
+    }
+}
+
+impl ShmHandler for Osd {
+    fn shm_state(&mut self) -> &mut Shm {
+        &mut self.shm
+    }
+}
+
+delegate_registry!(Osd);
+
+impl ProvidesRegistryState for Osd {
+    fn registry(&mut self) -> &mut RegistryState {
+        &mut self.registry_state
+    }
+    registry_handlers![OutputState];
+}
+
+smithay_client_toolkit::delegate_dispatch2!(Osd);
+
+/// Show each received text for `timeout`, runs on its own thread since Wayland dispatch blocks
+fn run_overlay(texts: mpsc::Receiver<String>, timeout: Duration) -> Result<()> {
+    let conn = Connection::connect_to_env()?;
+    let (globals, mut queue) = registry_queue_init::<Osd>(&conn)?;
+    let qh = queue.handle();
+
+    let mut osd = Osd {
+        registry_state: RegistryState::new(&globals),
+        output_state: OutputState::new(&globals, &qh),
+        compositor: CompositorState::bind(&globals, &qh)?,
+        layer_shell: LayerShell::bind(&globals, &qh)?,
+        shm: Shm::bind(&globals, &qh)?,
+        pool: None,
+        layer: None,
+        configured: false,
+        text: String::new(),
+    };
+
+    // Wait for something to show, then keep it up until no new text arrives for `timeout`
+    while let Ok(text) = texts.recv() {
+        osd.show(text, &mut queue)?;
+        loop {
+            match texts.recv_timeout(timeout) {
+                Ok(text) => osd.show(text, &mut queue)?,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        }
+        osd.hide(&mut queue)?;
+    }
+    Ok(())
+}
+
+/// Flash a short overlay whenever windows are pinned, unpinned, staged or unstaged
+pub async fn run_osd(business_logic: BusinessLogic, config: OsdConfig) -> Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let timeout = Duration::from_millis(config.timeout_ms);
+    std::thread::spawn(move || {
+        if let Err(_e) = run_overlay(rx, timeout) {
+            tracing::error!("OSD error: {_e:?}");
+        }
+    });
+
+    let mut changes = business_logic.subscribe();
+    let mut last = business_logic.snapshot().await;
+    loop {
+        match changes.recv().await {
+            Ok(()) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        }
+
+        let snapshot = business_logic.snapshot().await;
+        if snapshot.sticky == last.sticky && snapshot.staged == last.staged {
+            continue;
+        }
+
+        let open: HashSet<u64> = crate::system_integration::get_full_window_info()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(|w| w.id)
+            .collect();
+        let state_changes = StateChanges::between(&last, &snapshot, |id| open.contains(&id));
+        last = snapshot;
+
+        if let Some(text) = changes_to_text(&state_changes)
+            && tx.send(text).is_err()
+        {
+            // The overlay thread gave up, e.g. no layer-shell support
+            return Ok(());
+        }
+    }
+}
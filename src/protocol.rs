@@ -1,16 +1,264 @@
 use anyhow::Result;
 
+use crate::business::{DaemonStatus, SetMode, TrackedWindow};
+use crate::config::FollowMode;
+use crate::rules::{Action, Outcome, Rule, RuleSource};
+use crate::stats::{AppStats, NO_APP_ID, UsageStats};
+use crate::system_integration::{WindowInfo, WindowSelector, WorkspaceInfo};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+
 /// Define request types
 #[derive(Debug)]
 pub enum Request {
-    Add { window_id: u64 },
-    Remove { window_id: u64 },
-    List,
+    Add {
+        window_id: u64,
+        /// Remove the window again after this long
+        duration: Option<Duration>,
+    },
+    Remove {
+        window_id: u64,
+        /// Move the window back to its home workspace, the configured default when None
+        home: Option<bool>,
+    },
+    List {
+        detailed: bool,
+        format: OutputFormat,
+    },
     ToggleActive,
-    ToggleAppid { appid: String },
-    ToggleTitle { title: String },
+    ToggleAppid {
+        appid: String,
+    },
+    ToggleTitle {
+        title: String,
+    },
     Stage(StageArgs),
     Unstage(UnstageArgs),
+    SetOutput {
+        window_id: u64,
+        output: String,
+    },
+    MoveOutput {
+        window_id: u64,
+        output: String,
+    },
+    Mode {
+        mode: Option<FollowMode>,
+        primary_output: Option<String>,
+    },
+    Subscribe,
+    Pause {
+        output: Option<String>,
+    },
+    Resume {
+        output: Option<String>,
+    },
+    Idle {
+        idle: bool,
+    },
+    RuleAdd {
+        rule: Rule,
+    },
+    RuleList {
+        format: OutputFormat,
+    },
+    RuleRemove {
+        id: usize,
+    },
+    RuleTest {
+        window_id: u64,
+    },
+    Status {
+        format: OutputFormat,
+        /// One line for shell prompts instead of the full status
+        short: bool,
+    },
+    Stats {
+        format: OutputFormat,
+    },
+    Undo,
+    Bring {
+        selector: WindowSelector,
+    },
+    TraceIpc {
+        enabled: Option<bool>,
+    },
+    FocusMode {
+        enabled: Option<bool>,
+    },
+    Attach {
+        follower: u64,
+        leader: u64,
+    },
+    Detach {
+        follower: u64,
+    },
+    /// Unstage one window and stage another in its place, both or neither
+    Swap {
+        staged_id: u64,
+        sticky_id: u64,
+    },
+    /// Mark a window (the focused one when None) so sticky-follow leaves it in place, or clear the mark
+    Protect {
+        window_id: Option<u64>,
+        protect: bool,
+    },
+    GroupCreate {
+        name: String,
+        window_ids: Vec<u64>,
+    },
+    GroupRemove {
+        name: String,
+    },
+    GroupList {
+        format: OutputFormat,
+    },
+    SlotSet {
+        slot: u32,
+        window_id: Option<u64>,
+    },
+    SlotClear {
+        slot: u32,
+    },
+    SlotList {
+        format: OutputFormat,
+    },
+    FocusSlot {
+        slot: u32,
+    },
+    Pick,
+    /// Remove every window from the sticky list
+    Clear {
+        dry_run: bool,
+    },
+    /// Set the label of a tracked window, or remove it when None
+    Label {
+        window_id: u64,
+        label: Option<String>,
+    },
+    /// Look up the window a label belongs to
+    Resolve {
+        label: String,
+    },
+    /// Wait for a window to open and apply an action to it
+    WaitFor {
+        selector: WindowSelector,
+        action: Action,
+        timeout: Duration,
+    },
+    /// Reconcile the sticky list with a batch of windows in one step
+    Set {
+        mode: SetMode,
+        selectors: Vec<WindowSelector>,
+    },
+    /// Run a stage/unstage request and only answer once the windows arrived
+    Wait {
+        request: Box<Request>,
+    },
+}
+
+impl Request {
+    /// Requests that change sticky or staged windows and can be reverted with `undo`
+    pub fn is_undoable(&self) -> bool {
+        match self {
+            Request::Wait { request } => request.is_undoable(),
+            Request::Stage(args) => !args.list && !args.dry_run,
+            Request::Unstage(args) => !args.dry_run,
+            Request::Clear { dry_run } => !dry_run,
+            Request::Add { .. }
+            | Request::Remove { .. }
+            | Request::ToggleActive
+            | Request::ToggleAppid { .. }
+            | Request::ToggleTitle { .. }
+            | Request::Swap { .. }
+            | Request::Set { .. }
+            | Request::Bring { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Undoable requests that move windows between workspaces
+    pub fn moves_windows(&self) -> bool {
+        match self {
+            Request::Wait { request } => request.moves_windows(),
+            _ => matches!(
+                self,
+                Request::Stage(StageArgs {
+                    list: false,
+                    dry_run: false,
+                    ..
+                }) | Request::Unstage(UnstageArgs { dry_run: false, .. })
+                    | Request::Swap { .. }
+            ),
+        }
+    }
+
+    /// Requests that only report state, left out of the audit log
+    pub fn is_read_only(&self) -> bool {
+        if let Request::Wait { request } = self {
+            return request.is_read_only();
+        }
+        matches!(
+            self,
+            Request::List { .. }
+                | Request::Stage(StageArgs { list: true, .. })
+                | Request::Stage(StageArgs { dry_run: true, .. })
+                | Request::Unstage(UnstageArgs { dry_run: true, .. })
+                | Request::Clear { dry_run: true }
+                | Request::Mode { mode: None, .. }
+                | Request::Subscribe
+                | Request::RuleList { .. }
+                | Request::RuleTest { .. }
+                | Request::Status { .. }
+                | Request::Stats { .. }
+                | Request::TraceIpc { enabled: None }
+                | Request::FocusMode { enabled: None }
+                | Request::GroupList { .. }
+                | Request::SlotList { .. }
+                | Request::Pick
+                | Request::Resolve { .. }
+        )
+    }
+
+    /// Requests the read-only socket serves, pick is left out since it grabs the pointer
+    pub fn is_observing(&self) -> bool {
+        self.is_read_only() && !matches!(self, Request::Pick)
+    }
+}
+
+/// Rendering of list-type responses
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Text meant for people
+    #[default]
+    Plain,
+    /// A single JSON document, for jq
+    Json,
+    /// Tab-separated rows without a header, for awk and cut
+    Tsv,
+}
+
+impl OutputFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutputFormat::Plain => "plain",
+            OutputFormat::Json => "json",
+            OutputFormat::Tsv => "tsv",
+        }
+    }
+
+    fn parse(name: &str) -> Result<Self> {
+        match name {
+            "plain" => Ok(OutputFormat::Plain),
+            "json" => Ok(OutputFormat::Json),
+            "tsv" => Ok(OutputFormat::Tsv),
+            _ => Err(anyhow::anyhow!(
+                "Invalid output format {}, expected plain, json or tsv",
+                name
+            )),
+        }
+    }
 }
 
 #[derive(Debug, Default)]
@@ -18,9 +266,14 @@ pub struct StageArgs {
     pub window_id: Option<u64>,
     pub all: bool,
     pub list: bool,
+    pub detailed: bool,
     pub active: bool,
     pub appid: Option<String>,
     pub title: Option<String>,
+    /// Only report which windows would move
+    pub dry_run: bool,
+    /// Rendering of `list`
+    pub format: OutputFormat,
 }
 
 #[allow(dead_code)]
@@ -31,6 +284,8 @@ pub struct UnstageArgs {
     pub active: bool,
     pub appid: Option<String>,
     pub title: Option<String>,
+    /// Only report which windows would move
+    pub dry_run: bool,
 }
 
 #[derive(Debug)]
@@ -40,6 +295,123 @@ pub enum Response {
     Data(String),
 }
 
+/// Parse `<id>`, `--appid <appid>` or `--title <title...>`
+fn parse_selector<'a>(parts: &mut impl Iterator<Item = &'a str>) -> Result<WindowSelector> {
+    match parts.next() {
+        Some("--appid") => match parts.next() {
+            Some(appid) => Ok(WindowSelector::AppId(appid.to_string())),
+            None => Err(anyhow::anyhow!("Missing appid")),
+        },
+        Some("--title") => {
+            // Title may contain spaces, join remaining parts
+            let title = parts.collect::<Vec<_>>().join(" ");
+            if title.is_empty() {
+                Err(anyhow::anyhow!("Missing title"))
+            } else {
+                Ok(WindowSelector::Title(title))
+            }
+        }
+        Some(id_str) => id_str
+            .parse::<u64>()
+            .map(WindowSelector::Id)
+            .map_err(|_| anyhow::anyhow!("Invalid window id")),
+        None => Err(anyhow::anyhow!("Missing window id")),
+    }
+}
+
+/// Parse the flags of list-type commands, `--detailed` where allowed and `--format <plain|json|tsv>`
+fn parse_list_flags<'a>(
+    parts: &mut impl Iterator<Item = &'a str>,
+    detailed_allowed: bool,
+) -> Result<(bool, OutputFormat)> {
+    let (mut detailed, mut format) = (false, OutputFormat::Plain);
+    while let Some(arg) = parts.next() {
+        match arg {
+            "--detailed" if detailed_allowed => detailed = true,
+            "--format" => {
+                let name = parts
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("Missing output format"))?;
+                format = OutputFormat::parse(name)?;
+            }
+            _ => return Err(anyhow::anyhow!("Invalid argument: {}", arg)),
+        }
+    }
+    Ok((detailed, format))
+}
+
+/// Parse an optional trailing `--dry-run`
+fn parse_dry_run<'a>(parts: &mut impl Iterator<Item = &'a str>) -> Result<bool> {
+    match parts.next() {
+        None => Ok(false),
+        Some("--dry-run") => Ok(true),
+        Some(arg) => Err(anyhow::anyhow!("Invalid argument: {}", arg)),
+    }
+}
+
+/// Parse a slot number
+fn parse_slot<'a>(parts: &mut impl Iterator<Item = &'a str>) -> Result<u32> {
+    match parts.next() {
+        Some(slot) => slot
+            .parse::<u32>()
+            .map_err(|_| anyhow::anyhow!("Invalid slot number")),
+        None => Err(anyhow::anyhow!("Missing slot number")),
+    }
+}
+
+/// Parse a duration such as `90s`, `45m` or `1h30m`, a bare number counts as seconds
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let invalid = || anyhow::anyhow!("Invalid duration: {} (expected e.g. 90s, 45m, 1h30m)", s);
+    if let Ok(secs) = s.parse::<u64>() {
+        return match secs {
+            0 => Err(invalid()),
+            secs => Ok(Duration::from_secs(secs)),
+        };
+    }
+
+    let mut total = 0u64;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(invalid)?;
+        let value: u64 = rest[..digits].parse().map_err(|_| invalid())?;
+        let unit = match rest[digits..].chars().next() {
+            Some('s') => 1,
+            Some('m') => 60,
+            Some('h') => 3600,
+            Some('d') => 86400,
+            _ => return Err(invalid()),
+        };
+        total = value
+            .checked_mul(unit)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or_else(invalid)?;
+        rest = &rest[digits + 1..];
+    }
+    if total == 0 {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(total))
+}
+
+/// Format a duration the way parse_duration reads it, e.g. `1h30m` or `45s`
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs % 3600 / 60, secs % 60);
+    let mut out = String::new();
+    if hours > 0 {
+        out.push_str(&format!("{}h", hours));
+    }
+    if minutes > 0 {
+        out.push_str(&format!("{}m", minutes));
+    }
+    if seconds > 0 || out.is_empty() {
+        out.push_str(&format!("{}s", seconds));
+    }
+    out
+}
+
 /// Parse string command to Request
 pub fn parse_request(line: &str) -> Result<Request> {
     let line = line.trim();
@@ -47,20 +419,36 @@ pub fn parse_request(line: &str) -> Result<Request> {
 
     match parts.next() {
         Some("add") => {
-            if let Some(id_str) = parts.next() {
-                if let Ok(id) = id_str.parse::<u64>() {
-                    Ok(Request::Add { window_id: id })
-                } else {
-                    Err(anyhow::anyhow!("Invalid window id"))
-                }
-            } else {
-                Err(anyhow::anyhow!("Missing window id"))
-            }
+            let window_id = match parts.next() {
+                Some(id_str) => id_str
+                    .parse::<u64>()
+                    .map_err(|_| anyhow::anyhow!("Invalid window id"))?,
+                None => return Err(anyhow::anyhow!("Missing window id")),
+            };
+            let duration = match (parts.next(), parts.next()) {
+                (None, _) => None,
+                (Some("--for"), Some(duration)) => Some(parse_duration(duration)?),
+                (Some("--for"), None) => return Err(anyhow::anyhow!("Missing duration")),
+                (Some(_), _) => return Err(anyhow::anyhow!("Invalid argument for add")),
+            };
+            Ok(Request::Add {
+                window_id,
+                duration,
+            })
         }
         Some("remove") => {
             if let Some(id_str) = parts.next() {
                 if let Ok(id) = id_str.parse::<u64>() {
-                    Ok(Request::Remove { window_id: id })
+                    let home = match parts.next() {
+                        None => None,
+                        Some("--home") => Some(true),
+                        Some("--stay") => Some(false),
+                        Some(arg) => return Err(anyhow::anyhow!("Invalid argument: {}", arg)),
+                    };
+                    Ok(Request::Remove {
+                        window_id: id,
+                        home,
+                    })
                 } else {
                     Err(anyhow::anyhow!("Invalid window id"))
                 }
@@ -68,7 +456,10 @@ pub fn parse_request(line: &str) -> Result<Request> {
                 Err(anyhow::anyhow!("Missing window id"))
             }
         }
-        Some("list") => Ok(Request::List),
+        Some("list") => {
+            let (detailed, format) = parse_list_flags(&mut parts, true)?;
+            Ok(Request::List { detailed, format })
+        }
         Some("toggle_active") => Ok(Request::ToggleActive),
         Some("toggle_appid") => {
             if let Some(appid) = parts.next() {
@@ -108,6 +499,8 @@ pub fn parse_request(line: &str) -> Result<Request> {
                 } else {
                     let stage_args = StageArgs {
                         title: Some(title),
+                        dry_run: false,
+                        format: OutputFormat::Plain,
                         ..Default::default()
                     };
                     return Ok(Request::Stage(stage_args));
@@ -119,25 +512,37 @@ pub fn parse_request(line: &str) -> Result<Request> {
                     window_id: None,
                     all: true,
                     list: false,
+                    detailed: false,
                     active: false,
                     appid: None,
                     title: None,
+                    dry_run: parse_dry_run(&mut parts)?,
+                    format: OutputFormat::Plain,
                 })),
-                Some("--list") => Ok(Request::Stage(StageArgs {
-                    window_id: None,
-                    all: false,
-                    list: true,
-                    active: false,
-                    appid: None,
-                    title: None,
-                })),
+                Some("--list") => {
+                    let (detailed, format) = parse_list_flags(&mut parts, true)?;
+                    Ok(Request::Stage(StageArgs {
+                        window_id: None,
+                        all: false,
+                        list: true,
+                        detailed,
+                        active: false,
+                        appid: None,
+                        title: None,
+                        dry_run: false,
+                        format,
+                    }))
+                }
                 Some("--active") => Ok(Request::Stage(StageArgs {
                     window_id: None,
                     all: false,
                     list: false,
+                    detailed: false,
                     active: true,
                     appid: None,
                     title: None,
+                    dry_run: false,
+                    format: OutputFormat::Plain,
                 })),
                 Some("--appid") => {
                     if let Some(appid) = parts.next() {
@@ -145,9 +550,12 @@ pub fn parse_request(line: &str) -> Result<Request> {
                             window_id: None,
                             all: false,
                             list: false,
+                            detailed: false,
                             active: false,
                             appid: Some(appid.to_string()),
                             title: None,
+                            dry_run: false,
+                            format: OutputFormat::Plain,
                         }))
                     } else {
                         Err(anyhow::anyhow!("Missing appid for stage"))
@@ -163,9 +571,12 @@ pub fn parse_request(line: &str) -> Result<Request> {
                             window_id: None,
                             all: false,
                             list: false,
+                            detailed: false,
                             active: false,
                             appid: None,
                             title: Some(title),
+                            dry_run: false,
+                            format: OutputFormat::Plain,
                         }))
                     }
                 }
@@ -175,9 +586,12 @@ pub fn parse_request(line: &str) -> Result<Request> {
                             window_id: Some(id),
                             all: false,
                             list: false,
+                            detailed: false,
                             active: false,
                             appid: None,
                             title: None,
+                            dry_run: false,
+                            format: OutputFormat::Plain,
                         }))
                     } else {
                         Err(anyhow::anyhow!("Invalid window id"))
@@ -213,6 +627,7 @@ pub fn parse_request(line: &str) -> Result<Request> {
                     active: false,
                     appid: None,
                     title: None,
+                    dry_run: parse_dry_run(&mut parts)?,
                 })),
                 Some("--active") => Ok(Request::Unstage(UnstageArgs {
                     window_id: None,
@@ -220,6 +635,7 @@ pub fn parse_request(line: &str) -> Result<Request> {
                     active: true,
                     appid: None,
                     title: None,
+                    dry_run: false,
                 })),
                 Some("--appid") => {
                     if let Some(appid) = parts.next() {
@@ -229,6 +645,7 @@ pub fn parse_request(line: &str) -> Result<Request> {
                             active: false,
                             appid: Some(appid.to_string()),
                             title: None,
+                            dry_run: false,
                         }))
                     } else {
                         Err(anyhow::anyhow!("Missing appid for unstage"))
@@ -246,6 +663,7 @@ pub fn parse_request(line: &str) -> Result<Request> {
                             active: false,
                             appid: None,
                             title: Some(title),
+                            dry_run: false,
                         }))
                     }
                 }
@@ -257,6 +675,7 @@ pub fn parse_request(line: &str) -> Result<Request> {
                             active: false,
                             appid: None,
                             title: None,
+                            dry_run: false,
                         }))
                     } else {
                         Err(anyhow::anyhow!("Invalid window id"))
@@ -265,10 +684,976 @@ pub fn parse_request(line: &str) -> Result<Request> {
                 None => Err(anyhow::anyhow!("Missing argument for unstage")),
             }
         }
+        Some(cmd @ ("set_output" | "move_output")) => {
+            let window_id = match parts.next() {
+                Some(id_str) => id_str
+                    .parse::<u64>()
+                    .map_err(|_| anyhow::anyhow!("Invalid window id"))?,
+                None => return Err(anyhow::anyhow!("Missing window id")),
+            };
+            if let Some(output) = parts.next() {
+                let output = output.to_string();
+                if cmd == "set_output" {
+                    Ok(Request::SetOutput { window_id, output })
+                } else {
+                    Ok(Request::MoveOutput { window_id, output })
+                }
+            } else {
+                Err(anyhow::anyhow!("Missing output"))
+            }
+        }
+        Some("subscribe") => Ok(Request::Subscribe),
+        Some("status") => {
+            let mut parts = parts.peekable();
+            let short = parts.next_if_eq(&"--short").is_some();
+            let format = parse_list_flags(&mut parts, false)?.1;
+            if short && format != OutputFormat::Plain {
+                return Err(anyhow::anyhow!("--short can't be combined with --format"));
+            }
+            Ok(Request::Status { format, short })
+        }
+        Some("stats") => Ok(Request::Stats {
+            format: parse_list_flags(&mut parts, false)?.1,
+        }),
+        Some("undo") => Ok(Request::Undo),
+        Some("pick") => Ok(Request::Pick),
+        Some("clear") => Ok(Request::Clear {
+            dry_run: parse_dry_run(&mut parts)?,
+        }),
+        Some("wait") => {
+            let rest = line
+                .split_once(char::is_whitespace)
+                .map_or("", |(_, rest)| rest);
+            match parse_request(rest)? {
+                request @ (Request::Stage(StageArgs { list: false, .. }) | Request::Unstage(_)) => {
+                    Ok(Request::Wait {
+                        request: Box::new(request),
+                    })
+                }
+                _ => Err(anyhow::anyhow!("wait only works with stage and unstage")),
+            }
+        }
+        Some("attach") => {
+            let mut id = || match parts.next() {
+                Some(id_str) => id_str
+                    .parse::<u64>()
+                    .map_err(|_| anyhow::anyhow!("Invalid window id")),
+                None => Err(anyhow::anyhow!("Missing window id")),
+            };
+            Ok(Request::Attach {
+                follower: id()?,
+                leader: id()?,
+            })
+        }
+        Some("detach") => match parts.next() {
+            Some(id_str) => match id_str.parse::<u64>() {
+                Ok(follower) => Ok(Request::Detach { follower }),
+                Err(_) => Err(anyhow::anyhow!("Invalid window id")),
+            },
+            None => Err(anyhow::anyhow!("Missing window id")),
+        },
+        Some("swap") => {
+            let mut id = || match parts.next().map(|id| id.parse::<u64>()) {
+                Some(Ok(id)) => Ok(id),
+                Some(Err(_)) => Err(anyhow::anyhow!("Invalid window id")),
+                None => Err(anyhow::anyhow!(
+                    "Missing window id, expected swap <staged> <sticky>"
+                )),
+            };
+            Ok(Request::Swap {
+                staged_id: id()?,
+                sticky_id: id()?,
+            })
+        }
+        Some(cmd @ ("protect" | "unprotect")) => {
+            let window_id = match parts.next() {
+                Some(id_str) => Some(
+                    id_str
+                        .parse::<u64>()
+                        .map_err(|_| anyhow::anyhow!("Invalid window id"))?,
+                ),
+                None => None,
+            };
+            Ok(Request::Protect {
+                window_id,
+                protect: cmd == "protect",
+            })
+        }
+        Some(cmd @ ("label" | "unlabel")) => {
+            let window_id = parts
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("Missing window id"))?
+                .parse::<u64>()
+                .map_err(|_| anyhow::anyhow!("Invalid window id"))?;
+            let label = match (cmd, parts.next()) {
+                ("label", Some(label)) => Some(label.to_string()),
+                ("label", None) => return Err(anyhow::anyhow!("Missing label")),
+                _ => None,
+            };
+            Ok(Request::Label { window_id, label })
+        }
+        Some("resolve") => match parts.next() {
+            Some(label) => Ok(Request::Resolve {
+                label: label.to_string(),
+            }),
+            None => Err(anyhow::anyhow!("Missing label")),
+        },
+        Some("bring") => Ok(Request::Bring {
+            selector: parse_selector(&mut parts)?,
+        }),
+        Some("trace_ipc") => match parts.next() {
+            None => Ok(Request::TraceIpc { enabled: None }),
+            Some("on") => Ok(Request::TraceIpc {
+                enabled: Some(true),
+            }),
+            Some("off") => Ok(Request::TraceIpc {
+                enabled: Some(false),
+            }),
+            Some(_) => Err(anyhow::anyhow!(
+                "Invalid argument for trace_ipc, expected on or off"
+            )),
+        },
+        Some("focus_mode") => match parts.next() {
+            None => Ok(Request::FocusMode { enabled: None }),
+            Some("on") => Ok(Request::FocusMode {
+                enabled: Some(true),
+            }),
+            Some("off") => Ok(Request::FocusMode {
+                enabled: Some(false),
+            }),
+            Some(_) => Err(anyhow::anyhow!(
+                "Invalid argument for focus_mode, expected on or off"
+            )),
+        },
+        Some(cmd @ ("pause" | "resume")) => {
+            let output = match parts.next() {
+                Some("--output") => match parts.next() {
+                    Some(output) => Some(output.to_string()),
+                    None => return Err(anyhow::anyhow!("Missing output for {}", cmd)),
+                },
+                Some(_) => return Err(anyhow::anyhow!("Invalid argument for {}", cmd)),
+                None => None,
+            };
+            if cmd == "pause" {
+                Ok(Request::Pause { output })
+            } else {
+                Ok(Request::Resume { output })
+            }
+        }
+        Some("mode") => match parts.next() {
+            None => Ok(Request::Mode {
+                mode: None,
+                primary_output: None,
+            }),
+            Some("global") => Ok(Request::Mode {
+                mode: Some(FollowMode::Global),
+                primary_output: None,
+            }),
+            Some("output") => Ok(Request::Mode {
+                mode: Some(FollowMode::Output),
+                primary_output: None,
+            }),
+            Some("primary") => Ok(Request::Mode {
+                mode: Some(FollowMode::Primary),
+                primary_output: parts.next().map(|s| s.to_string()),
+            }),
+            Some(_) => Err(anyhow::anyhow!(
+                "Invalid mode, expected global, output or primary"
+            )),
+        },
+        Some("wait-for") => {
+            let action = match parts.next() {
+                Some("sticky") => Action::Sticky,
+                Some("stage") => Action::Stage,
+                Some("float") => Action::Float,
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "Invalid action, expected sticky, stage or float"
+                    ));
+                }
+            };
+            let timeout = parse_duration(
+                parts
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("Missing timeout"))?,
+            )?;
+            let selector = parse_selector(&mut parts)?;
+            Ok(Request::WaitFor {
+                selector,
+                action,
+                timeout,
+            })
+        }
+        Some("set") => {
+            let mode = match parts.next() {
+                Some("replace") => SetMode::Replace,
+                Some("add") => SetMode::Add,
+                Some("remove") => SetMode::Remove,
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "Invalid set mode, expected replace, add or remove"
+                    ));
+                }
+            };
+            // Selectors are sent as a JSON array of strings, titles may contain spaces
+            let json = line.splitn(3, char::is_whitespace).nth(2).unwrap_or("");
+            let lines: Vec<String> = serde_json::from_str(json)
+                .map_err(|e| anyhow::anyhow!("Invalid window list: {}", e))?;
+            let selectors = lines
+                .iter()
+                .map(|l| parse_selector(&mut l.split_whitespace()))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Request::Set { mode, selectors })
+        }
+        Some("rule") => match parts.next() {
+            Some("add") => {
+                // The rule is sent as JSON, it may contain spaces
+                let json = line.splitn(3, char::is_whitespace).nth(2).unwrap_or("");
+                let rule: Rule = serde_json::from_str(json)
+                    .map_err(|e| anyhow::anyhow!("Invalid rule: {}", e))?;
+                Ok(Request::RuleAdd { rule })
+            }
+            Some("list") => Ok(Request::RuleList {
+                format: parse_list_flags(&mut parts, false)?.1,
+            }),
+            Some("remove") => match parts.next().map(|id| id.parse::<usize>()) {
+                Some(Ok(id)) => Ok(Request::RuleRemove { id }),
+                Some(Err(_)) => Err(anyhow::anyhow!("Invalid rule id")),
+                None => Err(anyhow::anyhow!("Missing rule id")),
+            },
+            Some("test") => match parts.next().map(|id| id.parse::<u64>()) {
+                Some(Ok(window_id)) => Ok(Request::RuleTest { window_id }),
+                Some(Err(_)) => Err(anyhow::anyhow!("Invalid window id")),
+                None => Err(anyhow::anyhow!("Missing window id")),
+            },
+            _ => Err(anyhow::anyhow!(
+                "Invalid rule command, expected add, list, remove or test"
+            )),
+        },
+        Some("group") => match parts.next() {
+            Some("create") => {
+                let name = parts
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("Missing group name"))?
+                    .to_string();
+                let window_ids = parts
+                    .map(|id| id.parse::<u64>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| anyhow::anyhow!("Invalid window id"))?;
+                if window_ids.len() < 2 {
+                    return Err(anyhow::anyhow!("A group needs at least two windows"));
+                }
+                Ok(Request::GroupCreate { name, window_ids })
+            }
+            Some("remove") => match parts.next() {
+                Some(name) => Ok(Request::GroupRemove {
+                    name: name.to_string(),
+                }),
+                None => Err(anyhow::anyhow!("Missing group name")),
+            },
+            Some("list") => Ok(Request::GroupList {
+                format: parse_list_flags(&mut parts, false)?.1,
+            }),
+            _ => Err(anyhow::anyhow!(
+                "Invalid group command, expected create, remove or list"
+            )),
+        },
+        Some("slot") => match parts.next() {
+            Some("set") => {
+                let slot = parse_slot(&mut parts)?;
+                let window_id = match parts.next() {
+                    Some(id_str) => Some(
+                        id_str
+                            .parse::<u64>()
+                            .map_err(|_| anyhow::anyhow!("Invalid window id"))?,
+                    ),
+                    None => None,
+                };
+                Ok(Request::SlotSet { slot, window_id })
+            }
+            Some("clear") => Ok(Request::SlotClear {
+                slot: parse_slot(&mut parts)?,
+            }),
+            Some("list") => Ok(Request::SlotList {
+                format: parse_list_flags(&mut parts, false)?.1,
+            }),
+            _ => Err(anyhow::anyhow!(
+                "Invalid slot command, expected set, clear or list"
+            )),
+        },
+        Some("focus_slot") => Ok(Request::FocusSlot {
+            slot: parse_slot(&mut parts)?,
+        }),
+        Some("idle") => match parts.next() {
+            Some("enter") => Ok(Request::Idle { idle: true }),
+            Some("leave") => Ok(Request::Idle { idle: false }),
+            _ => Err(anyhow::anyhow!(
+                "Invalid idle command, expected enter or leave"
+            )),
+        },
         _ => Err(anyhow::anyhow!("Unknown command")),
     }
 }
 
+/// Format rules as one `ID  matcher  (source)` line each
+pub fn format_rules(rules: &[(usize, Rule, RuleSource)]) -> String {
+    if rules.is_empty() {
+        return "No rules\n".to_string();
+    }
+    rules
+        .iter()
+        .map(|(id, rule, source)| match rule.priority {
+            0 => format!("{}  {}  ({})\n", id, rule.describe(), source.as_str()),
+            priority => format!(
+                "{}  {}  ({}, priority {})\n",
+                id,
+                rule.describe(),
+                source.as_str(),
+                priority
+            ),
+        })
+        .collect()
+}
+
+/// Format the result of a rule dry run
+pub fn format_rule_test(window: &WindowInfo, outcome: Option<&Outcome>, tracked: bool) -> String {
+    let mut text = format!(
+        "Window {}  app_id={}  title={:?}  {}{}\n",
+        window.id,
+        window.app_id.as_deref().unwrap_or("-"),
+        window.title.as_deref().unwrap_or(""),
+        if window.is_floating {
+            "floating"
+        } else {
+            "tiled"
+        },
+        if window.is_fullscreen {
+            " fullscreen"
+        } else {
+            ""
+        },
+    );
+    match outcome {
+        Some(outcome) => {
+            let ids: Vec<String> = outcome.rule_ids.iter().map(|id| id.to_string()).collect();
+            text.push_str(&format!(
+                "{} {} {}: {}\n",
+                if ids.len() == 1 { "Rule" } else { "Rules" },
+                ids.join(", "),
+                if ids.len() == 1 { "matches" } else { "match" },
+                outcome.describe()
+            ));
+        }
+        None => text.push_str("No rule matches\n"),
+    }
+    if tracked {
+        text.push_str("Window is already sticky or staged, rules would not change it\n");
+    }
+    text
+}
+
+/// Format occupied slots as `slot: window` lines
+pub fn format_slots(slots: &[(u32, u64)]) -> String {
+    if slots.is_empty() {
+        return "No slots\n".to_string();
+    }
+    slots
+        .iter()
+        .map(|(slot, window_id)| format!("{}: {}\n", slot, window_id))
+        .collect()
+}
+
+/// Format groups as `name: id, id, ...` lines
+pub fn format_groups(groups: &[(String, Vec<u64>)]) -> String {
+    if groups.is_empty() {
+        return "No groups\n".to_string();
+    }
+    let mut out = String::new();
+    for (name, members) in groups {
+        let members: Vec<String> = members.iter().map(|id| id.to_string()).collect();
+        out.push_str(&format!("{}: {}\n", name, members.join(", ")));
+    }
+    out
+}
+
+/// Format the daemon status followed by a latency table
+pub fn format_status(status: &DaemonStatus) -> String {
+    let mut out = String::new();
+    let stream = if status.event_stream_connected {
+        "connected"
+    } else {
+        "disconnected"
+    };
+    out.push_str(&format!(
+        "Backend: {} (event stream {})\n",
+        status.backend.as_str(),
+        stream
+    ));
+    if status.queued_requests > 0 {
+        out.push_str(&format!(
+            "Queued: {} requests waiting for the compositor\n",
+            status.queued_requests
+        ));
+    }
+    match (&status.primary_output, status.follow_mode) {
+        (Some(primary), FollowMode::Primary) => out.push_str(&format!(
+            "Follow mode: {} ({})\n",
+            status.follow_mode.as_str(),
+            primary
+        )),
+        _ => out.push_str(&format!("Follow mode: {}\n", status.follow_mode.as_str())),
+    }
+    let mut paused = Vec::new();
+    if status.paused.all {
+        paused.push("all outputs".to_string());
+    }
+    let mut outputs: Vec<&String> = status.paused.outputs.iter().collect();
+    outputs.sort();
+    paused.extend(outputs.into_iter().cloned());
+    if status.paused.locked {
+        paused.push("session locked".to_string());
+    }
+    if paused.is_empty() {
+        out.push_str("Paused: no\n");
+    } else {
+        out.push_str(&format!("Paused: {}\n", paused.join(", ")));
+    }
+    out.push_str(&format!(
+        "Focus mode: {}\n",
+        if status.focus_mode { "on" } else { "off" }
+    ));
+    if !status.attachments.is_empty() {
+        let pairs: Vec<String> = status
+            .attachments
+            .iter()
+            .map(|(follower, leader)| format!("{} follows {}", follower, leader))
+            .collect();
+        out.push_str(&format!("Attached: {}\n", pairs.join(", ")));
+    }
+    if !status.protected.is_empty() {
+        let ids: Vec<String> = status.protected.iter().map(|id| id.to_string()).collect();
+        out.push_str(&format!("Protected: {}\n", ids.join(", ")));
+    }
+    out.push_str(&format!(
+        "Windows: {} sticky, {} staged\n",
+        status.sticky, status.staged
+    ));
+    out.push_str(&format!(
+        "Compositor actions: {}, events: {}\n",
+        status.moves, status.events
+    ));
+    out.push_str(&format!(
+        "Errors: {} move, {} query, {} event parse\n",
+        status.move_failures, status.query_failures, status.event_parse_errors
+    ));
+    for error in &status.recent_errors {
+        out.push_str(&format!(
+            "  {}s ago [{}] {}\n",
+            error.at.elapsed().as_secs(),
+            error.kind.as_str(),
+            error.message.lines().next().unwrap_or("")
+        ));
+    }
+    out.push_str(&format!(
+        "IPC tracing: {}\n",
+        if status.trace_ipc { "on" } else { "off" }
+    ));
+    out.push_str("Tasks:\n");
+    for task in &status.tasks {
+        let state = if task.running {
+            "running"
+        } else {
+            "restarting"
+        };
+        out.push_str(&format!("  {}: {}", task.name, state));
+        if task.restarts > 0 {
+            out.push_str(&format!(", restarted {} times", task.restarts));
+        }
+        if let Some((at, error)) = &task.last_error {
+            out.push_str(&format!(
+                ", last stopped {}s ago: {}",
+                at.elapsed().as_secs(),
+                error.lines().next().unwrap_or("")
+            ));
+        }
+        out.push('\n');
+    }
+
+    if status.latencies.is_empty() {
+        out.push_str("Latency: no operations yet\n");
+        return out;
+    }
+    let ms = |d: std::time::Duration| format!("{:.1}ms", d.as_secs_f64() * 1000.0);
+    out.push_str(&format!(
+        "\n{:<22} {:>6} {:>9} {:>9} {:>9} {:>9}\n",
+        "Latency", "count", "p50", "p90", "p99", "max"
+    ));
+    for latency in &status.latencies {
+        out.push_str(&format!(
+            "{:<22} {:>6} {:>9} {:>9} {:>9} {:>9}\n",
+            latency.operation.as_str(),
+            latency.count,
+            ms(latency.p50),
+            ms(latency.p90),
+            ms(latency.p99),
+            ms(latency.max)
+        ));
+    }
+    out
+}
+
+/// One window ID per line, in ascending order, followed by its label if it has one
+pub fn format_window_ids(ids: &[u64], labels: &HashMap<u64, String>) -> String {
+    if ids.is_empty() {
+        return "No windows\n".to_string();
+    }
+    let mut ids = ids.to_vec();
+    ids.sort_unstable();
+    ids.iter()
+        .map(|id| match labels.get(id) {
+            Some(label) => format!("{} ({})\n", id, label),
+            None => format!("{}\n", id),
+        })
+        .collect()
+}
+
+/// Format tracked windows grouped under their output and workspace
+pub fn format_tracked_windows(windows: &[TrackedWindow]) -> String {
+    if windows.is_empty() {
+        return "No windows\n".to_string();
+    }
+
+    // output -> workspace label -> window lines
+    let mut groups: BTreeMap<String, BTreeMap<(u64, String), Vec<String>>> = BTreeMap::new();
+    for tracked in windows {
+        let output = tracked
+            .workspace
+            .as_ref()
+            .and_then(|ws| ws.output.clone())
+            .unwrap_or_else(|| "(no output)".to_string());
+        let workspace = match &tracked.workspace {
+            Some(ws) => {
+                let idx = ws.idx.unwrap_or(0);
+                let label = match &ws.name {
+                    Some(name) => format!("workspace {} \"{}\"", idx, name),
+                    None => format!("workspace {}", idx),
+                };
+                (idx, label)
+            }
+            None => (u64::MAX, "(no workspace)".to_string()),
+        };
+        let state = if tracked.staged { "staged" } else { "sticky" };
+        let id = match &tracked.label {
+            Some(label) => format!("{} ({})", tracked.window.id, label),
+            None => tracked.window.id.to_string(),
+        };
+        let mut line = format!(
+            "{} [{}] {} - {}",
+            id,
+            state,
+            tracked.window.app_id.as_deref().unwrap_or("?"),
+            tracked.window.title.as_deref().unwrap_or("")
+        );
+        if let Some(expires_in) = tracked.expires_in {
+            line.push_str(&format!(" (removed in {})", format_duration(expires_in)));
+        }
+        groups
+            .entry(output)
+            .or_default()
+            .entry(workspace)
+            .or_default()
+            .push(line);
+    }
+
+    let mut out = String::new();
+    for (output, workspaces) in groups {
+        out.push_str(&format!("{}\n", output));
+        for ((_, label), lines) in workspaces {
+            out.push_str(&format!("  {}\n", label));
+            for line in lines {
+                out.push_str(&format!("    {}\n", line));
+            }
+        }
+    }
+    out
+}
+
+/// One row of a list-type response, fields in column order
+pub type Record = Vec<(&'static str, Value)>;
+
+/// Render a list-type response, `plain` text or `records` as a JSON array of objects or TSV rows
+pub fn render_records(
+    format: OutputFormat,
+    plain: impl FnOnce() -> String,
+    records: impl FnOnce() -> Vec<Record>,
+) -> String {
+    match format {
+        OutputFormat::Plain => plain(),
+        OutputFormat::Json => {
+            let rows: Vec<Value> = records().into_iter().map(record_object).collect();
+            format!("{}\n", Value::Array(rows))
+        }
+        OutputFormat::Tsv => records()
+            .iter()
+            .map(|record| tsv_row(record.iter().map(|(_, value)| value)))
+            .collect(),
+    }
+}
+
+fn record_object(record: Record) -> Value {
+    Value::Object(
+        record
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+/// One tab-separated line, lists become comma-separated and missing values empty
+fn tsv_row<'a>(values: impl Iterator<Item = &'a Value>) -> String {
+    let fields: Vec<String> = values.map(tsv_field).collect();
+    format!("{}\n", fields.join("\t"))
+}
+
+fn tsv_field(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        // Tabs and newlines in titles would break the columns
+        Value::String(s) => s.replace(['\t', '\n', '\r'], " "),
+        Value::Array(items) => items.iter().map(tsv_field).collect::<Vec<_>>().join(","),
+        other => other.to_string(),
+    }
+}
+
+/// Tracked windows as records: id, state, app_id, title, output, workspace and timer
+pub fn tracked_window_records(windows: &[TrackedWindow]) -> Vec<Record> {
+    windows
+        .iter()
+        .map(|tracked| {
+            let ws = tracked.workspace.as_ref();
+            vec![
+                ("id", json!(tracked.window.id)),
+                ("label", json!(tracked.label)),
+                (
+                    "state",
+                    json!(if tracked.staged { "staged" } else { "sticky" }),
+                ),
+                ("app_id", json!(tracked.window.app_id)),
+                ("title", json!(tracked.window.title)),
+                ("output", json!(ws.and_then(|ws| ws.output.as_deref()))),
+                ("workspace_id", json!(tracked.window.workspace_id)),
+                ("workspace_idx", json!(ws.and_then(|ws| ws.idx))),
+                (
+                    "workspace_name",
+                    json!(ws.and_then(|ws| ws.name.as_deref())),
+                ),
+                (
+                    "expires_in_secs",
+                    json!(tracked.expires_in.map(|d| d.as_secs())),
+                ),
+            ]
+        })
+        .collect()
+}
+
+pub fn slot_records(slots: &[(u32, u64)]) -> Vec<Record> {
+    slots
+        .iter()
+        .map(|(slot, window_id)| vec![("slot", json!(slot)), ("window_id", json!(window_id))])
+        .collect()
+}
+
+pub fn group_records(groups: &[(String, Vec<u64>)]) -> Vec<Record> {
+    groups
+        .iter()
+        .map(|(name, members)| vec![("name", json!(name)), ("windows", json!(members))])
+        .collect()
+}
+
+/// Rules as records, the last column is the rule itself as JSON
+pub fn rule_records(rules: &[(usize, Rule, RuleSource)]) -> Vec<Record> {
+    rules
+        .iter()
+        .map(|(id, rule, source)| {
+            vec![
+                ("id", json!(id)),
+                ("source", json!(source.as_str())),
+                ("priority", json!(rule.priority)),
+                ("description", json!(rule.describe())),
+                ("rule", serde_json::to_value(rule).unwrap_or(Value::Null)),
+            ]
+        })
+        .collect()
+}
+
+/// One-line status for shell prompts, e.g. "📌3 ⬇2", " ⏸" is appended while sticky-follow is paused
+/// Both counts are always there, so the format stays the same whatever the state
+pub fn format_status_short(status: &DaemonStatus) -> String {
+    let mut line = format!("📌{} ⬇{}", status.sticky, status.staged);
+    if status.paused.all || !status.paused.outputs.is_empty() {
+        line.push_str(" ⏸");
+    }
+    line.push('\n');
+    line
+}
+
+/// Apps listed per section of `nsticky stats`
+const STATS_TOP: usize = 5;
+
+/// Apps made sticky at least this often without a rule get a rule suggestion
+const SUGGEST_RULE_AFTER: u64 = 3;
+
+/// Summarize usage statistics, `ruled` are app IDs that already have a sticky rule
+pub fn format_stats(stats: &UsageStats, ruled: &HashSet<String>) -> String {
+    if stats.apps.is_empty() {
+        return "No usage recorded yet\n".to_string();
+    }
+    let mut out = String::new();
+    if let Some(since) = &stats.since {
+        out.push_str(&format!("Counting since {}\n", since));
+    }
+
+    type Key = fn(&AppStats) -> u64;
+    let top = |key: Key| {
+        let mut apps: Vec<(&String, &AppStats)> =
+            stats.apps.iter().filter(|(_, app)| key(app) > 0).collect();
+        apps.sort_by_key(|(_, app)| std::cmp::Reverse(key(app)));
+        apps.truncate(STATS_TOP);
+        apps
+    };
+    let sections: [(&str, Key); 3] = [
+        ("Made sticky most often", |app| app.stuck),
+        ("Followed most often", |app| app.followed),
+        ("Staged most often", |app| app.staged),
+    ];
+    for (heading, key) in sections {
+        let apps = top(key);
+        if apps.is_empty() {
+            continue;
+        }
+        out.push_str(&format!("\n{}:\n", heading));
+        for (app_id, app) in apps {
+            out.push_str(&format!(
+                "  {:<24} {} sticky, {} unstuck, {} staged, {} follows\n",
+                app_id, app.stuck, app.unstuck, app.staged, app.followed
+            ));
+        }
+    }
+
+    let recent: Vec<_> = stats.days.iter().rev().take(7).collect();
+    if !recent.is_empty() {
+        out.push_str("\nLast days:\n");
+        for (date, day) in recent {
+            out.push_str(&format!(
+                "  {}  {} sticky, {} staged, {} follows\n",
+                date, day.stuck, day.staged, day.followed
+            ));
+        }
+    }
+
+    let suggestions: Vec<_> = top(|app| app.stuck)
+        .into_iter()
+        .filter(|(app_id, app)| {
+            app.stuck >= SUGGEST_RULE_AFTER
+                && app_id.as_str() != NO_APP_ID
+                && !ruled.contains(*app_id)
+        })
+        .collect();
+    if !suggestions.is_empty() {
+        out.push_str("\nNo rule yet, consider:\n");
+        for (app_id, app) in suggestions {
+            out.push_str(&format!(
+                "  nsticky rule add --appid {}   # made sticky {} times\n",
+                app_id, app.stuck
+            ));
+        }
+    }
+    out
+}
+
+/// One record per app, most often made sticky first
+pub fn stats_records(stats: &UsageStats, ruled: &HashSet<String>) -> Vec<Record> {
+    let mut apps: Vec<(&String, &AppStats)> = stats.apps.iter().collect();
+    apps.sort_by_key(|(_, app)| std::cmp::Reverse(app.stuck));
+    apps.into_iter()
+        .map(|(app_id, app)| {
+            vec![
+                ("app_id", json!(app_id)),
+                ("stuck", json!(app.stuck)),
+                ("unstuck", json!(app.unstuck)),
+                ("staged", json!(app.staged)),
+                ("followed", json!(app.followed)),
+                ("has_rule", json!(ruled.contains(app_id))),
+            ]
+        })
+        .collect()
+}
+
+/// Render the daemon status as text, one JSON object, or TSV
+/// TSV has a `key<TAB>value` row per field, then a `key<TAB>fields...` row per task, latency, error and attachment
+pub fn render_status(format: OutputFormat, status: &DaemonStatus) -> String {
+    if format == OutputFormat::Plain {
+        return format_status(status);
+    }
+
+    let mut paused_outputs: Vec<&String> = status.paused.outputs.iter().collect();
+    paused_outputs.sort();
+    let fields: Record = vec![
+        ("backend", json!(status.backend.as_str())),
+        (
+            "event_stream_connected",
+            json!(status.event_stream_connected),
+        ),
+        ("queued_requests", json!(status.queued_requests)),
+        ("follow_mode", json!(status.follow_mode.as_str())),
+        ("primary_output", json!(status.primary_output)),
+        ("paused", json!(status.paused.all)),
+        ("paused_outputs", json!(paused_outputs)),
+        ("locked", json!(status.paused.locked)),
+        ("focus_mode", json!(status.focus_mode)),
+        ("protected", json!(status.protected)),
+        ("sticky", json!(status.sticky)),
+        ("staged", json!(status.staged)),
+        ("moves", json!(status.moves)),
+        ("events", json!(status.events)),
+        ("move_failures", json!(status.move_failures)),
+        ("query_failures", json!(status.query_failures)),
+        ("event_parse_errors", json!(status.event_parse_errors)),
+        ("trace_ipc", json!(status.trace_ipc)),
+    ];
+    let ms = |d: Duration| json!((d.as_secs_f64() * 10_000.0).round() / 10.0);
+    // (TSV row name, JSON key, rows)
+    let lists: Vec<(&str, &str, Vec<Record>)> = vec![
+        (
+            "attachment",
+            "attachments",
+            status
+                .attachments
+                .iter()
+                .map(|(follower, leader)| {
+                    vec![("follower", json!(follower)), ("leader", json!(leader))]
+                })
+                .collect(),
+        ),
+        (
+            "recent_error",
+            "recent_errors",
+            status
+                .recent_errors
+                .iter()
+                .map(|error| {
+                    vec![
+                        ("kind", json!(error.kind.as_str())),
+                        ("secs_ago", json!(error.at.elapsed().as_secs())),
+                        ("message", json!(error.message.lines().next().unwrap_or(""))),
+                    ]
+                })
+                .collect(),
+        ),
+        (
+            "task",
+            "tasks",
+            status
+                .tasks
+                .iter()
+                .map(|task| {
+                    let last_error = task.last_error.as_ref();
+                    vec![
+                        ("name", json!(task.name)),
+                        ("running", json!(task.running)),
+                        ("restarts", json!(task.restarts)),
+                        (
+                            "last_error",
+                            json!(last_error.map(|(_, e)| e.lines().next().unwrap_or(""))),
+                        ),
+                    ]
+                })
+                .collect(),
+        ),
+        (
+            "latency",
+            "latencies",
+            status
+                .latencies
+                .iter()
+                .map(|latency| {
+                    vec![
+                        ("operation", json!(latency.operation.as_str())),
+                        ("count", json!(latency.count)),
+                        ("p50_ms", ms(latency.p50)),
+                        ("p90_ms", ms(latency.p90)),
+                        ("p99_ms", ms(latency.p99)),
+                        ("max_ms", ms(latency.max)),
+                    ]
+                })
+                .collect(),
+        ),
+    ];
+
+    if format == OutputFormat::Json {
+        let mut object = record_object(fields);
+        for (_, key, records) in lists {
+            let rows: Vec<Value> = records.into_iter().map(record_object).collect();
+            object[key] = Value::Array(rows);
+        }
+        return format!("{}\n", object);
+    }
+    let mut out = String::new();
+    for (key, value) in &fields {
+        out.push_str(&tsv_row([json!(key), value.clone()].iter()));
+    }
+    for (name, _, records) in &lists {
+        for record in records {
+            let key = json!(name);
+            out.push_str(&tsv_row(
+                std::iter::once(&key).chain(record.iter().map(|(_, value)| value)),
+            ));
+        }
+    }
+    out
+}
+
+/// Describe a workspace for humans, e.g. `workspace 2 "web" on eDP-1`
+pub fn workspace_label(ws: &WorkspaceInfo) -> String {
+    let mut label = format!("workspace {}", ws.idx.unwrap_or(0));
+    if let Some(name) = &ws.name {
+        label.push_str(&format!(" \"{}\"", name));
+    }
+    if let Some(output) = &ws.output {
+        label.push_str(&format!(" on {}", output));
+    }
+    label
+}
+
+/// Report of a `--dry-run`, one line per window with where it is and where it would go
+/// `destination` is None for operations that leave windows in place
+pub fn format_dry_run(
+    action: &str,
+    windows: &[TrackedWindow],
+    destination: Option<&str>,
+) -> String {
+    let mut out = format!("Would {}: {} windows\n", action, windows.len());
+    for tracked in windows {
+        let from = tracked
+            .workspace
+            .as_ref()
+            .map(workspace_label)
+            .unwrap_or_else(|| "(no workspace)".to_string());
+        let route = match destination {
+            Some(to) => format!("{} -> {}", from, to),
+            None => format!("stays on {}", from),
+        };
+        out.push_str(&format!(
+            "  {} {} - {}: {}\n",
+            tracked.window.id,
+            tracked.window.app_id.as_deref().unwrap_or("?"),
+            tracked.window.title.as_deref().unwrap_or(""),
+            route
+        ));
+    }
+    out
+}
+
 /// Convert Response to string
 pub fn format_response(response: Response) -> String {
     match response {
@@ -0,0 +1,452 @@
+use anyhow::Result;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::system_integration::WindowInfo;
+
+/// What happens to a window matching a rule when it opens
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Make the window sticky
+    Sticky,
+    /// Make the window sticky and move it to the stage workspace
+    Stage,
+    /// Leave the window alone, no later rule applies to it
+    Ignore,
+    /// Make the window floating
+    Float,
+}
+
+impl Action {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::Sticky => "sticky",
+            Action::Stage => "stage",
+            Action::Ignore => "ignore",
+            Action::Float => "float",
+        }
+    }
+}
+
+fn default_actions() -> Vec<Action> {
+    vec![Action::Sticky]
+}
+
+fn is_default_priority(priority: &i32) -> bool {
+    *priority == 0
+}
+
+/// How many rules apply to a window
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    /// Only the first matching rule applies
+    #[default]
+    First,
+    /// Every matching rule applies, until an ignore rule matches
+    All,
+}
+
+/// Applies actions to windows as soon as they open
+/// Every condition that is set has to match
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rule {
+    /// Exact application ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_id: Option<String>,
+    /// Substring of the window title
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Regular expression searched in the window title
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title_regex: Option<String>,
+    /// Floating (true) or tiled (false) windows only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub floating: Option<bool>,
+    /// Fullscreen (true) or non-fullscreen (false) windows only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fullscreen: Option<bool>,
+    /// Actions applied on match, sticky when omitted
+    #[serde(default = "default_actions")]
+    pub actions: Vec<Action>,
+    /// Move the window to this output, sticky and staged windows keep following it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// Rules with a higher priority are evaluated first, equal priorities keep their order
+    #[serde(default, skip_serializing_if = "is_default_priority")]
+    pub priority: i32,
+}
+
+impl Rule {
+    /// Check that the rule has at least one condition, a valid title regex and compatible actions
+    pub fn validate(&self) -> Result<()> {
+        if self.app_id.is_none()
+            && self.title.is_none()
+            && self.title_regex.is_none()
+            && self.floating.is_none()
+            && self.fullscreen.is_none()
+        {
+            anyhow::bail!(
+                "Rule needs at least one of app_id, title, title_regex, floating or fullscreen"
+            );
+        }
+        if let Some(pattern) = &self.title_regex {
+            Regex::new(pattern)
+                .map_err(|e| anyhow::anyhow!("Invalid title_regex {:?}: {}", pattern, e))?;
+        }
+
+        if self.actions.is_empty() {
+            anyhow::bail!("Rule needs at least one action");
+        }
+        if self.has_action(Action::Ignore) && (self.actions.len() > 1 || self.output.is_some()) {
+            anyhow::bail!("The ignore action can't be combined with other actions or an output");
+        }
+        if self.has_action(Action::Sticky) && self.has_action(Action::Stage) {
+            anyhow::bail!("Use either the sticky or the stage action, stage implies sticky");
+        }
+        Ok(())
+    }
+
+    /// A fullscreen condition never matches on a backend that doesn't report fullscreen state
+    fn check_backend(&self) -> Result<()> {
+        let backend = crate::system_integration::backend();
+        if self.fullscreen.is_some() && !backend.reports_fullscreen() {
+            anyhow::bail!(
+                "The {} backend doesn't report fullscreen windows, drop the fullscreen condition",
+                backend.as_str()
+            );
+        }
+        Ok(())
+    }
+
+    pub fn has_action(&self, action: Action) -> bool {
+        self.actions.contains(&action)
+    }
+
+    pub fn matches(&self, window: &WindowInfo) -> bool {
+        if let Some(app_id) = &self.app_id
+            && window.app_id.as_deref() != Some(app_id.as_str())
+        {
+            return false;
+        }
+        let window_title = window.title.as_deref().unwrap_or_default();
+        if let Some(title) = &self.title
+            && (window.title.is_none() || !window_title.contains(title.as_str()))
+        {
+            return false;
+        }
+        // Validated when the rule was added, rules are only evaluated when windows open
+        if let Some(pattern) = &self.title_regex
+            && (window.title.is_none()
+                || !Regex::new(pattern).is_ok_and(|re| re.is_match(window_title)))
+        {
+            return false;
+        }
+        if self
+            .floating
+            .is_some_and(|floating| floating != window.is_floating)
+        {
+            return false;
+        }
+        if self
+            .fullscreen
+            .is_some_and(|fullscreen| fullscreen != window.is_fullscreen)
+        {
+            return false;
+        }
+        true
+    }
+
+    /// Human readable summary like `app_id=firefox title~/^Picture/ floating -> sticky, float`
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(app_id) = &self.app_id {
+            parts.push(format!("app_id={}", app_id));
+        }
+        if let Some(title) = &self.title {
+            parts.push(format!("title~{:?}", title));
+        }
+        if let Some(pattern) = &self.title_regex {
+            parts.push(format!("title~/{}/", pattern));
+        }
+        match self.floating {
+            Some(true) => parts.push("floating".to_string()),
+            Some(false) => parts.push("tiled".to_string()),
+            None => {}
+        }
+        match self.fullscreen {
+            Some(true) => parts.push("fullscreen".to_string()),
+            Some(false) => parts.push("!fullscreen".to_string()),
+            None => {}
+        }
+        let conditions = if parts.is_empty() {
+            "(empty)".to_string()
+        } else {
+            parts.join(" ")
+        };
+
+        let mut actions: Vec<String> = self
+            .actions
+            .iter()
+            .map(|action| action.as_str().to_string())
+            .collect();
+        if let Some(output) = &self.output {
+            actions.push(format!("output={}", output));
+        }
+        format!("{} -> {}", conditions, actions.join(", "))
+    }
+}
+
+/// Where a rule was defined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleSource {
+    /// `[[rules]]` in config.toml, read-only at runtime
+    Config,
+    /// Added with `nsticky rule add`, persisted in the rules state file
+    Runtime,
+}
+
+impl RuleSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleSource::Config => "config",
+            RuleSource::Runtime => "runtime",
+        }
+    }
+}
+
+/// What the matching rules do to a window
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    /// IDs of the matching rules that took effect, in evaluation order
+    pub rule_ids: Vec<usize>,
+    pub actions: Vec<Action>,
+    pub output: Option<String>,
+}
+
+impl Outcome {
+    pub fn has_action(&self, action: Action) -> bool {
+        self.actions.contains(&action)
+    }
+
+    /// Merge a matching rule, returns false once no further rule may apply
+    fn merge(&mut self, id: usize, rule: &Rule) -> bool {
+        if rule.has_action(Action::Ignore) {
+            // Windows ignored by the first match are left alone,
+            // later ignore rules only keep lower priority rules out
+            if self.rule_ids.is_empty() {
+                self.rule_ids.push(id);
+                self.actions.push(Action::Ignore);
+            }
+            return false;
+        }
+
+        self.rule_ids.push(id);
+        for action in &rule.actions {
+            if !self.actions.contains(action) {
+                self.actions.push(*action);
+            }
+        }
+        // Stage implies sticky
+        if self.has_action(Action::Stage) {
+            self.actions.retain(|action| *action != Action::Sticky);
+        }
+        if self.output.is_none() {
+            self.output = rule.output.clone();
+        }
+        true
+    }
+
+    /// Human readable summary like `sticky, float, output=DP-1`
+    pub fn describe(&self) -> String {
+        let mut actions: Vec<String> = self
+            .actions
+            .iter()
+            .map(|action| action.as_str().to_string())
+            .collect();
+        if let Some(output) = &self.output {
+            actions.push(format!("output={}", output));
+        }
+        actions.join(", ")
+    }
+}
+
+/// On-disk format of the rules state file
+#[derive(Default, Serialize, Deserialize)]
+struct RulesFile {
+    #[serde(default)]
+    rules: Vec<Rule>,
+}
+
+/// Static rules from the config followed by rules added at runtime
+/// Rule IDs are 1-based positions in that combined list, evaluation follows priorities
+#[derive(Debug, Default)]
+pub struct RuleSet {
+    config: Vec<Rule>,
+    runtime: Vec<Rule>,
+    mode: MatchMode,
+    /// Rules state file, None keeps runtime rules in memory only
+    path: Option<PathBuf>,
+}
+
+/// Get rules state file path ($XDG_STATE_HOME/nsticky/rules.toml)
+pub fn rules_path() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?)
+            .join(".local")
+            .join("state"),
+    };
+    Some(base.join("nsticky").join("rules.toml"))
+}
+
+impl RuleSet {
+    /// Merge config rules with the persisted runtime rules
+    pub fn load(config: Vec<Rule>, mode: MatchMode) -> Result<Self> {
+        for rule in &config {
+            rule.validate()?;
+            if let Err(e) = rule.check_backend() {
+                tracing::warn!("Rule {} can't work: {e}", rule.describe());
+            }
+        }
+
+        let path = rules_path();
+        let runtime = match &path {
+            Some(path) if path.exists() => {
+                let content = std::fs::read_to_string(path)?;
+                let file: RulesFile = toml::from_str(&content)
+                    .map_err(|e| anyhow::anyhow!("Invalid rules file {}: {}", path.display(), e))?;
+                for rule in &file.rules {
+                    rule.validate()?;
+                    if let Err(e) = rule.check_backend() {
+                        tracing::warn!("Rule {} can't work: {e}", rule.describe());
+                    }
+                }
+                file.rules
+            }
+            _ => Vec::new(),
+        };
+
+        Ok(Self {
+            config,
+            runtime,
+            mode,
+            path,
+        })
+    }
+
+    /// Config rules only, used when the rules state file can't be read
+    pub fn from_config(config: Vec<Rule>, mode: MatchMode) -> Self {
+        Self {
+            config: config
+                .into_iter()
+                .filter(|r| r.validate().is_ok())
+                .collect(),
+            runtime: Vec::new(),
+            mode,
+            path: None,
+        }
+    }
+
+    /// Rules with their ID and source, in evaluation order
+    fn ordered(&self) -> Vec<(usize, &Rule, RuleSource)> {
+        let mut rules: Vec<(usize, &Rule, RuleSource)> = self
+            .config
+            .iter()
+            .map(|rule| (rule, RuleSource::Config))
+            .chain(self.runtime.iter().map(|rule| (rule, RuleSource::Runtime)))
+            .enumerate()
+            .map(|(i, (rule, source))| (i + 1, rule, source))
+            .collect();
+        // Stable, so equal priorities keep config rules first and runtime rules in the order they were added
+        rules.sort_by_key(|(_, rule, _)| std::cmp::Reverse(rule.priority));
+        rules
+    }
+
+    /// All rules with their ID and source, in evaluation order
+    pub fn list(&self) -> Vec<(usize, Rule, RuleSource)> {
+        self.ordered()
+            .into_iter()
+            .map(|(id, rule, source)| (id, rule.clone(), source))
+            .collect()
+    }
+
+    /// Run a window through the rules, None when no rule matches
+    pub fn evaluate(&self, window: &WindowInfo) -> Option<Outcome> {
+        let mut outcome = Outcome {
+            rule_ids: Vec::new(),
+            actions: Vec::new(),
+            output: None,
+        };
+        for (id, rule, _) in self.ordered() {
+            if !rule.matches(window) {
+                continue;
+            }
+            if !outcome.merge(id, rule) || self.mode == MatchMode::First {
+                break;
+            }
+        }
+        if outcome.rule_ids.is_empty() {
+            return None;
+        }
+        Some(outcome)
+    }
+
+    /// Add a runtime rule and persist it, returns its ID
+    pub fn add(&mut self, rule: Rule) -> Result<usize> {
+        rule.validate()?;
+        rule.check_backend()?;
+        self.runtime.push(rule);
+        if let Err(e) = self.save() {
+            self.runtime.pop();
+            return Err(e);
+        }
+        Ok(self.config.len() + self.runtime.len())
+    }
+
+    /// Remove a runtime rule by ID
+    /// Cases: unknown ID -> error, config rule -> error, runtime rule -> removed and persisted
+    pub fn remove(&mut self, id: usize) -> Result<Rule> {
+        if id == 0 || id > self.config.len() + self.runtime.len() {
+            return Err(anyhow::anyhow!("No rule with ID {}", id));
+        }
+        if id <= self.config.len() {
+            return Err(anyhow::anyhow!(
+                "Rule {} is defined in config.toml, remove it there",
+                id
+            ));
+        }
+
+        let index = id - self.config.len() - 1;
+        let rule = self.runtime.remove(index);
+        if let Err(e) = self.save() {
+            self.runtime.insert(index, rule);
+            return Err(e);
+        }
+        Ok(rule)
+    }
+
+    /// Write runtime rules to the state file, atomically
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let file = RulesFile {
+            rules: self.runtime.clone(),
+        };
+        let content = format!(
+            "# Managed by nsticky, edit with `nsticky rule add` / `nsticky rule remove`\n{}",
+            toml::to_string(&file)?
+        );
+        let tmp_path = path.with_extension("toml.tmp");
+        std::fs::write(&tmp_path, content)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+/// Compositor session variables, the first one set identifies the session
+const SESSION_VARS: [&str; 4] = ["NIRI_SOCKET", "SWAYSOCK", "I3SOCK", "WAYLAND_DISPLAY"];
+
+/// Short stable id of the compositor session, None outside of one
+/// Concurrent sessions (a TTY session and a nested one) get different ids
+pub fn id() -> Option<String> {
+    let value = SESSION_VARS
+        .iter()
+        .find_map(|var| std::env::var_os(var).filter(|value| !value.is_empty()))?;
+    Some(format!("{:08x}", fnv1a(value.as_encoded_bytes())))
+}
+
+/// Control socket of this session's daemon, $NSTICKY_SOCKET wins over the derived path
+pub fn socket_path() -> String {
+    if let Some(path) = std::env::var_os("NSTICKY_SOCKET").filter(|path| !path.is_empty()) {
+        return path.to_string_lossy().into_owned();
+    }
+    match id() {
+        Some(id) => format!("/tmp/niri_sticky_cli-{id}.sock"),
+        None => "/tmp/niri_sticky_cli.sock".to_string(),
+    }
+}
+
+/// Read-only socket next to the control socket, e.g. /tmp/niri_sticky_cli-<id>-ro.sock
+pub fn read_only_socket_path() -> String {
+    let path = socket_path();
+    match path.strip_suffix(".sock") {
+        Some(stem) => format!("{stem}-ro.sock"),
+        None => format!("{path}-ro"),
+    }
+}
+
+/// State export file name inside $XDG_RUNTIME_DIR/nsticky
+pub fn state_file_name() -> String {
+    match id() {
+        Some(id) => format!("state-{id}.json"),
+        None => "state.json".to_string(),
+    }
+}
+
+/// Where this session's files live, printed by `nsticky session`
+pub fn describe() -> String {
+    let state = crate::export::export_path()
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|| "none (XDG_RUNTIME_DIR is not set)".to_string());
+    format!(
+        "Session: {}\nSocket: {}\nRead-only socket: {}\nState file: {}\n",
+        id().as_deref().unwrap_or("none"),
+        socket_path(),
+        read_only_socket_path(),
+        state
+    )
+}
+
+/// FNV-1a folded to 32 bits, stable across builds unlike the std hasher
+fn fnv1a(bytes: &[u8]) -> u32 {
+    let hash = bytes.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    (hash ^ (hash >> 32)) as u32
+}
@@ -0,0 +1,258 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tokio::sync::broadcast;
+
+use crate::business::{BusinessLogic, StateChanges};
+use crate::system_integration::WindowInfo;
+
+/// Per-day counters older than this many days are dropped
+const KEEP_DAYS: usize = 90;
+
+/// Counter key of windows without an app ID
+pub const NO_APP_ID: &str = "(no app ID)";
+
+/// How often counters changed by workspace follows are written out
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Counters of one application
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppStats {
+    /// Times one of its windows became sticky
+    pub stuck: u64,
+    /// Times one of its windows stopped being sticky
+    pub unstuck: u64,
+    /// Times one of its windows was staged
+    pub staged: u64,
+    /// Times one of its windows followed a workspace switch
+    pub followed: u64,
+}
+
+/// Counters of one UTC day
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DayStats {
+    pub stuck: u64,
+    pub staged: u64,
+    pub followed: u64,
+}
+
+/// Usage counters kept in the stats state file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsageStats {
+    /// Day counting started, YYYY-MM-DD
+    pub since: Option<String>,
+    /// Counters per app ID
+    pub apps: BTreeMap<String, AppStats>,
+    /// Counters per day (YYYY-MM-DD), the last KEEP_DAYS days only
+    pub days: BTreeMap<String, DayStats>,
+}
+
+impl UsageStats {
+    /// Counters of the app and of today, for every window in `ids`
+    fn record(
+        &mut self,
+        ids: &[u64],
+        app_ids: &HashMap<u64, String>,
+        mut bump: impl FnMut(&mut AppStats, &mut DayStats),
+    ) {
+        if ids.is_empty() {
+            return;
+        }
+        let today = crate::audit::date(SystemTime::now());
+        self.since.get_or_insert_with(|| today.clone());
+        let day = self.days.entry(today).or_default();
+        for id in ids {
+            let app_id = app_ids.get(id).map_or(NO_APP_ID, String::as_str);
+            bump(self.apps.entry(app_id.to_string()).or_default(), day);
+        }
+        self.prune_days();
+    }
+
+    /// Add the counters of `other` to these
+    fn merge(&mut self, other: &UsageStats) {
+        self.since = match (self.since.take(), other.since.clone()) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        for (app_id, counts) in &other.apps {
+            let app = self.apps.entry(app_id.clone()).or_default();
+            app.stuck += counts.stuck;
+            app.unstuck += counts.unstuck;
+            app.staged += counts.staged;
+            app.followed += counts.followed;
+        }
+        for (date, counts) in &other.days {
+            let day = self.days.entry(date.clone()).or_default();
+            day.stuck += counts.stuck;
+            day.staged += counts.staged;
+            day.followed += counts.followed;
+        }
+        self.prune_days();
+    }
+
+    fn prune_days(&mut self) {
+        while self.days.len() > KEEP_DAYS {
+            self.days.pop_first();
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.apps.is_empty() && self.days.is_empty()
+    }
+}
+
+/// Counts not written to the stats file yet, None while statistics are off
+/// Daemons of concurrent sessions share the file, so each only adds its own counts to it
+static PENDING: Mutex<Option<UsageStats>> = Mutex::new(None);
+
+/// Get stats state file path ($XDG_STATE_HOME/nsticky/stats.json)
+pub fn stats_path() -> Option<PathBuf> {
+    crate::rules::rules_path().map(|path| path.with_file_name("stats.json"))
+}
+
+/// Counters on disk plus those not written yet, None when statistics are off
+pub fn current() -> Option<UsageStats> {
+    let pending = PENDING.lock().unwrap_or_else(|e| e.into_inner()).clone()?;
+    let mut stats = match stats_path().map(|path| load(&path)) {
+        Some(Ok(stats)) => stats,
+        Some(Err(e)) => {
+            tracing::warn!("Failed to read usage statistics: {e:?}");
+            UsageStats::default()
+        }
+        None => UsageStats::default(),
+    };
+    stats.merge(&pending);
+    Some(stats)
+}
+
+fn with_stats(update: impl FnOnce(&mut UsageStats)) {
+    if let Some(stats) = PENDING.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        update(stats);
+    }
+}
+
+/// Count sticky windows that followed a workspace switch, no-op while statistics are off
+pub fn record_follows(moved: &[u64], windows: &[WindowInfo]) {
+    let app_ids = windows
+        .iter()
+        .filter(|w| moved.contains(&w.id))
+        .filter_map(|w| Some((w.id, w.app_id.clone()?)))
+        .collect();
+    with_stats(|stats| {
+        stats.record(moved, &app_ids, |app, day| {
+            app.followed += 1;
+            day.followed += 1;
+        })
+    });
+}
+
+/// Counters in the stats file, an unparsable file is moved aside and counting starts over
+fn load(path: &Path) -> Result<UsageStats> {
+    if !path.exists() {
+        return Ok(UsageStats::default());
+    }
+    let content = std::fs::read_to_string(path)?;
+    match serde_json::from_str(&content) {
+        Ok(stats) => Ok(stats),
+        Err(e) => {
+            // Starting over beats refusing to count, keep the old file around for inspection
+            tracing::warn!(
+                "Invalid stats file {}, starting over: {e:?}",
+                path.display()
+            );
+            let _ = std::fs::rename(path, path.with_extension("json.bad"));
+            Ok(UsageStats::default())
+        }
+    }
+}
+
+/// Add pending counts to the stats file, atomically like the rules file
+/// Counts stay pending until the file was replaced, so a failed write is retried
+fn save(path: Option<&PathBuf>) -> Result<()> {
+    let Some(path) = path else {
+        return Ok(());
+    };
+    // Held across the write, counts recorded meanwhile must not be cleared unsaved
+    let mut pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(counts) = pending.as_mut().filter(|counts| !counts.is_empty()) else {
+        return Ok(());
+    };
+    let mut stats = load(path)?;
+    stats.merge(counts);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp_path = path.with_extension(format!("json.{}.tmp", std::process::id()));
+    std::fs::write(&tmp_path, serde_json::to_string_pretty(&stats)? + "\n")?;
+    std::fs::rename(&tmp_path, path)?;
+    *counts = UsageStats::default();
+    Ok(())
+}
+
+/// Write pending counts before the daemon exits, no-op while statistics are off
+pub fn flush() {
+    if let Err(e) = save(stats_path().as_ref()) {
+        tracing::warn!("Failed to save usage statistics: {e:?}");
+    }
+}
+
+/// Count sticky and stage changes per app and per day, persisting them in the stats file
+pub async fn run_stats(business_logic: BusinessLogic) -> Result<()> {
+    let path = stats_path();
+    // A restarted task keeps the counts the previous run didn't write
+    PENDING
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_or_insert_with(UsageStats::default);
+
+    let mut changes = business_logic.subscribe();
+    let mut flush = tokio::time::interval(FLUSH_INTERVAL);
+    let mut last = business_logic.snapshot().await;
+    loop {
+        tokio::select! {
+            change = changes.recv() => match change {
+                Ok(()) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => return save(path.as_ref()),
+            },
+            _ = flush.tick() => {
+                save(path.as_ref())?;
+                continue;
+            }
+        }
+
+        let snapshot = business_logic.snapshot().await;
+        if snapshot.sticky == last.sticky && snapshot.staged == last.staged {
+            continue;
+        }
+
+        let windows = crate::system_integration::get_full_window_info()
+            .await
+            .unwrap_or_default();
+        let app_ids: HashMap<u64, String> = windows
+            .iter()
+            .filter_map(|w| Some((w.id, w.app_id.clone()?)))
+            .collect();
+        let is_open = |id| windows.iter().any(|w| w.id == id);
+        let changes = StateChanges::between(&last, &snapshot, is_open);
+        last = snapshot;
+
+        with_stats(|stats| {
+            stats.record(&changes.stuck, &app_ids, |app, day| {
+                app.stuck += 1;
+                day.stuck += 1;
+            });
+            stats.record(&changes.unstuck, &app_ids, |app, _| app.unstuck += 1);
+            stats.record(&changes.staged, &app_ids, |app, day| {
+                app.staged += 1;
+                day.staged += 1;
+            });
+        });
+        save(path.as_ref())?;
+    }
+}
@@ -0,0 +1,84 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// First restart delay, doubled after every failure up to MAX_BACKOFF
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// A task that ran this long before failing starts over at MIN_BACKOFF
+const HEALTHY_AFTER: Duration = Duration::from_secs(60);
+
+/// Health of a supervised task, shown by `nsticky status`
+#[derive(Debug, Clone)]
+pub struct TaskHealth {
+    pub name: &'static str,
+    /// False while waiting to be restarted
+    pub running: bool,
+    pub restarts: u32,
+    /// When and why the task last stopped
+    pub last_error: Option<(Instant, String)>,
+}
+
+static TASKS: Mutex<BTreeMap<&'static str, TaskHealth>> = Mutex::new(BTreeMap::new());
+
+fn update(name: &'static str, f: impl FnOnce(&mut TaskHealth)) {
+    let mut tasks = TASKS.lock().unwrap_or_else(|e| e.into_inner());
+    let health = tasks.entry(name).or_insert(TaskHealth {
+        name,
+        running: false,
+        restarts: 0,
+        last_error: None,
+    });
+    f(health);
+}
+
+/// Health of every supervised task, sorted by name
+pub fn health() -> Vec<TaskHealth> {
+    let tasks = TASKS.lock().unwrap_or_else(|e| e.into_inner());
+    tasks.values().cloned().collect()
+}
+
+/// Run a long-lived task, restarting it with exponential backoff whenever it fails, panics or exits
+/// `task` is called again for every restart
+pub fn spawn<F, Fut>(name: &'static str, mut task: F)
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    update(name, |health| health.running = true);
+    tokio::spawn(async move {
+        let mut backoff = MIN_BACKOFF;
+        loop {
+            let started = Instant::now();
+            // Running the task on its own turns a panic into a JoinError instead of killing us
+            let error = match tokio::spawn(task()).await {
+                Ok(Ok(())) => "exited".to_string(),
+                Ok(Err(e)) => format!("{e:#}"),
+                Err(e) => e.to_string(),
+            };
+            if started.elapsed() >= HEALTHY_AFTER {
+                backoff = MIN_BACKOFF;
+            }
+            tracing::error!(
+                task = name,
+                "{} stopped: {}, restarting in {}s",
+                name,
+                error,
+                backoff.as_secs()
+            );
+            update(name, |health| {
+                health.running = false;
+                health.last_error = Some((Instant::now(), error));
+            });
+
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+            update(name, |health| {
+                health.running = true;
+                health.restarts += 1;
+            });
+        }
+    });
+}
@@ -0,0 +1,425 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+};
+
+use crate::logging;
+use crate::metrics::ErrorKind;
+use crate::system_integration::{CompositorEvent, WindowInfo, WorkspaceInfo};
+
+const MAGIC: &[u8; 6] = b"i3-ipc";
+
+const RUN_COMMAND: u32 = 0;
+const GET_WORKSPACES: u32 = 1;
+const SUBSCRIBE: u32 = 2;
+const GET_TREE: u32 = 4;
+
+const EVENT_WORKSPACE: u32 = 0x8000_0000;
+const EVENT_OUTPUT: u32 = 0x8000_0001;
+const EVENT_WINDOW: u32 = 0x8000_0003;
+
+/// IPC socket of the running sway or i3
+fn socket_path() -> Result<String> {
+    std::env::var("SWAYSOCK")
+        .or_else(|_| std::env::var("I3SOCK"))
+        .map_err(|_| anyhow::anyhow!("Neither SWAYSOCK nor I3SOCK is set"))
+}
+
+/// Whether the IPC socket accepts connections
+pub async fn is_reachable() -> bool {
+    match socket_path() {
+        Ok(socket_path) => UnixStream::connect(&socket_path).await.is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Messages are the magic string, payload length and type in native byte order, then the payload
+async fn write_message(stream: &mut UnixStream, kind: u32, payload: &str) -> Result<()> {
+    let mut message = Vec::with_capacity(14 + payload.len());
+    message.extend_from_slice(MAGIC);
+    message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+    message.extend_from_slice(&kind.to_ne_bytes());
+    message.extend_from_slice(payload.as_bytes());
+    logging::trace_ipc(">", "sway", &message);
+    stream.write_all(&message).await?;
+    Ok(())
+}
+
+async fn read_message(stream: &mut UnixStream) -> Result<(u32, Value)> {
+    let mut header = [0u8; 14];
+    stream.read_exact(&mut header).await?;
+    if &header[..6] != MAGIC {
+        anyhow::bail!("Invalid i3 IPC reply");
+    }
+    let len = u32::from_ne_bytes(header[6..10].try_into()?) as usize;
+    let kind = u32::from_ne_bytes(header[10..14].try_into()?);
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload).await?;
+    if logging::trace_ipc_enabled() {
+        logging::trace_ipc("<", "sway", &[&header[..], &payload].concat());
+    }
+    Ok((kind, serde_json::from_slice(&payload)?))
+}
+
+/// Send one request on a fresh connection and return the reply
+async fn request(kind: u32, payload: &str) -> Result<Value> {
+    let mut stream = UnixStream::connect(socket_path()?).await?;
+    tracing::debug!(kind, payload, "sway request");
+    write_message(&mut stream, kind, payload).await?;
+    let (_, reply) = read_message(&mut stream).await?;
+    tracing::trace!(%reply, "sway reply");
+    Ok(reply)
+}
+
+/// Quote a workspace or output name for a command
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Parse a window container, sway sets app_id for Wayland clients and window_properties for X11 ones
+fn parse_container(node: &Value, workspace_id: Option<u64>, floating: bool) -> Option<WindowInfo> {
+    let id = node.get("id").and_then(|v| v.as_u64())?;
+    let app_id = node
+        .get("app_id")
+        .and_then(|v| v.as_str())
+        .or_else(|| {
+            node.get("window_properties")
+                .and_then(|p| p.get("class"))
+                .and_then(|v| v.as_str())
+        })
+        .map(|s| s.to_string());
+    let title = node
+        .get("name")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+    Some(WindowInfo {
+        id,
+        app_id,
+        title,
+        workspace_id,
+        is_focused: node.get("focused").and_then(|v| v.as_bool()) == Some(true),
+        is_floating: floating || node.get("type").and_then(|v| v.as_str()) == Some("floating_con"),
+        is_fullscreen: node
+            .get("fullscreen_mode")
+            .and_then(|v| v.as_u64())
+            .is_some_and(|mode| mode > 0),
+    })
+}
+
+/// Containers holding a client window, as opposed to splits and workspaces
+fn is_window(node: &Value) -> bool {
+    let has_children = ["nodes", "floating_nodes"].iter().any(|key| {
+        node.get(key)
+            .and_then(|v| v.as_array())
+            .is_some_and(|children| !children.is_empty())
+    });
+    let is_con = matches!(
+        node.get("type").and_then(|v| v.as_str()),
+        Some("con") | Some("floating_con")
+    );
+    let has_client = node.get("pid").is_some_and(|v| !v.is_null())
+        || node.get("window").is_some_and(|v| !v.is_null());
+    is_con && !has_children && has_client
+}
+
+/// Walk the layout tree collecting windows with the workspace they are on
+fn collect_windows(
+    node: &Value,
+    workspace_id: Option<u64>,
+    floating: bool,
+    windows: &mut Vec<WindowInfo>,
+) {
+    let workspace_id = if node.get("type").and_then(|v| v.as_str()) == Some("workspace") {
+        node.get("id").and_then(|v| v.as_u64())
+    } else {
+        workspace_id
+    };
+    if is_window(node) {
+        windows.extend(parse_container(node, workspace_id, floating));
+        return;
+    }
+    for (key, is_floating) in [("nodes", floating), ("floating_nodes", true)] {
+        if let Some(children) = node.get(key).and_then(|v| v.as_array()) {
+            for child in children {
+                collect_windows(child, workspace_id, is_floating, windows);
+            }
+        }
+    }
+}
+
+/// Get all workspaces from sway
+pub async fn get_workspaces() -> Result<Vec<WorkspaceInfo>> {
+    let reply = request(GET_WORKSPACES, "").await?;
+    let mut workspaces = Vec::new();
+    if let Some(arr) = reply.as_array() {
+        for item in arr {
+            if let Some(id) = item.get("id").and_then(|v| v.as_u64()) {
+                workspaces.push(WorkspaceInfo {
+                    id,
+                    // Named workspaces without a number report -1
+                    idx: item.get("num").and_then(|v| v.as_u64()),
+                    name: item
+                        .get("name")
+                        .and_then(|v| v.as_str())
+                        .map(|s| s.to_string()),
+                    output: item
+                        .get("output")
+                        .and_then(|v| v.as_str())
+                        .map(|s| s.to_string()),
+                    is_active: item.get("visible").and_then(|v| v.as_bool()) == Some(true),
+                    is_focused: item.get("focused").and_then(|v| v.as_bool()) == Some(true),
+                });
+            }
+        }
+    }
+    Ok(workspaces)
+}
+
+/// Get full window information from sway
+pub async fn get_full_window_info() -> Result<Vec<WindowInfo>> {
+    let tree = request(GET_TREE, "").await?;
+    let mut windows = Vec::new();
+    collect_windows(&tree, None, false, &mut windows);
+    Ok(windows)
+}
+
+/// Get active window ID from sway
+pub async fn get_active_window_id() -> Result<u64> {
+    get_full_window_info()
+        .await?
+        .into_iter()
+        .find(|w| w.is_focused)
+        .map(|w| w.id)
+        .ok_or_else(|| anyhow::anyhow!("Focused window id not found"))
+}
+
+/// Run a command and return the raw reply, a command sway refused is an error
+async fn run_command(command: &str) -> Result<String> {
+    let result = request(RUN_COMMAND, command).await;
+    crate::metrics::record_move();
+    let reply = match result {
+        Ok(reply) => reply,
+        Err(e) => {
+            crate::metrics::record_error(ErrorKind::MoveFailed, &e);
+            return Err(e);
+        }
+    };
+    // sway answers with one {"success": bool} per command
+    let succeeded = matches!(&reply, Value::Array(replies) if replies
+        .iter()
+        .all(|r| r.get("success").and_then(|v| v.as_bool()) == Some(true)));
+    if !succeeded {
+        crate::metrics::record_error(ErrorKind::MoveFailed, &reply);
+        anyhow::bail!("sway refused the command: {}", reply);
+    }
+    Ok(reply.to_string())
+}
+
+async fn workspace_name(ws_id: u64) -> Result<String> {
+    get_workspaces()
+        .await?
+        .into_iter()
+        .find(|ws| ws.id == ws_id)
+        .and_then(|ws| ws.name)
+        .ok_or_else(|| anyhow::anyhow!("Workspace {} not found in sway", ws_id))
+}
+
+/// Move window to workspace
+pub async fn move_to_workspace(win_id: u64, ws_id: u64) -> Result<()> {
+    let name = workspace_name(ws_id).await?;
+    let response = run_command(&format!(
+        "[con_id={}] move container to workspace {}",
+        win_id,
+        quote(&name)
+    ))
+    .await?;
+    tracing::info!(
+        operation = "move_to_workspace",
+        window_id = win_id,
+        workspace_id = ws_id,
+        "move_to_workspace response: {}",
+        response
+    );
+    Ok(())
+}
+
+/// Move window to named workspace, sway creates it when missing
+pub async fn move_to_named_workspace(win_id: u64, workspace_name: &str) -> Result<()> {
+    let response = run_command(&format!(
+        "[con_id={}] move container to workspace {}",
+        win_id,
+        quote(workspace_name)
+    ))
+    .await?;
+    tracing::info!(
+        operation = "move_to_named_workspace",
+        window_id = win_id,
+        "move_to_named_workspace {} response: {}",
+        workspace_name,
+        response
+    );
+    Ok(())
+}
+
+/// Make a window floating
+pub async fn move_to_floating(win_id: u64) -> Result<()> {
+    let response = run_command(&format!("[con_id={}] floating enable", win_id)).await?;
+    tracing::info!(
+        operation = "move_to_floating",
+        window_id = win_id,
+        "move_to_floating response: {}",
+        response
+    );
+    Ok(())
+}
+
+/// Focus a window
+pub async fn focus_window(win_id: u64) -> Result<()> {
+    let response = run_command(&format!("[con_id={}] focus", win_id)).await?;
+    tracing::info!(
+        operation = "focus_window",
+        window_id = win_id,
+        "focus_window response: {}",
+        response
+    );
+    Ok(())
+}
+
+/// Move window next to `anchor_id`, onto the anchor's workspace
+pub async fn move_next_to(win_id: u64, anchor_id: u64) -> Result<()> {
+    let response = run_command(&format!(
+        "[con_id={anchor}] mark --add {mark}; [con_id={win}] move container to mark {mark}; [con_id={anchor}] unmark {mark}",
+        anchor = anchor_id,
+        win = win_id,
+        mark = "_nsticky_anchor"
+    ))
+    .await?;
+    tracing::info!(
+        operation = "move_next_to",
+        window_id = win_id,
+        "move_next_to {} response: {}",
+        anchor_id,
+        response
+    );
+    Ok(())
+}
+
+/// Move window to the visible workspace of another output
+pub async fn move_to_output(win_id: u64, output_name: &str) -> Result<()> {
+    let response = run_command(&format!(
+        "[con_id={}] move container to output {}",
+        win_id,
+        quote(output_name)
+    ))
+    .await?;
+    tracing::info!(
+        operation = "move_to_output",
+        window_id = win_id,
+        "move_to_output {} response: {}",
+        output_name,
+        response
+    );
+    Ok(())
+}
+
+/// Subscription to sway workspace, window and output events
+pub struct EventStream {
+    stream: UnixStream,
+    /// The current workspaces and windows are sent first, like niri does
+    started: bool,
+}
+
+impl EventStream {
+    pub async fn connect() -> Result<Self> {
+        let mut stream = UnixStream::connect(socket_path()?).await?;
+        write_message(
+            &mut stream,
+            SUBSCRIBE,
+            &json!(["workspace", "window", "output"]).to_string(),
+        )
+        .await?;
+        let (_, reply) = read_message(&mut stream).await?;
+        if reply.get("success").and_then(|v| v.as_bool()) != Some(true) {
+            anyhow::bail!("Failed to subscribe to sway events: {}", reply);
+        }
+        Ok(Self {
+            stream,
+            started: false,
+        })
+    }
+
+    /// Next event from sway, None once the connection closes
+    pub async fn next(&mut self) -> Result<Option<Vec<CompositorEvent>>> {
+        if !self.started {
+            self.started = true;
+            return Ok(Some(vec![
+                CompositorEvent::WorkspacesChanged(get_workspaces().await?),
+                CompositorEvent::WindowsChanged(get_full_window_info().await?),
+            ]));
+        }
+
+        let (kind, event) = match read_message(&mut self.stream).await {
+            Ok(message) => message,
+            Err(e)
+                if e.downcast_ref::<std::io::Error>()
+                    .is_some_and(|e| e.kind() == std::io::ErrorKind::UnexpectedEof) =>
+            {
+                return Ok(None);
+            }
+            // The whole message was read, so the stream is still usable
+            Err(e) if e.downcast_ref::<serde_json::Error>().is_some() => {
+                crate::metrics::record_error(
+                    ErrorKind::EventParse,
+                    format_args!("sway event: {}", e),
+                );
+                return Ok(Some(Vec::new()));
+            }
+            Err(e) => return Err(e),
+        };
+        let change = event.get("change").and_then(|v| v.as_str()).unwrap_or("");
+        tracing::trace!(kind, change, "sway event");
+
+        let events = match kind {
+            EVENT_WORKSPACE => match change {
+                "focus" => {
+                    let mut events =
+                        vec![CompositorEvent::WorkspacesChanged(get_workspaces().await?)];
+                    if let Some(ws_id) = event
+                        .get("current")
+                        .and_then(|ws| ws.get("id"))
+                        .and_then(|v| v.as_u64())
+                    {
+                        events.push(CompositorEvent::WorkspaceActivated(ws_id));
+                    }
+                    events
+                }
+                "init" | "empty" | "move" | "rename" | "reload" => {
+                    vec![CompositorEvent::WorkspacesChanged(get_workspaces().await?)]
+                }
+                _ => Vec::new(),
+            },
+            EVENT_OUTPUT => vec![CompositorEvent::WorkspacesChanged(get_workspaces().await?)],
+            EVENT_WINDOW => {
+                let Some(container) = event.get("container") else {
+                    return Ok(Some(Vec::new()));
+                };
+                let id = container.get("id").and_then(|v| v.as_u64());
+                match (change, id) {
+                    ("focus", Some(id)) => vec![CompositorEvent::WindowFocusChanged(Some(id))],
+                    ("close", Some(id)) => vec![CompositorEvent::WindowClosed(id)],
+                    ("new" | "title" | "floating" | "fullscreen_mode" | "move", _) => {
+                        parse_container(container, None, false)
+                            .map(CompositorEvent::WindowOpenedOrChanged)
+                            .into_iter()
+                            .collect()
+                    }
+                    _ => Vec::new(),
+                }
+            }
+            _ => Vec::new(),
+        };
+        Ok(Some(events))
+    }
+}
@@ -1,11 +1,12 @@
 use anyhow::Result;
-use serde_json::{Value, json};
 use std::collections::HashSet;
-use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-    net::UnixStream,
-    process::Command,
-};
+use std::future::Future;
+use std::sync::OnceLock;
+
+#[cfg(feature = "foreign-toplevel")]
+use crate::foreign_toplevel;
+use crate::metrics::{ErrorKind, Operation, timed};
+use crate::{niri, sway};
 
 /// Window information structure
 #[derive(Debug, Clone)]
@@ -13,89 +14,289 @@ pub struct WindowInfo {
     pub id: u64,
     pub app_id: Option<String>,
     pub title: Option<String>,
+    pub workspace_id: Option<u64>,
+    pub is_focused: bool,
+    pub is_floating: bool,
+    /// Only reported by compositors that expose it, false otherwise
+    pub is_fullscreen: bool,
 }
 
-/// Get active workspace ID from Niri
-pub async fn get_active_workspace_id() -> Result<u64> {
-    let output = tokio::process::Command::new("niri")
-        .args(["msg", "-j", "workspaces"])
-        .output()
-        .await?;
+/// Workspace information structure
+#[derive(Debug, Clone)]
+pub struct WorkspaceInfo {
+    pub id: u64,
+    pub idx: Option<u64>,
+    pub name: Option<String>,
+    pub output: Option<String>,
+    pub is_active: bool,
+    pub is_focused: bool,
+}
+
+/// Compositor nsticky talks to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Niri,
+    /// sway or i3, over the i3 IPC protocol
+    Sway,
+    /// Any compositor with ext-foreign-toplevel-list, window tracking only (experimental)
+    #[cfg(feature = "foreign-toplevel")]
+    #[serde(rename = "foreign-toplevel")]
+    ForeignToplevel,
+}
+
+impl Backend {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Backend::Niri => "niri",
+            Backend::Sway => "sway",
+            #[cfg(feature = "foreign-toplevel")]
+            Backend::ForeignToplevel => "foreign-toplevel",
+        }
+    }
 
-    if !output.status.success() {
-        anyhow::bail!("Failed to get workspaces");
+    /// Whether windows report fullscreen state, only the i3 IPC does
+    pub fn reports_fullscreen(&self) -> bool {
+        matches!(self, Backend::Sway)
     }
+}
+
+static BACKEND: OnceLock<Backend> = OnceLock::new();
+
+/// Pick the backend from the environment the compositor sets up for its clients
+pub fn detect_backend() -> Result<Backend> {
+    let is_set = |var: &str| std::env::var_os(var).is_some_and(|value| !value.is_empty());
+    if is_set("NIRI_SOCKET") {
+        return Ok(Backend::Niri);
+    }
+    if is_set("SWAYSOCK") || is_set("I3SOCK") {
+        return Ok(Backend::Sway);
+    }
+    #[cfg(feature = "foreign-toplevel")]
+    if is_set("WAYLAND_DISPLAY") {
+        return Ok(Backend::ForeignToplevel);
+    }
+    if is_set("HYPRLAND_INSTANCE_SIGNATURE") {
+        anyhow::bail!(
+            "Hyprland has no dedicated backend, build with the foreign-toplevel feature to track its windows"
+        );
+    }
+    anyhow::bail!("No supported compositor found, none of NIRI_SOCKET, SWAYSOCK or I3SOCK is set")
+}
+
+/// Backend from the command line, then the config, then detection, falling back to niri
+pub fn resolve_backend(flag: Option<Backend>, configured: Option<Backend>) -> Backend {
+    flag.or(configured).unwrap_or_else(|| {
+        detect_backend().unwrap_or_else(|e| {
+            tracing::warn!("{e}, assuming niri");
+            Backend::Niri
+        })
+    })
+}
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let json: serde_json::Value = serde_json::from_str(&stdout)?;
+/// Select the compositor backend, only the first call has an effect
+pub fn set_backend(backend: Backend) {
+    let _ = BACKEND.set(backend);
+}
+
+pub fn backend() -> Backend {
+    *BACKEND.get_or_init(Backend::default)
+}
+
+/// Compositor events the daemon reacts to, shaped after niri's event stream
+#[derive(Debug, Clone)]
+pub enum CompositorEvent {
+    /// Full workspace list, also sent when outputs change
+    WorkspacesChanged(Vec<WorkspaceInfo>),
+    WorkspaceActivated(u64),
+    WindowFocusChanged(Option<u64>),
+    /// Full window list, sent once the stream starts
+    WindowsChanged(Vec<WindowInfo>),
+    WindowOpenedOrChanged(WindowInfo),
+    WindowClosed(u64),
+}
+
+/// Event stream of the selected backend
+pub enum EventStream {
+    Niri(niri::EventStream),
+    Sway(sway::EventStream),
+    #[cfg(feature = "foreign-toplevel")]
+    ForeignToplevel(foreign_toplevel::EventStream),
+}
 
-    if let Some(workspaces) = json.as_array() {
-        for workspace in workspaces {
-            if workspace.get("is_active").and_then(|v| v.as_bool()) == Some(true)
-                && let Some(id) = workspace.get("id").and_then(|v| v.as_u64())
-            {
-                return Ok(id);
+impl EventStream {
+    #[tracing::instrument(level = "debug", skip_all, fields(backend = backend().as_str()))]
+    pub async fn connect() -> Result<Self> {
+        Ok(match backend() {
+            Backend::Niri => EventStream::Niri(niri::EventStream::connect().await?),
+            Backend::Sway => EventStream::Sway(sway::EventStream::connect().await?),
+            #[cfg(feature = "foreign-toplevel")]
+            Backend::ForeignToplevel => {
+                EventStream::ForeignToplevel(foreign_toplevel::EventStream::connect().await?)
             }
+        })
+    }
+
+    /// Next batch of events, None once the compositor closes the stream
+    pub async fn next(&mut self) -> Result<Option<Vec<CompositorEvent>>> {
+        match self {
+            EventStream::Niri(stream) => stream.next().await,
+            EventStream::Sway(stream) => stream.next().await,
+            #[cfg(feature = "foreign-toplevel")]
+            EventStream::ForeignToplevel(stream) => stream.next().await,
         }
     }
+}
+
+/// Whether the compositor's IPC socket accepts connections right now
+pub async fn is_reachable() -> bool {
+    match backend() {
+        Backend::Niri => niri::is_reachable().await,
+        Backend::Sway => sway::is_reachable().await,
+        // Windows are tracked over the Wayland connection, there is no socket to lose
+        #[cfg(feature = "foreign-toplevel")]
+        Backend::ForeignToplevel => true,
+    }
+}
+
+/// Time a compositor query and count it when it fails
+async fn query<T>(fut: impl Future<Output = Result<T>>) -> Result<T> {
+    let result = timed(Operation::CompositorRequest, fut).await;
+    if let Err(e) = &result {
+        crate::metrics::record_error(ErrorKind::QueryFailed, e);
+    }
+    result
+}
+
+/// Get all workspaces from the compositor
+#[tracing::instrument(level = "debug", skip_all, fields(backend = backend().as_str()))]
+pub async fn get_workspaces() -> Result<Vec<WorkspaceInfo>> {
+    query(async {
+        match backend() {
+            Backend::Niri => niri::get_workspaces().await,
+            Backend::Sway => sway::get_workspaces().await,
+            #[cfg(feature = "foreign-toplevel")]
+            Backend::ForeignToplevel => foreign_toplevel::get_workspaces().await,
+        }
+    })
+    .await
+}
 
-    anyhow::bail!("Active workspace not found");
+/// Get active workspace ID from the compositor
+pub async fn get_active_workspace_id() -> Result<u64> {
+    let workspaces = get_workspaces().await?;
+    // Each output has an active workspace, prefer the one on the focused output
+    workspaces
+        .iter()
+        .find(|ws| ws.is_active && ws.is_focused)
+        .or_else(|| workspaces.iter().find(|ws| ws.is_active))
+        .map(|ws| ws.id)
+        .ok_or_else(|| anyhow::anyhow!("Active workspace not found"))
+}
+
+/// Get the output a workspace lives on
+pub async fn get_workspace_output(ws_id: u64) -> Result<Option<String>> {
+    let workspaces = get_workspaces().await?;
+    Ok(workspaces
+        .into_iter()
+        .find(|ws| ws.id == ws_id)
+        .and_then(|ws| ws.output))
 }
 
-/// Get active window ID from Niri
+/// Get active window ID from the compositor
+#[tracing::instrument(level = "debug", skip_all, fields(backend = backend().as_str()))]
 pub async fn get_active_window_id() -> Result<u64> {
-    let output = tokio::process::Command::new("niri")
-        .args(["msg", "--json", "focused-window"])
-        .output()
-        .await?;
-    if !output.status.success() {
-        anyhow::bail!("Failed to get focused window");
-    }
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let json: serde_json::Value = serde_json::from_str(&stdout)?;
-    if let Some(id) = json.get("id").and_then(|v| v.as_u64()) {
-        Ok(id)
-    } else {
-        anyhow::bail!("Focused window id not found");
-    }
-}
-
-/// Get full window information from Niri
-async fn get_full_window_info() -> Result<Vec<WindowInfo>> {
-    let output = Command::new("niri")
-        .args(["msg", "--json", "windows"])
-        .output()
-        .await?;
-    if !output.status.success() {
-        anyhow::bail!("Failed to get windows list");
-    }
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let json: Value = serde_json::from_str(&stdout)?;
-    let mut windows = Vec::new();
-    if let Some(arr) = json.as_array() {
-        for item in arr {
-            if let Some(id) = item.get("id").and_then(|v| v.as_u64()) {
-                let app_id = item
-                    .get("app_id")
-                    .and_then(|v| v.as_str())
-                    .map(|s| s.to_string());
-                let title = item
-                    .get("title")
-                    .and_then(|v| v.as_str())
-                    .map(|s| s.to_string());
-                windows.push(WindowInfo { id, app_id, title });
-            }
+    query(async {
+        match backend() {
+            Backend::Niri => niri::get_active_window_id().await,
+            Backend::Sway => sway::get_active_window_id().await,
+            #[cfg(feature = "foreign-toplevel")]
+            Backend::ForeignToplevel => foreign_toplevel::get_active_window_id().await,
+        }
+    })
+    .await
+}
+
+/// Get full window information from the compositor
+#[tracing::instrument(level = "debug", skip_all, fields(backend = backend().as_str()))]
+pub async fn get_full_window_info() -> Result<Vec<WindowInfo>> {
+    query(async {
+        match backend() {
+            Backend::Niri => niri::get_full_window_info().await,
+            Backend::Sway => sway::get_full_window_info().await,
+            #[cfg(feature = "foreign-toplevel")]
+            Backend::ForeignToplevel => foreign_toplevel::get_full_window_info().await,
+        }
+    })
+    .await
+}
+
+/// Let the user pick a window by clicking it, None when cancelled
+/// Not timed, the user takes as long as they like
+#[tracing::instrument(level = "debug", skip_all, fields(backend = backend().as_str()))]
+pub async fn pick_window() -> Result<Option<u64>> {
+    match backend() {
+        Backend::Niri => niri::pick_window().await,
+        Backend::Sway => anyhow::bail!("Picking windows is not supported by sway"),
+        #[cfg(feature = "foreign-toplevel")]
+        Backend::ForeignToplevel => {
+            anyhow::bail!("Picking windows is not supported by the foreign-toplevel backend")
         }
     }
-    Ok(windows)
 }
 
-/// Get full window list from Niri
+/// Get full window list from the compositor
 pub async fn get_full_window_list() -> Result<HashSet<u64>> {
     let windows = get_full_window_info().await?;
     Ok(windows.into_iter().map(|w| w.id).collect())
 }
 
+/// Get the output a window lives on
+pub async fn get_window_output(win_id: u64) -> Result<Option<String>> {
+    let windows = get_full_window_info().await?;
+    let workspace_id = windows
+        .into_iter()
+        .find(|w| w.id == win_id)
+        .and_then(|w| w.workspace_id);
+    match workspace_id {
+        Some(ws_id) => get_workspace_output(ws_id).await,
+        None => Ok(None),
+    }
+}
+
+/// A window picked by ID, app ID or title
+#[derive(Debug, Clone)]
+pub enum WindowSelector {
+    Id(u64),
+    /// Exact app ID
+    AppId(String),
+    /// Title substring
+    Title(String),
+}
+
+impl WindowSelector {
+    pub fn matches(&self, window: &WindowInfo) -> bool {
+        match self {
+            WindowSelector::Id(id) => window.id == *id,
+            WindowSelector::AppId(app_id) => window.app_id.as_deref() == Some(app_id.as_str()),
+            WindowSelector::Title(title) => window
+                .title
+                .as_deref()
+                .is_some_and(|t| t.contains(title.as_str())),
+        }
+    }
+}
+
+/// Resolve a selector to a window ID, the first matching window wins
+pub async fn find_window(selector: &WindowSelector) -> Result<Option<u64>> {
+    match selector {
+        WindowSelector::Id(id) => Ok(get_full_window_list().await?.contains(id).then_some(*id)),
+        WindowSelector::AppId(appid) => find_window_by_appid(appid).await,
+        WindowSelector::Title(title) => find_window_by_title(title).await,
+    }
+}
+
 /// Find window by application ID
 pub async fn find_window_by_appid(appid: &str) -> Result<Option<u64>> {
     let windows = get_full_window_info().await?;
@@ -123,53 +324,85 @@ pub async fn find_window_by_title(title: &str) -> Result<Option<u64>> {
 }
 
 /// Move window to workspace
+#[tracing::instrument(level = "debug", skip_all, fields(backend = backend().as_str(), window_id = win_id, workspace_id = ws_id))]
 pub async fn move_to_workspace(win_id: u64, ws_id: u64) -> Result<()> {
-    let socket_path = std::env::var("NIRI_SOCKET")?;
-
-    let stream = UnixStream::connect(&socket_path).await?;
-    let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
-
-    let cmd = json!({
-        "Action": {
-            "MoveWindowToWorkspace": {
-                "window_id": win_id,
-                "focus": false,
-                "reference": { "Id": ws_id }
-            }
+    timed(Operation::CompositorRequest, async {
+        match backend() {
+            Backend::Niri => niri::move_to_workspace(win_id, ws_id).await,
+            Backend::Sway => sway::move_to_workspace(win_id, ws_id).await,
+            #[cfg(feature = "foreign-toplevel")]
+            Backend::ForeignToplevel => foreign_toplevel::unsupported().await,
         }
-    });
-    let cmd_str = serde_json::to_string(&cmd)? + "\n";
-
-    writer.write_all(cmd_str.as_bytes()).await?;
-    writer.flush().await?;
-
-    let mut response = String::new();
-    reader.read_line(&mut response).await?;
-    println!("move_to_workspace response: {}", response.trim());
-    Ok(())
+    })
+    .await
 }
 
 /// Move window to named workspace
+#[tracing::instrument(level = "debug", skip_all, fields(backend = backend().as_str(), window_id = win_id, workspace = workspace_name))]
 pub async fn move_to_named_workspace(win_id: u64, workspace_name: &str) -> Result<()> {
-    let socket_path = std::env::var("NIRI_SOCKET")?;
-    let stream = UnixStream::connect(&socket_path).await?;
-    let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
-    let cmd = json!({
-        "Action": {
-            "MoveWindowToWorkspace": {
-                "window_id": win_id,
-                "focus": false,
-                "reference": { "Name": workspace_name }
-            }
+    timed(Operation::CompositorRequest, async {
+        match backend() {
+            Backend::Niri => niri::move_to_named_workspace(win_id, workspace_name).await,
+            Backend::Sway => sway::move_to_named_workspace(win_id, workspace_name).await,
+            #[cfg(feature = "foreign-toplevel")]
+            Backend::ForeignToplevel => foreign_toplevel::unsupported().await,
+        }
+    })
+    .await
+}
+
+/// Make a window floating
+#[tracing::instrument(level = "debug", skip_all, fields(backend = backend().as_str(), window_id = win_id))]
+pub async fn move_to_floating(win_id: u64) -> Result<()> {
+    timed(Operation::CompositorRequest, async {
+        match backend() {
+            Backend::Niri => niri::move_to_floating(win_id).await,
+            Backend::Sway => sway::move_to_floating(win_id).await,
+            #[cfg(feature = "foreign-toplevel")]
+            Backend::ForeignToplevel => foreign_toplevel::unsupported().await,
+        }
+    })
+    .await
+}
+
+/// Give keyboard focus to a window
+#[tracing::instrument(level = "debug", skip_all, fields(backend = backend().as_str(), window_id = win_id))]
+pub async fn focus_window(win_id: u64) -> Result<()> {
+    timed(Operation::CompositorRequest, async {
+        match backend() {
+            Backend::Niri => niri::focus_window(win_id).await,
+            Backend::Sway => sway::focus_window(win_id).await,
+            #[cfg(feature = "foreign-toplevel")]
+            Backend::ForeignToplevel => foreign_toplevel::unsupported().await,
+        }
+    })
+    .await
+}
+
+/// Move window to a workspace, placed right after `anchor_id` which is already there
+#[tracing::instrument(level = "debug", skip_all, fields(backend = backend().as_str(), window_id = win_id, anchor = anchor_id, workspace_id = ws_id))]
+pub async fn move_next_to(win_id: u64, anchor_id: u64, ws_id: u64) -> Result<()> {
+    timed(Operation::CompositorRequest, async {
+        match backend() {
+            Backend::Niri => niri::move_next_to(win_id, anchor_id, ws_id).await,
+            Backend::Sway => sway::move_next_to(win_id, anchor_id).await,
+            #[cfg(feature = "foreign-toplevel")]
+            Backend::ForeignToplevel => foreign_toplevel::unsupported().await,
+        }
+    })
+    .await
+}
+
+/// Move window to the active workspace of another output
+#[tracing::instrument(level = "debug", skip_all, fields(backend = backend().as_str(), window_id = win_id, output = output_name))]
+pub async fn move_to_output(win_id: u64, output_name: &str) -> Result<()> {
+    timed(Operation::CompositorRequest, async {
+        match backend() {
+            Backend::Niri => niri::move_to_output(win_id, output_name).await,
+            Backend::Sway => sway::move_to_output(win_id, output_name).await,
+            #[cfg(feature = "foreign-toplevel")]
+            Backend::ForeignToplevel => foreign_toplevel::unsupported().await,
         }
-    });
-    let cmd_str = serde_json::to_string(&cmd)? + "\n";
-    writer.write_all(cmd_str.as_bytes()).await?;
-    writer.flush().await?;
-    let mut response = String::new();
-    reader.read_line(&mut response).await?;
-    println!("move_to_named_workspace response: {}", response.trim());
-    Ok(())
+    })
+    .await
 }
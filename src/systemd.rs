@@ -0,0 +1,45 @@
+use anyhow::Result;
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+
+/// Send a state update to the service manager (sd_notify protocol)
+/// Returns false when not running under a Type=notify service
+pub fn notify(state: &str) -> Result<bool> {
+    let Some(socket_path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return Ok(false);
+    };
+    let socket_path = socket_path.to_string_lossy().to_string();
+
+    let socket = UnixDatagram::unbound()?;
+    if let Some(name) = socket_path.strip_prefix('@') {
+        // Abstract socket namespace
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name.as_bytes())?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = name;
+            anyhow::bail!("Abstract notify sockets are only supported on Linux");
+        }
+    } else {
+        socket.send_to(state.as_bytes(), &socket_path)?;
+    }
+    Ok(true)
+}
+
+/// Get the watchdog interval requested by the service manager, if any
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = std::env::var("WATCHDOG_PID")
+        && pid.parse::<u32>().ok() != Some(std::process::id())
+    {
+        return None;
+    }
+    let usec = std::env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    if usec == 0 {
+        return None;
+    }
+    Some(Duration::from_micros(usec))
+}
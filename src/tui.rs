@@ -0,0 +1,323 @@
+use anyhow::Result;
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Cell, Row, Table, TableState},
+};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+    sync::mpsc,
+};
+
+use crate::{business::StateSnapshot, cli::send_command, system_integration};
+
+/// Everything the UI loop reacts to
+enum AppEvent {
+    Key(event::KeyEvent),
+    /// New daemon state, None when the daemon went away
+    State(Option<StateSnapshot>),
+    /// Periodic refresh of the window list
+    Tick,
+}
+
+/// A window row as shown in the table
+struct WindowRow {
+    id: u64,
+    app_id: String,
+    title: String,
+    workspace: String,
+}
+
+struct App {
+    socket_path: String,
+    windows: Vec<WindowRow>,
+    snapshot: Option<StateSnapshot>,
+    table: TableState,
+    message: String,
+}
+
+impl App {
+    /// Reload all windows from niri, keeping the selection on the same window
+    async fn refresh_windows(&mut self) {
+        let selected_id = self.selected().map(|w| w.id);
+
+        let workspaces: HashMap<u64, String> = system_integration::get_workspaces()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(|ws| {
+                let name = ws
+                    .name
+                    .or_else(|| ws.idx.map(|idx| idx.to_string()))
+                    .unwrap_or_else(|| ws.id.to_string());
+                let label = match ws.output {
+                    Some(output) => format!("{}:{}", output, name),
+                    None => name,
+                };
+                (ws.id, label)
+            })
+            .collect();
+
+        match system_integration::get_full_window_info().await {
+            Ok(windows) => {
+                let mut rows: Vec<WindowRow> = windows
+                    .into_iter()
+                    .map(|w| WindowRow {
+                        id: w.id,
+                        app_id: w.app_id.unwrap_or_default(),
+                        title: w.title.unwrap_or_default(),
+                        workspace: w
+                            .workspace_id
+                            .and_then(|id| workspaces.get(&id).cloned())
+                            .unwrap_or_else(|| "-".to_string()),
+                    })
+                    .collect();
+                rows.sort_by_key(|w| w.id);
+                self.windows = rows;
+            }
+            Err(e) => self.message = format!("Failed to list windows: {}", e),
+        }
+
+        let index = selected_id
+            .and_then(|id| self.windows.iter().position(|w| w.id == id))
+            .or(if self.windows.is_empty() {
+                None
+            } else {
+                Some(0)
+            });
+        self.table.select(index);
+    }
+
+    fn selected(&self) -> Option<&WindowRow> {
+        self.table.selected().and_then(|i| self.windows.get(i))
+    }
+
+    fn is_sticky(&self, id: u64) -> bool {
+        self.snapshot
+            .as_ref()
+            .is_some_and(|s| s.sticky.contains(&id))
+    }
+
+    fn is_staged(&self, id: u64) -> bool {
+        self.snapshot
+            .as_ref()
+            .is_some_and(|s| s.staged.contains(&id))
+    }
+
+    /// Send a command to the daemon and show its reply in the status line
+    async fn run_command(&mut self, cmd: String) {
+        self.message = match send_command(&self.socket_path, &cmd).await {
+            Ok(response) => response.trim().replace('\n', " "),
+            Err(e) => format!("Daemon not reachable: {}", e),
+        };
+    }
+
+    /// Handle a key press, returns false when the UI should exit
+    async fn handle_key(&mut self, key: event::KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Down | KeyCode::Char('j') => self.table.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => self.table.select_previous(),
+            KeyCode::Home | KeyCode::Char('g') => self.table.select_first(),
+            KeyCode::End | KeyCode::Char('G') => self.table.select_last(),
+            KeyCode::Char(' ') | KeyCode::Char('s') => {
+                if let Some(id) = self.selected().map(|w| w.id) {
+                    let cmd = if self.is_sticky(id) {
+                        format!("remove {id}\n")
+                    } else {
+                        format!("add {id}\n")
+                    };
+                    self.run_command(cmd).await;
+                }
+            }
+            KeyCode::Enter | KeyCode::Char('t') => {
+                if let Some(id) = self.selected().map(|w| w.id) {
+                    let cmd = if self.is_staged(id) {
+                        format!("unstage {id}\n")
+                    } else {
+                        format!("stage {id}\n")
+                    };
+                    self.run_command(cmd).await;
+                }
+            }
+            KeyCode::Char('a') => self.run_command("stage --all\n".to_string()).await,
+            KeyCode::Char('u') => self.run_command("unstage --all\n".to_string()).await,
+            KeyCode::Char('r') => self.refresh_windows().await,
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [table_area, status_area, help_area] = Layout::vertical([
+            Constraint::Min(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let focused = self.snapshot.as_ref().and_then(|s| s.focused);
+        let rows: Vec<Row> = self
+            .windows
+            .iter()
+            .map(|w| {
+                let (state, color) = if self.is_staged(w.id) {
+                    ("staged", Color::Yellow)
+                } else if self.is_sticky(w.id) {
+                    ("sticky", Color::Green)
+                } else {
+                    ("", Color::Reset)
+                };
+                let mut style = Style::default().fg(color);
+                if focused == Some(w.id) {
+                    style = style.add_modifier(Modifier::BOLD);
+                }
+                Row::new(vec![
+                    Cell::from(state),
+                    Cell::from(w.id.to_string()),
+                    Cell::from(w.app_id.as_str()),
+                    Cell::from(w.workspace.as_str()),
+                    Cell::from(w.title.as_str()),
+                ])
+                .style(style)
+            })
+            .collect();
+
+        let title = match &self.snapshot {
+            Some(s) => format!(
+                " nsticky: {} sticky, {} staged ",
+                s.sticky.len(),
+                s.staged.len()
+            ),
+            None => " nsticky: daemon not running ".to_string(),
+        };
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(6),
+                Constraint::Length(6),
+                Constraint::Length(24),
+                Constraint::Length(16),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(
+            Row::new(vec!["State", "ID", "App ID", "Workspace", "Title"])
+                .style(Style::default().add_modifier(Modifier::UNDERLINED)),
+        )
+        .block(Block::bordered().title(title))
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+        frame.render_stateful_widget(table, table_area, &mut self.table);
+
+        frame.render_widget(Line::from(self.message.as_str()), status_area);
+        frame.render_widget(
+            Line::from(
+                "space: sticky  enter: stage  a: stage all  u: unstage all  r: refresh  q: quit",
+            )
+            .style(Style::default().fg(Color::DarkGray)),
+            help_area,
+        );
+    }
+}
+
+/// Forward daemon state updates, reconnecting whenever the daemon restarts
+async fn watch_state(socket_path: String, tx: mpsc::UnboundedSender<AppEvent>) {
+    loop {
+        if let Ok(stream) = UnixStream::connect(&socket_path).await {
+            let (reader, mut writer) = stream.into_split();
+            let mut reader = BufReader::new(reader);
+            if writer.write_all(b"subscribe\n").await.is_ok() {
+                let mut line = String::new();
+                while let Ok(n) = reader.read_line(&mut line).await {
+                    if n == 0 {
+                        break;
+                    }
+                    if let Ok(snapshot) = serde_json::from_str::<StateSnapshot>(&line)
+                        && tx.send(AppEvent::State(Some(snapshot))).is_err()
+                    {
+                        return;
+                    }
+                    line.clear();
+                }
+            }
+        }
+        if tx.send(AppEvent::State(None)).is_err() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_secs(2)).await;
+    }
+}
+
+async fn run_app(terminal: &mut DefaultTerminal, socket_path: &str) -> Result<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    // Terminal input blocks, read it on a plain thread
+    let key_tx = tx.clone();
+    std::thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if let Event::Key(key) = event
+                && key.kind == KeyEventKind::Press
+                && key_tx.send(AppEvent::Key(key)).is_err()
+            {
+                return;
+            }
+        }
+    });
+
+    tokio::spawn(watch_state(socket_path.to_string(), tx.clone()));
+
+    // New windows don't change the daemon state, poll for them
+    let tick_tx = tx;
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(2));
+        loop {
+            interval.tick().await;
+            if tick_tx.send(AppEvent::Tick).is_err() {
+                return;
+            }
+        }
+    });
+
+    let mut app = App {
+        socket_path: socket_path.to_string(),
+        windows: Vec::new(),
+        snapshot: None,
+        table: TableState::default(),
+        message: String::new(),
+    };
+
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+        let Some(event) = rx.recv().await else {
+            return Ok(());
+        };
+        match event {
+            AppEvent::Key(key) => {
+                if !app.handle_key(key).await {
+                    return Ok(());
+                }
+            }
+            AppEvent::State(snapshot) => {
+                app.snapshot = snapshot;
+                app.refresh_windows().await;
+            }
+            AppEvent::Tick => app.refresh_windows().await,
+        }
+    }
+}
+
+/// Run the interactive terminal UI until the user quits
+pub async fn run_tui(socket_path: &str) -> Result<()> {
+    let mut terminal = ratatui::init();
+    let result = run_app(&mut terminal, socket_path).await;
+    ratatui::restore();
+    result
+}